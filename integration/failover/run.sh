@@ -0,0 +1,84 @@
+#!/bin/bash
+#
+# Stop the primary and check that pgdog promotes a replica with pg_promote()
+# and sends writes to it.
+#
+SCRIPT_DIR=$( cd -- "$( dirname -- "${BASH_SOURCE[0]}" )" &> /dev/null && pwd )
+set -e
+
+COMPOSE="docker-compose -f ${SCRIPT_DIR}/../load_balancer/docker-compose.yml"
+
+export PGUSER=postgres
+export PGHOST=127.0.0.1
+export PGDATABASE=postgres
+export PGPASSWORD=postgres
+
+${COMPOSE} up -d
+
+echo "Waiting for Postgres to be ready"
+
+for p in 45000 45001 45002; do
+    while ! PGPORT=${p} pg_isready; do
+        sleep 1
+    done
+done
+
+# Wait for the replicas to start streaming.
+for p in 45001 45002; do
+    while [[ "$(PGPORT=${p} psql -tAc 'SELECT pg_is_in_recovery()' 2> /dev/null)" != "t" ]]; do
+        sleep 1
+    done
+done
+
+pushd ${SCRIPT_DIR}/../../
+cargo build --release
+target/release/pgdog \
+    --config ${SCRIPT_DIR}/pgdog.toml \
+    --users ${SCRIPT_DIR}/users.toml &
+popd
+
+export PGPORT=6432
+while ! pg_isready; do
+    sleep 1
+done
+
+psql -c 'CREATE TABLE IF NOT EXISTS failover_test (id BIGINT)'
+psql -c 'INSERT INTO failover_test VALUES (1)'
+
+echo "Stopping the primary"
+${COMPOSE} stop primary
+
+echo "Waiting for failover"
+promoted=""
+for _ in $(seq 1 60); do
+    if psql -c 'INSERT INTO failover_test VALUES (2)' 2> /dev/null; then
+        promoted="yes"
+        break
+    fi
+    sleep 1
+done
+
+killall pgdog || true
+
+if [[ -z "${promoted}" ]]; then
+    echo "Replica wasn't promoted"
+    ${COMPOSE} down
+    exit 1
+fi
+
+# Exactly one replica was promoted.
+primaries=0
+for p in 45001 45002; do
+    if [[ "$(PGPORT=${p} psql -tAc 'SELECT pg_is_in_recovery()')" == "f" ]]; then
+        primaries=$((primaries + 1))
+    fi
+done
+
+${COMPOSE} down
+
+if [[ "${primaries}" != "1" ]]; then
+    echo "Expected one promoted replica, found ${primaries}"
+    exit 1
+fi
+
+echo "Failover complete"
@@ -0,0 +1,308 @@
+//! CUTOVER command.
+//!
+//! Sends clients of one database to another, e.g. a copy of it kept up to date
+//! with logical replication. New transactions on both databases are paused and in-flight
+//! ones are allowed to finish. Once the target's subscriptions to the source have caught up,
+//! the two databases swap places. If anything fails along the way,
+//! both are resumed and nothing changes.
+//!
+//! `CUTOVER ROLLBACK <database>` swaps them back, without checking replication.
+//! The configuration file should be updated before the next reload.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tokio::time::{sleep, Instant};
+use tracing::{info, warn};
+
+use crate::backend::databases::{self, databases};
+use crate::backend::pool::{Cluster, Lsn, Pool};
+use crate::backend::Server;
+use crate::config::config;
+
+use super::prelude::*;
+
+/// How often transactions and replication are checked.
+static INTERVAL: Duration = Duration::from_millis(100);
+
+/// Completed cutovers, source to target.
+static CUTOVERS: Lazy<Mutex<HashMap<String, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// CUTOVER command.
+pub enum Cutover {
+    /// Switch from source to target.
+    Switch { source: String, target: String },
+    /// Switch back to the source.
+    Rollback { source: String },
+}
+
+#[async_trait]
+impl Command for Cutover {
+    fn name(&self) -> String {
+        match self {
+            Self::Switch { .. } => "CUTOVER".into(),
+            Self::Rollback { .. } => "CUTOVER ROLLBACK".into(),
+        }
+    }
+
+    fn parse(sql: &str) -> Result<Self, Error> {
+        let sql = sql.replace(";", "");
+        let parts = sql.split_whitespace().collect::<Vec<_>>();
+        let keyword = |word: &str, keyword: &str| word.eq_ignore_ascii_case(keyword);
+
+        match parts[..] {
+            [cutover, rollback, source]
+                if keyword(cutover, "cutover") && keyword(rollback, "rollback") =>
+            {
+                Ok(Self::Rollback {
+                    source: source.to_owned(),
+                })
+            }
+            [cutover, source, to, target]
+                if keyword(cutover, "cutover") && keyword(to, "to") && source != target =>
+            {
+                Ok(Self::Switch {
+                    source: source.to_owned(),
+                    target: target.to_owned(),
+                })
+            }
+            _ => Err(Error::Syntax),
+        }
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let (source, target) = match self {
+            Self::Switch { source, target } => (source.clone(), target.clone()),
+            Self::Rollback { source } => {
+                let target = CUTOVERS
+                    .lock()
+                    .get(source)
+                    .cloned()
+                    .ok_or_else(|| Error::NoCutover(source.clone()))?;
+                (source.clone(), target)
+            }
+        };
+
+        let (from, to) = Self::clusters(&source, &target)?;
+        let deadline = Instant::now() + config().config.general.cutover_timeout();
+
+        info!(r#"cutover from "{}" to "{}" started"#, source, target);
+
+        // Pause new transactions on both databases, for all users,
+        // since their clients are sent to each other's servers.
+        let mut paused = Self::pools(&source);
+        paused.extend(Self::pools(&target));
+        paused.iter().for_each(|pool| pool.pause());
+
+        let ready = match self {
+            Self::Switch { .. } => match Self::drain(&paused, deadline).await {
+                Ok(()) => Self::caught_up(&from, &to, deadline).await,
+                Err(err) => Err(err),
+            },
+            Self::Rollback { .. } => Self::drain(&paused, deadline).await,
+        };
+
+        // Old pools are shut down by the swap.
+        if let Err(err) = ready.and_then(|_| {
+            databases::swap(&source, &target).map_err(|err| Error::Backend(Box::new(err)))
+        }) {
+            paused.iter().for_each(|pool| pool.resume());
+            warn!(
+                r#"cutover from "{}" to "{}" rolled back: {}"#,
+                source, target, err
+            );
+            return Err(err);
+        }
+
+        match self {
+            Self::Switch { .. } => {
+                CUTOVERS.lock().insert(source.clone(), target.clone());
+            }
+            Self::Rollback { .. } => {
+                CUTOVERS.lock().remove(&source);
+            }
+        }
+
+        warn!(
+            r#"cutover from "{}" to "{}" complete, update the configuration before reloading"#,
+            source, target
+        );
+
+        Ok(vec![])
+    }
+}
+
+impl Cutover {
+    /// Source and target clusters. Users share servers, so any user will do.
+    fn clusters(source: &str, target: &str) -> Result<(Cluster, Cluster), Error> {
+        let databases = databases();
+        let (user, from) = databases
+            .all()
+            .iter()
+            .find(|(user, _)| user.database == source)
+            .ok_or_else(|| Error::NoDatabase(source.to_owned()))?;
+        let to = databases
+            .cluster((user.user.as_str(), target))
+            .map_err(|_| Error::NoDatabase(target.to_owned()))?;
+
+        if from.shards().len() != to.shards().len() {
+            return Err(Error::CutoverShards);
+        }
+
+        Ok((from.clone(), to))
+    }
+
+    /// All pools of the database, for all users.
+    fn pools(database: &str) -> Vec<Pool> {
+        databases()
+            .all()
+            .iter()
+            .filter(|(user, _)| user.database == database)
+            .flat_map(|(_, cluster)| cluster.shards())
+            .flat_map(|shard| shard.pools())
+            .collect()
+    }
+
+    /// Wait for in-flight transactions to finish.
+    async fn drain(pools: &[Pool], deadline: Instant) -> Result<(), Error> {
+        while pools.iter().any(|pool| pool.state().checked_out > 0) {
+            if Instant::now() >= deadline {
+                return Err(Error::CutoverTimeout);
+            }
+            sleep(INTERVAL).await;
+        }
+
+        Ok(())
+    }
+
+    /// Wait for the target's subscriptions to the source to receive everything
+    /// written to the source, shard by shard. Subscriptions to the source
+    /// are the ones with a replication slot on it.
+    async fn caught_up(from: &Cluster, to: &Cluster, deadline: Instant) -> Result<(), Error> {
+        for (source, target) in from.shards().iter().zip(to.shards()) {
+            let (Some(source), Some(target)) = (source.primary_pool(), target.primary_pool())
+            else {
+                return Err(Error::Backend(Box::new(
+                    crate::backend::pool::Error::NoPrimary.into(),
+                )));
+            };
+
+            let mut source_server = Self::connect(&source).await?;
+            let mut target_server = Self::connect(&target).await?;
+
+            let slots = target_server
+                .fetch_all::<String>(
+                    "SELECT subslotname::text FROM pg_subscription
+                    WHERE subenabled AND subslotname IS NOT NULL
+                    AND subdbid = (SELECT oid FROM pg_database WHERE datname = current_database())",
+                )
+                .await
+                .map_err(|err| Error::Backend(Box::new(err)))?;
+
+            if slots.is_empty() {
+                return Err(Error::NoSubscription(target.addr().to_string()));
+            }
+
+            let lsn = Lsn::current(&mut source_server)
+                .await
+                .map_err(|err| Error::Backend(Box::new(err)))?
+                .ok_or_else(|| Error::Backend(Box::new(crate::backend::pool::Error::Lsn.into())))?;
+
+            let query = caught_up_query(&slots, lsn);
+
+            loop {
+                let caught_up = source_server
+                    .fetch_all::<String>(query.as_str())
+                    .await
+                    .map_err(|err| Error::Backend(Box::new(err)))?
+                    .first()
+                    .map(|caught_up| caught_up == "true")
+                    .unwrap_or(false);
+
+                if caught_up {
+                    info!("replication caught up to {} [{}]", lsn, target.addr());
+                    break;
+                }
+
+                if Instant::now() >= deadline {
+                    return Err(Error::CutoverTimeout);
+                }
+                sleep(INTERVAL).await;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Connect to the server directly, since the source pools are paused.
+    async fn connect(pool: &Pool) -> Result<Server, Error> {
+        pool.standalone()
+            .await
+            .map_err(|err| Error::Backend(Box::new(err)))
+    }
+}
+
+/// Check that the source's replication slots used by the subscriptions
+/// confirmed receiving everything up to the LSN. Slots of subscriptions
+/// to other databases aren't on the source, so they're ignored.
+fn caught_up_query(slots: &[String], lsn: Lsn) -> String {
+    let slots = slots
+        .iter()
+        .map(|slot| format!("'{}'", slot.replace('\'', "''")))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        "SELECT COALESCE(count(*) > 0 AND bool_and(confirmed_flush_lsn >= '{}'::pg_lsn), false)::text
+        FROM pg_replication_slots
+        WHERE slot_type = 'logical' AND database = current_database() AND slot_name IN ({})",
+        lsn,
+        slots
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::admin::parser::{ParseResult, Parser};
+
+    #[test]
+    fn test_parse() {
+        let Cutover::Switch { source, target } = Cutover::parse("cutover prod to prod_v2").unwrap()
+        else {
+            panic!("not a switch");
+        };
+        assert_eq!(source, "prod");
+        assert_eq!(target, "prod_v2");
+
+        let Cutover::Rollback { source } = Cutover::parse("cutover rollback prod").unwrap() else {
+            panic!("not a rollback");
+        };
+        assert_eq!(source, "prod");
+
+        assert!(Cutover::parse("cutover prod").is_err());
+        assert!(Cutover::parse("cutover prod to prod").is_err());
+        assert!(Cutover::parse("cutover prod into prod_v2").is_err());
+
+        // Database names keep their case.
+        let Ok(ParseResult::Cutover(Cutover::Switch { source, target })) =
+            Parser::parse("CUTOVER Prod TO Prod_v2;")
+        else {
+            panic!("not a switch");
+        };
+        assert_eq!(source, "Prod");
+        assert_eq!(target, "Prod_v2");
+    }
+
+    #[test]
+    fn test_caught_up_query() {
+        let slots = vec!["sub_one".to_string(), "it's".to_string()];
+        let query = caught_up_query(&slots, "0/16B3748".parse().unwrap());
+        assert!(query.contains("count(*) > 0"));
+        assert!(query.contains("database = current_database()"));
+        assert!(query.contains("'0/16B3748'::pg_lsn"));
+        assert!(query.contains("IN ('sub_one', 'it''s')"));
+    }
+}
@@ -27,4 +27,25 @@ pub enum Error {
 
     #[error("{0}")]
     Config(#[from] crate::config::error::Error),
+
+    #[error("{0}")]
+    Router(#[from] crate::frontend::router::Error),
+
+    #[error("no database server \"{0}\"")]
+    NoServer(String),
+
+    #[error("no database \"{0}\"")]
+    NoDatabase(String),
+
+    #[error("databases have a different number of shards")]
+    CutoverShards,
+
+    #[error("no subscriptions on \"{0}\"")]
+    NoSubscription(String),
+
+    #[error("cutover timeout")]
+    CutoverTimeout,
+
+    #[error("no cutover to roll back for \"{0}\"")]
+    NoCutover(String),
 }
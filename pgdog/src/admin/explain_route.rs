@@ -0,0 +1,145 @@
+//! EXPLAIN ROUTE command.
+//!
+//! Show the routing decision the query parser makes for a query,
+//! e.g. `EXPLAIN ROUTE prod alice SELECT * FROM users WHERE id = 1`.
+
+use crate::{backend::databases::databases, frontend::router::RouteInfo};
+
+use super::prelude::*;
+
+pub struct ExplainRoute {
+    database: String,
+    user: String,
+    query: String,
+}
+
+#[async_trait]
+impl Command for ExplainRoute {
+    fn name(&self) -> String {
+        "EXPLAIN".into()
+    }
+
+    fn parse(sql: &str) -> Result<Self, Error> {
+        let mut rest = sql.trim();
+        let mut words = vec![];
+
+        // EXPLAIN ROUTE <database> <user> <query>
+        for _ in 0..4 {
+            let (word, remainder) = rest.split_once(char::is_whitespace).ok_or(Error::Syntax)?;
+            words.push(word);
+            rest = remainder.trim_start();
+        }
+
+        if !words[0].eq_ignore_ascii_case("explain")
+            || !words[1].eq_ignore_ascii_case("route")
+            || rest.is_empty()
+        {
+            return Err(Error::Syntax);
+        }
+
+        Ok(Self {
+            database: words[2].to_owned(),
+            user: words[3].to_owned(),
+            query: rest.trim_end().trim_end_matches(';').to_owned(),
+        })
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let cluster = databases()
+            .cluster((self.user.as_str(), self.database.as_str()))
+            .map_err(|err| Error::Backend(Box::new(err)))?;
+        let info = RouteInfo::new(&cluster, &self.query, &[])?;
+        let route = &info.route;
+        let limit = route.limit();
+
+        let rows = [
+            ("command", info.command.name().to_string()),
+            ("shard", route.shard().to_string()),
+            (
+                "role",
+                if route.is_read() {
+                    "replica".into()
+                } else {
+                    "primary".into()
+                },
+            ),
+            (
+                "sharding_key",
+                info.key
+                    .as_ref()
+                    .map(|(key, _)| key.clone())
+                    .unwrap_or_default(),
+            ),
+            (
+                "source",
+                info.key
+                    .as_ref()
+                    .map(|(_, source)| source.to_string())
+                    .unwrap_or_default(),
+            ),
+            (
+                "order_by",
+                route
+                    .order_by()
+                    .iter()
+                    .map(|key| key.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+            (
+                "aggregates",
+                route
+                    .aggregate()
+                    .targets()
+                    .iter()
+                    .map(|target| target.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+            (
+                "limit",
+                limit
+                    .limit
+                    .map(|limit| limit.to_string())
+                    .unwrap_or_default(),
+            ),
+            (
+                "offset",
+                limit
+                    .offset
+                    .map(|offset| offset.to_string())
+                    .unwrap_or_default(),
+            ),
+            ("cached", info.cached.to_string()),
+        ];
+
+        let mut messages =
+            vec![RowDescription::new(&[Field::text("name"), Field::text("value")]).message()?];
+        for (name, value) in rows {
+            let mut dr = DataRow::new();
+            dr.add(name).add(value);
+            messages.push(dr.message()?);
+        }
+
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_explain_route() {
+        let cmd = ExplainRoute::parse(
+            "EXPLAIN ROUTE pgdog  pgdog SELECT * FROM sharded WHERE email = 'Test';",
+        )
+        .unwrap();
+        assert_eq!(cmd.database, "pgdog");
+        assert_eq!(cmd.user, "pgdog");
+        assert_eq!(cmd.query, "SELECT * FROM sharded WHERE email = 'Test'");
+
+        assert!(ExplainRoute::parse("EXPLAIN ROUTE pgdog pgdog").is_err());
+        assert!(ExplainRoute::parse("EXPLAIN pgdog pgdog SELECT 1").is_err());
+    }
+}
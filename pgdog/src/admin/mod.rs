@@ -6,7 +6,9 @@ use crate::net::messages::Message;
 
 pub mod backend;
 pub mod ban;
+pub mod cutover;
 pub mod error;
+pub mod explain_route;
 pub mod parser;
 pub mod pause;
 pub mod prelude;
@@ -15,6 +17,7 @@ pub mod reload;
 pub mod reset_query_cache;
 pub mod set;
 pub mod setup_schema;
+pub mod show_bans;
 pub mod show_clients;
 pub mod show_config;
 pub mod show_lists;
@@ -1,12 +1,13 @@
 //! Admin command parser.
 
 use super::{
-    ban::Ban, pause::Pause, prelude::Message, reconnect::Reconnect, reload::Reload,
-    reset_query_cache::ResetQueryCache, set::Set, setup_schema::SetupSchema,
-    show_clients::ShowClients, show_config::ShowConfig, show_lists::ShowLists,
-    show_peers::ShowPeers, show_pools::ShowPools, show_prepared_statements::ShowPreparedStatements,
-    show_query_cache::ShowQueryCache, show_servers::ShowServers, show_stats::ShowStats,
-    show_version::ShowVersion, shutdown::Shutdown, Command, Error,
+    ban::Ban, cutover::Cutover, explain_route::ExplainRoute, pause::Pause, prelude::Message,
+    reconnect::Reconnect, reload::Reload, reset_query_cache::ResetQueryCache, set::Set,
+    setup_schema::SetupSchema, show_bans::ShowBans, show_clients::ShowClients,
+    show_config::ShowConfig, show_lists::ShowLists, show_peers::ShowPeers, show_pools::ShowPools,
+    show_prepared_statements::ShowPreparedStatements, show_query_cache::ShowQueryCache,
+    show_servers::ShowServers, show_stats::ShowStats, show_version::ShowVersion,
+    shutdown::Shutdown, Command, Error,
 };
 
 use tracing::debug;
@@ -31,6 +32,9 @@ pub enum ParseResult {
     ShowPrepared(ShowPreparedStatements),
     Set(Set),
     Ban(Ban),
+    ShowBans(ShowBans),
+    ExplainRoute(ExplainRoute),
+    Cutover(Cutover),
 }
 
 impl ParseResult {
@@ -57,6 +61,9 @@ impl ParseResult {
             ShowPrepared(cmd) => cmd.execute().await,
             Set(set) => set.execute().await,
             Ban(ban) => ban.execute().await,
+            ShowBans(show_bans) => show_bans.execute().await,
+            ExplainRoute(explain_route) => explain_route.execute().await,
+            Cutover(cutover) => cutover.execute().await,
         }
     }
 
@@ -83,6 +90,9 @@ impl ParseResult {
             ShowPrepared(show) => show.name(),
             Set(set) => set.name(),
             Ban(ban) => ban.name(),
+            ShowBans(show_bans) => show_bans.name(),
+            ExplainRoute(explain_route) => explain_route.name(),
+            Cutover(cutover) => cutover.name(),
         }
     }
 }
@@ -93,6 +103,7 @@ pub struct Parser;
 impl Parser {
     /// Parse the query and return a command we can execute.
     pub fn parse(sql: &str) -> Result<ParseResult, Error> {
+        let original = sql;
         let sql = sql.trim().replace(";", "").to_lowercase();
         let mut iter = sql.split(" ");
 
@@ -102,6 +113,10 @@ impl Parser {
             "reconnect" => ParseResult::Reconnect(Reconnect::parse(&sql)?),
             "reload" => ParseResult::Reload(Reload::parse(&sql)?),
             "ban" | "unban" => ParseResult::Ban(Ban::parse(&sql)?),
+            // Database names and the query are case-sensitive,
+            // so they're parsed from the original text.
+            "cutover" => ParseResult::Cutover(Cutover::parse(original)?),
+            "explain" => ParseResult::ExplainRoute(ExplainRoute::parse(original)?),
             "show" => match iter.next().ok_or(Error::Syntax)?.trim() {
                 "clients" => ParseResult::ShowClients(ShowClients::parse(&sql)?),
                 "pools" => ParseResult::ShowPools(ShowPools::parse(&sql)?),
@@ -113,6 +128,7 @@ impl Parser {
                 "version" => ParseResult::ShowVersion(ShowVersion::parse(&sql)?),
                 "lists" => ParseResult::ShowLists(ShowLists::parse(&sql)?),
                 "prepared" => ParseResult::ShowPrepared(ShowPreparedStatements::parse(&sql)?),
+                "bans" => ParseResult::ShowBans(ShowBans::parse(&sql)?),
                 command => {
                     debug!("unknown admin show command: '{}'", command);
                     return Err(Error::Syntax);
@@ -52,6 +52,10 @@ impl Command for Set {
     }
 
     async fn execute(&self) -> Result<Vec<Message>, Error> {
+        if let Some(server) = self.name.strip_prefix("weight.") {
+            return self.weight(server);
+        }
+
         let _lock = databases::lock();
         let mut config = (*config()).clone();
         match self.name.as_str() {
@@ -90,6 +94,43 @@ impl Command for Set {
 }
 
 impl Set {
+    /// Change the load balancing weight of a database server, e.g.
+    /// `SET weight."10.0.0.1:5432" TO 0`, without recreating the pools.
+    fn weight(&self, server: &str) -> Result<Vec<Message>, Error> {
+        let weight: usize = self.value.parse()?;
+        let matches =
+            |host: &str, port: u16| host == server || format!("{}:{}", host, port) == server;
+
+        let _lock = databases::lock();
+        let mut config = (*config()).clone();
+        let mut found = false;
+
+        for database in config.config.databases.iter_mut() {
+            if matches(&database.host, database.port) {
+                database.weight = Some(weight);
+                found = true;
+            }
+        }
+
+        if !found {
+            return Err(Error::NoServer(server.to_owned()));
+        }
+
+        config::set(config)?;
+
+        for cluster in databases::databases().all().values() {
+            for shard in cluster.shards() {
+                for pool in shard.pools() {
+                    if matches(&pool.addr().host, pool.addr().port) {
+                        pool.set_weight(weight);
+                    }
+                }
+            }
+        }
+
+        Ok(vec![])
+    }
+
     fn from_json<T: DeserializeOwned>(value: &str) -> serde_json::Result<T> {
         serde_json::from_str::<T>(&format!(r#""{}""#, value))
     }
@@ -105,5 +146,10 @@ mod test {
         let cmd = Set::parse(cmd).unwrap();
         assert_eq!(cmd.name, "query_timeout");
         assert_eq!(cmd.value, "5000");
+
+        let cmd = r#"SET weight."127.0.0.1:5432" TO 0"#;
+        let cmd = Set::parse(cmd).unwrap();
+        assert_eq!(cmd.name, "weight.127.0.0.1:5432");
+        assert_eq!(cmd.value, "0");
     }
 }
@@ -0,0 +1,69 @@
+//! SHOW BANS command.
+//!
+//! Recent bans for each pool, with the reason
+//! and the current state of the pool's circuit breaker.
+
+use crate::{
+    backend::databases::databases,
+    net::messages::{DataRow, Field, Protocol, RowDescription},
+    util::format_time,
+};
+
+use super::prelude::*;
+
+/// SHOW BANS command.
+pub struct ShowBans;
+
+#[async_trait]
+impl Command for ShowBans {
+    fn name(&self) -> String {
+        "SHOW BANS".into()
+    }
+
+    fn parse(_sql: &str) -> Result<Self, Error> {
+        Ok(Self)
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let mut messages = vec![RowDescription::new(&[
+            Field::text("database"),
+            Field::text("user"),
+            Field::text("addr"),
+            Field::numeric("port"),
+            Field::numeric("shard"),
+            Field::text("role"),
+            Field::text("circuit"),
+            Field::text("reason"),
+            Field::text("banned_at"),
+            Field::numeric("ban_timeout"),
+            Field::text("unbanned_at"),
+        ])
+        .message()?];
+
+        for (user, cluster) in databases().all() {
+            for (shard_num, shard) in cluster.shards().iter().enumerate() {
+                for (role, pool) in shard.pools_with_roles() {
+                    let state = pool.state();
+
+                    for ban in state.bans.iter().rev() {
+                        let mut dr = DataRow::new();
+                        dr.add(user.database.as_str())
+                            .add(user.user.as_str())
+                            .add(pool.addr().host.as_str())
+                            .add(pool.addr().port as i64)
+                            .add(shard_num)
+                            .add(role.to_string())
+                            .add(state.circuit.to_string())
+                            .add(ban.reason.to_string())
+                            .add(format_time(ban.created_at.into()))
+                            .add(ban.ban_timeout.as_millis() as u64)
+                            .add(ban.unbanned_at.map(|time| format_time(time.into())));
+                        messages.push(dr.message()?);
+                    }
+                }
+            }
+        }
+
+        Ok(messages)
+    }
+}
@@ -4,6 +4,7 @@ use chrono::DateTime;
 
 use super::prelude::*;
 use crate::frontend::comms::comms;
+use crate::frontend::router::parser::role::ROLE_PARAMETER;
 use crate::net::messages::*;
 use crate::util::format_time;
 
@@ -41,6 +42,7 @@ impl Command for ShowClients {
             Field::text("application_name"),
             Field::numeric("memory_used"),
             Field::bool("locked"),
+            Field::text("role"),
         ]);
 
         let mut rows = vec![];
@@ -77,7 +79,8 @@ impl Command for ShowClients {
                 .add(client.stats.errors)
                 .add(client.paramters.get_default("application_name", ""))
                 .add(client.stats.memory_used)
-                .add(client.stats.locked);
+                .add(client.stats.locked)
+                .add(client.paramters.get_default(ROLE_PARAMETER, "auto"));
             rows.push(row.message()?);
         }
 
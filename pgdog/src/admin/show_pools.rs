@@ -40,6 +40,7 @@ impl Command for ShowPools {
             Field::numeric("re_synced"),
             Field::numeric("out_of_sync"),
             Field::bool("online"),
+            Field::numeric("replica_lag"),
         ]);
         let mut messages = vec![rd.message()?];
         for (user, cluster) in databases().all() {
@@ -68,7 +69,8 @@ impl Command for ShowPools {
                         .add(state.errors)
                         .add(state.re_synced)
                         .add(state.out_of_sync)
-                        .add(state.online);
+                        .add(state.online)
+                        .add(state.replica_lag.map(|lag| lag.as_millis() as i64));
                     messages.push(row.message()?);
                 }
             }
@@ -4,7 +4,7 @@ use std::time::SystemTime;
 use tokio::time::Instant;
 
 use crate::{
-    backend::stats::stats,
+    backend::{databases::databases, stats::stats},
     net::messages::{DataRow, Field, Protocol, RowDescription},
     util::format_time,
 };
@@ -44,9 +44,17 @@ impl Command for ShowServers {
             Field::numeric("bytes_sent"),
             Field::numeric("age"),
             Field::text("application_name"),
+            Field::text("role"),
         ])
         .message()?];
 
+        let roles = databases()
+            .all()
+            .values()
+            .flat_map(|cluster| cluster.shards())
+            .flat_map(|shard| shard.pools_with_roles())
+            .map(|(role, pool)| (pool.addr().clone(), role))
+            .collect::<Vec<_>>();
         let stats = stats();
         let now = Instant::now();
         let now_time = SystemTime::now();
@@ -73,7 +81,14 @@ impl Command for ShowServers {
                 .add(server.stats.total.bytes_received)
                 .add(server.stats.total.bytes_sent)
                 .add(age.as_secs() as i64)
-                .add(server.application_name.as_str());
+                .add(server.application_name.as_str())
+                .add(
+                    roles
+                        .iter()
+                        .find(|(addr, _)| addr == &server.addr)
+                        .map(|(_, role)| role.to_string())
+                        .unwrap_or_default(),
+                );
             messages.push(dr.message()?);
         }
 
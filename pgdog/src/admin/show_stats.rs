@@ -40,6 +40,7 @@ impl Command for ShowStats {
                         // Field::numeric(&format!("{}_client_parse_count", prefix)),
                         Field::numeric(&format!("{}_server_parse_count", prefix)),
                         Field::numeric(&format!("{}_bind_count", prefix)),
+                        Field::numeric(&format!("{}_retries", prefix)),
                     ]
                 })
                 .collect::<Vec<Field>>(),
@@ -79,7 +80,8 @@ impl Command for ShowStats {
                             .add(stat.wait_time.as_millis() as u64)
                             // .add(0_i64)
                             .add(stat.parse_count)
-                            .add(stat.bind_count);
+                            .add(stat.bind_count)
+                            .add(stat.retries);
                     }
 
                     messages.push(dr.message()?);
@@ -57,6 +57,42 @@ pub fn replace_databases(new_databases: Databases, reload: bool) {
     reload_notify::done();
 }
 
+/// Swap two databases, sending clients of one to the other's servers.
+///
+/// Pools of both databases are re-created, so clients waiting
+/// on paused pools reconnect to the new ones.
+pub fn swap(source: &str, target: &str) -> Result<(), Error> {
+    let _lock = lock();
+    let databases = databases();
+    let mut swapped = databases.duplicate();
+
+    for user in databases.databases.keys() {
+        if user.database != source {
+            continue;
+        }
+
+        let other = User {
+            user: user.user.clone(),
+            database: target.to_owned(),
+        };
+
+        match (
+            swapped.databases.remove(user),
+            swapped.databases.remove(&other),
+        ) {
+            (Some(from), Some(to)) => {
+                swapped.databases.insert(user.clone(), to);
+                swapped.databases.insert(other, from);
+            }
+            _ => return Err(Error::NoDatabase(other)),
+        }
+    }
+
+    replace_databases(swapped, true);
+
+    Ok(())
+}
+
 /// Re-create all connections.
 pub fn reconnect() {
     replace_databases(databases().duplicate(), false);
@@ -319,6 +355,7 @@ pub(crate) fn new_pool(
     let mut mirrors_of = BTreeSet::new();
 
     if let Some(shards) = shards {
+        let strict = shards.iter().flatten().any(|database| database.strict);
         let mut shard_configs = vec![];
         for user_databases in shards {
             let primary = user_databases
@@ -333,7 +370,7 @@ pub(crate) fn new_pool(
                 });
             let replicas = user_databases
                 .iter()
-                .filter(|d| d.role == Role::Replica)
+                .filter(|d| matches!(d.role, Role::Replica | Role::Auto))
                 .map(|replica| {
                     mirrors_of.insert(replica.mirror_of.clone());
                     PoolConfig {
@@ -378,6 +415,7 @@ pub(crate) fn new_pool(
             sharded_tables,
             mirror_of,
             config.multi_tenant(),
+            strict,
         );
 
         Some((
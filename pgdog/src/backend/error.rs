@@ -89,6 +89,9 @@ pub enum Error {
 
     #[error("router error: {0}")]
     Router(String),
+
+    #[error("multi-shard query returned more than {0} rows")]
+    MultiShardRowLimit(usize),
 }
 
 impl Error {
@@ -103,4 +106,10 @@ impl Error {
             _ => false,
         }
     }
+
+    /// The query was stopped by pgdog, e.g. because it returned too many rows.
+    /// The client is told and can keep using its connection.
+    pub fn aborted(&self) -> bool {
+        matches!(self, Error::MultiShardRowLimit(_))
+    }
 }
@@ -0,0 +1,237 @@
+//! Pool circuit breaker.
+//!
+//! The circuit is closed while the pool is healthy. When the pool is banned,
+//! the circuit opens for a timeout that starts at `ban_initial_timeout`. When the ban
+//! expires, the circuit is half-open and the pool serves a few requests, the probes.
+//! The first successful request or healthcheck closes it. If the pool fails again instead,
+//! the circuit opens for twice as long as before, up to `ban_timeout`.
+
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+use super::Error;
+
+/// How many bans to remember per pool.
+const HISTORY: usize = 25;
+
+/// How many checkouts are allowed while the circuit is half-open.
+const PROBES: usize = 3;
+
+/// Circuit breaker state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CircuitState {
+    /// Pool is serving traffic.
+    #[default]
+    Closed,
+    /// Pool is banned.
+    Open,
+    /// Pool is serving a few requests after a ban, and will be banned for longer if it fails.
+    HalfOpen,
+}
+
+impl std::fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Closed => write!(f, "closed"),
+            Self::Open => write!(f, "open"),
+            Self::HalfOpen => write!(f, "half_open"),
+        }
+    }
+}
+
+/// Past or current ban.
+#[derive(Debug, Clone, Copy)]
+pub struct BanRecord {
+    /// When the pool was banned.
+    pub created_at: SystemTime,
+    /// Why it was banned.
+    pub reason: Error,
+    /// How long the ban was supposed to last.
+    pub ban_timeout: Duration,
+    /// When the ban was lifted, if it was.
+    pub unbanned_at: Option<SystemTime>,
+}
+
+/// Pool circuit breaker.
+#[derive(Debug, Clone, Default)]
+pub struct CircuitBreaker {
+    state: CircuitState,
+    /// How many times the circuit opened since it was last closed.
+    opened: u32,
+    /// Checkouts since the circuit became half-open.
+    probes: usize,
+    /// Recent bans, oldest first.
+    history: VecDeque<BanRecord>,
+}
+
+impl CircuitBreaker {
+    /// Open the circuit, returning how long the ban should last.
+    pub(super) fn open(&mut self, reason: Error, initial: Duration, max: Duration) -> Duration {
+        let was = self.state;
+        if was != CircuitState::Open {
+            self.opened = self.opened.saturating_add(1);
+        }
+        self.state = CircuitState::Open;
+
+        let ban_timeout = initial
+            .saturating_mul(2_u32.saturating_pow(self.opened - 1))
+            .min(max);
+
+        // Banned again while banned, e.g. by another check-in.
+        if let (CircuitState::Open, Some(last)) = (was, self.history.back_mut()) {
+            last.reason = reason;
+            last.ban_timeout = ban_timeout;
+            return ban_timeout;
+        }
+
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(BanRecord {
+            created_at: SystemTime::now(),
+            reason,
+            ban_timeout,
+            unbanned_at: None,
+        });
+
+        ban_timeout
+    }
+
+    /// Ban expired, let some traffic through.
+    pub(super) fn half_open(&mut self) {
+        if self.state == CircuitState::Open {
+            self.state = CircuitState::HalfOpen;
+            self.probes = 0;
+            self.unbanned();
+        }
+    }
+
+    /// A client wants a connection. Returns `false` if the circuit
+    /// is half-open and all the probes have been used.
+    pub(super) fn checkout(&mut self) -> bool {
+        if self.state != CircuitState::HalfOpen {
+            return true;
+        }
+
+        if self.probes < PROBES {
+            self.probes += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Pool served a request successfully.
+    pub(super) fn success(&mut self) {
+        if self.state == CircuitState::HalfOpen {
+            self.close();
+        }
+    }
+
+    /// Close the circuit and reset the backoff.
+    pub(super) fn close(&mut self) {
+        if self.state == CircuitState::Open {
+            self.unbanned();
+        }
+        self.state = CircuitState::Closed;
+        self.opened = 0;
+    }
+
+    /// Current state.
+    pub fn state(&self) -> CircuitState {
+        self.state
+    }
+
+    /// Recent bans, oldest first.
+    pub fn history(&self) -> &VecDeque<BanRecord> {
+        &self.history
+    }
+
+    fn unbanned(&mut self) {
+        if let Some(last) = self.history.back_mut() {
+            last.unbanned_at.get_or_insert_with(SystemTime::now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let initial = Duration::from_secs(5);
+        let max = Duration::from_secs(300);
+        let mut breaker = CircuitBreaker::default();
+
+        assert_eq!(breaker.open(Error::ServerError, initial, max), initial);
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        // Banned again while banned, same timeout.
+        assert_eq!(breaker.open(Error::ServerError, initial, max), initial);
+
+        // Failed while half-open, back off.
+        breaker.half_open();
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert_eq!(
+            breaker.open(Error::ServerError, initial, max),
+            Duration::from_secs(10)
+        );
+        breaker.half_open();
+        assert_eq!(
+            breaker.open(Error::CheckoutTimeout, initial, max),
+            Duration::from_secs(20)
+        );
+
+        for _ in 0..10 {
+            breaker.half_open();
+            breaker.open(Error::ServerError, initial, max);
+        }
+        assert_eq!(breaker.open(Error::ServerError, initial, max), max);
+
+        // Succeeded while half-open, start over.
+        breaker.half_open();
+        breaker.success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(breaker.open(Error::ServerError, initial, max), initial);
+
+        assert_eq!(breaker.history().len(), 14);
+        assert!(breaker
+            .history()
+            .iter()
+            .rev()
+            .skip(1)
+            .all(|ban| ban.unbanned_at.is_some()));
+        assert!(breaker.history().back().unwrap().unbanned_at.is_none());
+    }
+
+    #[test]
+    fn test_probes() {
+        let initial = Duration::from_secs(5);
+        let max = Duration::from_secs(300);
+        let mut breaker = CircuitBreaker::default();
+
+        // Closed, no limit.
+        for _ in 0..PROBES * 2 {
+            assert!(breaker.checkout());
+        }
+
+        breaker.open(Error::ServerError, initial, max);
+        breaker.half_open();
+        for _ in 0..PROBES {
+            assert!(breaker.checkout());
+        }
+        assert!(!breaker.checkout());
+
+        // Probe failed, opened again and probes are reset when half-open.
+        breaker.open(Error::ServerError, initial, max);
+        breaker.half_open();
+        assert!(breaker.checkout());
+
+        // Probe succeeded.
+        breaker.success();
+        for _ in 0..PROBES * 2 {
+            assert!(breaker.checkout());
+        }
+    }
+}
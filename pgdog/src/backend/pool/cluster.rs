@@ -45,6 +45,9 @@ pub struct Cluster {
     multi_tenant: Option<MultiTenant>,
     rw_strategy: ReadWriteStrategy,
     rw_split: ReadWriteSplit,
+    strict: bool,
+    read_your_writes: bool,
+    read_retries: usize,
 }
 
 /// Sharding configuration from the cluster.
@@ -81,6 +84,9 @@ pub struct ClusterConfig<'a> {
     pub multi_tenant: &'a Option<MultiTenant>,
     pub rw_strategy: ReadWriteStrategy,
     pub rw_split: ReadWriteSplit,
+    pub strict: bool,
+    pub read_your_writes: bool,
+    pub read_retries: usize,
 }
 
 impl<'a> ClusterConfig<'a> {
@@ -91,7 +97,15 @@ impl<'a> ClusterConfig<'a> {
         sharded_tables: ShardedTables,
         mirror_of: Option<&'a str>,
         multi_tenant: &'a Option<MultiTenant>,
+        strict: bool,
     ) -> Self {
+        let read_retries = shards
+            .iter()
+            .flat_map(|shard| shard.primary.iter().chain(shard.replicas.iter()))
+            .map(|pool| pool.config.read_retries)
+            .max()
+            .unwrap_or(general.read_retries);
+
         Self {
             name: &user.database,
             password: user.password(),
@@ -105,6 +119,9 @@ impl<'a> ClusterConfig<'a> {
             multi_tenant,
             rw_strategy: general.read_write_strategy,
             rw_split: general.read_write_split,
+            strict,
+            read_your_writes: general.read_your_writes,
+            read_retries,
         }
     }
 }
@@ -125,6 +142,9 @@ impl Cluster {
             multi_tenant,
             rw_strategy,
             rw_split,
+            strict,
+            read_your_writes,
+            read_retries,
         } = config;
 
         Self {
@@ -143,6 +163,9 @@ impl Cluster {
             multi_tenant: multi_tenant.clone(),
             rw_strategy,
             rw_split,
+            strict,
+            read_your_writes,
+            read_retries,
         }
     }
 
@@ -193,6 +216,9 @@ impl Cluster {
             multi_tenant: self.multi_tenant.clone(),
             rw_strategy: self.rw_strategy,
             rw_split: self.rw_split,
+            strict: self.strict,
+            read_your_writes: self.read_your_writes,
+            read_retries: self.read_retries,
         }
     }
 
@@ -248,7 +274,7 @@ impl Cluster {
     /// This cluster is read only (no primaries).
     pub fn read_only(&self) -> bool {
         for shard in &self.shards {
-            if shard.primary.is_some() {
+            if shard.has_primary() {
                 return false;
             }
         }
@@ -273,6 +299,26 @@ impl Cluster {
         !(self.shards().len() == 1 && (self.read_only() || self.write_only()))
     }
 
+    /// Reject multi-shard queries that can't be merged correctly.
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Reads are sent only to replicas that replayed the client's writes.
+    pub fn read_your_writes(&self) -> bool {
+        self.read_your_writes
+    }
+
+    /// How many times a read can be retried on another server.
+    pub fn read_retries(&self) -> usize {
+        self.read_retries
+    }
+
+    /// Reads marked critical can fall back to the primary.
+    pub fn critical_reads(&self) -> bool {
+        self.shards.iter().any(|shard| shard.critical_reads())
+    }
+
     /// Multi-tenant config.
     pub fn multi_tenant(&self) -> &Option<MultiTenant> {
         &self.multi_tenant
@@ -298,8 +344,9 @@ impl Cluster {
         let mut server = self.primary(0, &Request::default()).await?;
         let schema = Schema::load(&mut server).await?;
         info!(
-            "loaded {} tables from schema [{}]",
+            "loaded {} tables and {} functions from schema [{}]",
             schema.tables().len(),
+            schema.functions_len(),
             server.addr()
         );
         *self.schema.write() = schema;
@@ -307,7 +354,8 @@ impl Cluster {
     }
 
     fn load_schema(&self) -> bool {
-        self.multi_tenant.is_some()
+        // Function volatility is used to split reads from writes.
+        self.multi_tenant.is_some() || (!self.read_only() && !self.write_only())
     }
 
     /// Get currently loaded schema.
@@ -347,7 +395,7 @@ impl Cluster {
 #[cfg(test)]
 mod test {
     use crate::{
-        backend::{Pool, Replicas, Shard, ShardedTables},
+        backend::{Pool, Replicas, Schema, Shard, ShardedTables},
         config::{DataType, ReadWriteStrategy, ShardedTable},
     };
 
@@ -366,6 +414,7 @@ mod test {
                         data_type: DataType::Bigint,
                         centroids_path: None,
                         centroid_probes: 1,
+                        json_path: None,
                     }],
                     vec!["sharded_omni".into()],
                     false,
@@ -395,5 +444,13 @@ mod test {
         pub fn set_read_write_strategy(&mut self, rw_strategy: ReadWriteStrategy) {
             self.rw_strategy = rw_strategy;
         }
+
+        pub fn set_strict(&mut self, strict: bool) {
+            self.strict = strict;
+        }
+
+        pub fn set_schema(&self, schema: Schema) {
+            *self.schema.write() = schema;
+        }
     }
 }
@@ -4,7 +4,7 @@ use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
-use crate::config::{Database, General, PoolerMode, User};
+use crate::config::{Database, General, PoolerMode, ReplicaFallback, Role, User};
 
 /// Pool configuration.
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
@@ -39,6 +39,8 @@ pub struct Config {
     pub query_timeout: Duration, // ms
     /// Max ban duration.
     pub ban_timeout: Duration, // ms
+    /// Duration of the first ban, doubled for every consecutive one.
+    pub ban_initial_timeout: Duration, // ms
     /// Rollback timeout for dirty connections.
     pub rollback_timeout: Duration,
     /// Statement timeout
@@ -49,6 +51,21 @@ pub struct Config {
     pub pooler_mode: PoolerMode,
     /// Read only mode.
     pub read_only: bool,
+    /// Database role.
+    pub role: Role,
+    /// Maximum replication lag before the replica is banned.
+    pub max_replica_lag: Option<Duration>,
+    /// How long to wait for the replica to replay the client's writes.
+    pub read_your_writes_timeout: Duration,
+    /// Load balancing weight.
+    pub weight: usize,
+    /// How many times a read can be retried on another server.
+    pub read_retries: usize,
+    /// What to do with reads when all replicas are down.
+    pub replica_fallback: ReplicaFallback,
+    /// Percentage of the primary pool reserved for writes
+    /// when reads fall back to the primary.
+    pub primary_reserve: usize,
 }
 
 impl Config {
@@ -138,6 +155,7 @@ impl Config {
             idle_healthcheck_interval: Duration::from_millis(general.idle_healthcheck_interval),
             idle_healthcheck_delay: Duration::from_millis(general.idle_healthcheck_delay),
             ban_timeout: Duration::from_millis(general.ban_timeout),
+            ban_initial_timeout: Duration::from_millis(general.ban_initial_timeout),
             rollback_timeout: Duration::from_millis(general.rollback_timeout),
             statement_timeout: if let Some(statement_timeout) = database.statement_timeout {
                 Some(statement_timeout)
@@ -159,6 +177,15 @@ impl Config {
             read_only: database
                 .read_only
                 .unwrap_or(user.read_only.unwrap_or_default()),
+            role: database.role,
+            max_replica_lag: general.max_replica_lag.map(Duration::from_millis),
+            read_your_writes_timeout: Duration::from_millis(general.read_your_writes_timeout),
+            weight: database.weight.unwrap_or(1),
+            read_retries: database.read_retries.unwrap_or(general.read_retries),
+            replica_fallback: database
+                .replica_fallback
+                .unwrap_or(general.replica_fallback),
+            primary_reserve: database.primary_reserve.unwrap_or(general.primary_reserve),
             ..Default::default()
         }
     }
@@ -182,11 +209,19 @@ impl Default for Config {
             write_timeout: Duration::MAX,
             query_timeout: Duration::MAX,
             ban_timeout: Duration::from_secs(300),
+            ban_initial_timeout: Duration::from_secs(5),
             rollback_timeout: Duration::from_secs(5),
             statement_timeout: None,
             replication_mode: false,
             pooler_mode: PoolerMode::default(),
             read_only: false,
+            role: Role::default(),
+            max_replica_lag: None,
+            read_your_writes_timeout: Duration::from_secs(1),
+            weight: 1,
+            read_retries: 0,
+            replica_fallback: ReplicaFallback::default(),
+            primary_reserve: 0,
         }
     }
 }
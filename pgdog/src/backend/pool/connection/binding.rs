@@ -46,6 +46,23 @@ impl Binding {
         self.disconnect();
     }
 
+    /// Count the retry against the server(s) and release them.
+    pub(super) fn retry(&mut self) {
+        match self {
+            Binding::Server(Some(ref mut guard)) | Binding::Replication(Some(ref mut guard), _) => {
+                guard.stats_mut().retry()
+            }
+            Binding::MultiShard(ref mut guards, _) => {
+                for guard in guards {
+                    guard.stats_mut().retry();
+                }
+            }
+            _ => (),
+        }
+
+        self.disconnect();
+    }
+
     pub(super) fn connected(&self) -> bool {
         match self {
             Binding::Server(server) => server.is_some(),
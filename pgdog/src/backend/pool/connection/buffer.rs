@@ -42,79 +42,9 @@ impl Buffer {
 
     /// Sort the buffer.
     pub(super) fn sort(&mut self, columns: &[OrderBy], decoder: &Decoder) {
-        // Calculate column indices once, since
-        // fetching indices by name is O(number of columns).
-        let mut cols = vec![];
-        for column in columns {
-            match column {
-                OrderBy::Asc(_) => cols.push(column.clone()),
-                OrderBy::AscColumn(name) => {
-                    if let Some(index) = decoder.rd().field_index(name) {
-                        cols.push(OrderBy::Asc(index + 1));
-                    }
-                }
-                OrderBy::Desc(_) => cols.push(column.clone()),
-                OrderBy::DescColumn(name) => {
-                    if let Some(index) = decoder.rd().field_index(name) {
-                        cols.push(OrderBy::Desc(index + 1));
-                    }
-                }
-                OrderBy::AscVectorL2(_, _) => cols.push(column.clone()),
-                OrderBy::AscVectorL2Column(name, vector) => {
-                    if let Some(index) = decoder.rd().field_index(name) {
-                        cols.push(OrderBy::AscVectorL2(index + 1, vector.clone()));
-                    }
-                }
-            };
-        }
-
-        // Sort rows.
-        let order_by = move |a: &DataRow, b: &DataRow| -> Ordering {
-            for col in cols.iter() {
-                let index = col.index();
-                let asc = col.asc();
-                let index = if let Some(index) = index {
-                    index
-                } else {
-                    continue;
-                };
-                let left = a.get_column(index, decoder);
-                let right = b.get_column(index, decoder);
-
-                let ordering = match (left, right) {
-                    (Ok(Some(left)), Ok(Some(right))) => {
-                        // Handle the special vector case.
-                        if let OrderBy::AscVectorL2(_, vector) = col {
-                            let left: Option<Vector> = left.value.try_into().ok();
-                            let right: Option<Vector> = right.value.try_into().ok();
-
-                            if let (Some(left), Some(right)) = (left, right) {
-                                let left = left.distance_l2(vector);
-                                let right = right.distance_l2(vector);
-
-                                left.partial_cmp(&right)
-                            } else {
-                                Some(Ordering::Equal)
-                            }
-                        } else if asc {
-                            left.value.partial_cmp(&right.value)
-                        } else {
-                            right.value.partial_cmp(&left.value)
-                        }
-                    }
-
-                    _ => Some(Ordering::Equal),
-                };
-
-                if ordering != Some(Ordering::Equal) {
-                    return ordering.unwrap_or(Ordering::Equal);
-                }
-            }
-
-            Ordering::Equal
-        };
-
-        self.buffer.make_contiguous().sort_by(order_by);
+        self.buffer
+            .make_contiguous()
+            .sort_by(comparator(columns, decoder));
     }
 
     /// Execute aggregate functions.
@@ -166,6 +96,83 @@ impl Buffer {
     }
 }
 
+/// Compare rows using the `ORDER BY` clause.
+pub(super) fn comparator<'a>(
+    columns: &[OrderBy],
+    decoder: &'a Decoder,
+) -> impl Fn(&DataRow, &DataRow) -> Ordering + 'a {
+    // Calculate column indices once, since
+    // fetching indices by name is O(number of columns).
+    let mut cols = vec![];
+    for column in columns {
+        match column {
+            OrderBy::Asc(_) => cols.push(column.clone()),
+            OrderBy::AscColumn(name) => {
+                if let Some(index) = decoder.rd().field_index(name) {
+                    cols.push(OrderBy::Asc(index + 1));
+                }
+            }
+            OrderBy::Desc(_) => cols.push(column.clone()),
+            OrderBy::DescColumn(name) => {
+                if let Some(index) = decoder.rd().field_index(name) {
+                    cols.push(OrderBy::Desc(index + 1));
+                }
+            }
+            OrderBy::AscVectorL2(_, _) => cols.push(column.clone()),
+            OrderBy::AscVectorL2Column(name, vector) => {
+                if let Some(index) = decoder.rd().field_index(name) {
+                    cols.push(OrderBy::AscVectorL2(index + 1, vector.clone()));
+                }
+            }
+        };
+    }
+
+    move |a: &DataRow, b: &DataRow| -> Ordering {
+        for col in cols.iter() {
+            let index = col.index();
+            let asc = col.asc();
+            let index = if let Some(index) = index {
+                index
+            } else {
+                continue;
+            };
+            let left = a.get_column(index, decoder);
+            let right = b.get_column(index, decoder);
+
+            let ordering = match (left, right) {
+                (Ok(Some(left)), Ok(Some(right))) => {
+                    // Handle the special vector case.
+                    if let OrderBy::AscVectorL2(_, vector) = col {
+                        let left: Option<Vector> = left.value.try_into().ok();
+                        let right: Option<Vector> = right.value.try_into().ok();
+
+                        if let (Some(left), Some(right)) = (left, right) {
+                            let left = left.distance_l2(vector);
+                            let right = right.distance_l2(vector);
+
+                            left.partial_cmp(&right)
+                        } else {
+                            Some(Ordering::Equal)
+                        }
+                    } else if asc {
+                        left.value.partial_cmp(&right.value)
+                    } else {
+                        right.value.partial_cmp(&left.value)
+                    }
+                }
+
+                _ => Some(Ordering::Equal),
+            };
+
+            if ordering != Some(Ordering::Equal) {
+                return ordering.unwrap_or(Ordering::Equal);
+            }
+        }
+
+        Ordering::Equal
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -0,0 +1,179 @@
+//! Cursors and portals open on multiple shards.
+//!
+//! Each shard returns its rows in the cursor's order. To return `n` rows
+//! to the client, we make sure every shard that still has rows buffered at least `n` of them,
+//! and merge the shard queues, keeping whatever is left over for the next fetch.
+
+use std::collections::VecDeque;
+
+use crate::{
+    frontend::router::parser::OrderBy,
+    net::{
+        messages::{DataRow, RowDescription},
+        Decoder,
+    },
+};
+
+use super::buffer::comparator;
+
+/// Rows received from one shard but not returned to the client yet.
+#[derive(Debug, Default)]
+struct ShardRows {
+    rows: VecDeque<DataRow>,
+    done: bool,
+}
+
+/// Cursor open on multiple shards.
+#[derive(Debug, Default)]
+pub(super) struct Cursor {
+    shards: Vec<ShardRows>,
+    order_by: Vec<OrderBy>,
+    decoder: Decoder,
+    rd: RowDescription,
+}
+
+impl Cursor {
+    /// New cursor open on the given number of shards.
+    pub(super) fn new(shards: usize, order_by: &[OrderBy], decoder: Decoder) -> Self {
+        Self {
+            shards: (0..shards).map(|_| ShardRows::default()).collect(),
+            order_by: order_by.to_vec(),
+            decoder,
+            rd: RowDescription::default(),
+        }
+    }
+
+    /// Set the row description returned by the shards.
+    pub(super) fn row_description(&mut self, rd: RowDescription) {
+        self.decoder.row_description(&rd);
+        self.rd = rd;
+    }
+
+    /// Row description returned by the shards.
+    pub(super) fn rd(&self) -> &RowDescription {
+        &self.rd
+    }
+
+    /// Number of rows to request from the shard before `count` rows can be returned in order.
+    /// `None` means the shard has enough rows buffered or has no more rows.
+    ///
+    /// If `count` is `None`, all remaining rows are needed.
+    pub(super) fn missing(&self, shard: usize, count: Option<usize>) -> Option<Option<usize>> {
+        let shard = &self.shards[shard];
+        if shard.done {
+            return None;
+        }
+
+        match count {
+            Some(count) if shard.rows.len() >= count => None,
+            Some(count) => Some(Some(count - shard.rows.len())),
+            None => Some(None),
+        }
+    }
+
+    /// Buffer a row received from a shard.
+    pub(super) fn add(&mut self, shard: usize, row: DataRow) {
+        self.shards[shard].rows.push_back(row);
+    }
+
+    /// Number of rows buffered from all shards.
+    pub(super) fn buffered(&self) -> usize {
+        self.shards.iter().map(|shard| shard.rows.len()).sum()
+    }
+
+    /// The shard has no more rows.
+    pub(super) fn done(&mut self, shard: usize) {
+        self.shards[shard].done = true;
+    }
+
+    /// All rows have been returned to the client.
+    pub(super) fn exhausted(&self) -> bool {
+        self.shards
+            .iter()
+            .all(|shard| shard.done && shard.rows.is_empty())
+    }
+
+    /// Take up to `count` rows, merging them from all shards in order.
+    ///
+    /// The caller is responsible for requesting rows returned by [`Cursor::missing`] first.
+    pub(super) fn take(&mut self, count: Option<usize>) -> Vec<DataRow> {
+        let order_by = comparator(&self.order_by, &self.decoder);
+        let mut rows = vec![];
+
+        while count.map(|count| rows.len() < count).unwrap_or(true) {
+            let mut next: Option<usize> = None;
+
+            for (index, shard) in self.shards.iter().enumerate() {
+                let Some(row) = shard.rows.front() else {
+                    continue;
+                };
+
+                next = match next {
+                    Some(current) => {
+                        let current_row = &self.shards[current].rows[0];
+                        if order_by(row, current_row).is_lt() {
+                            Some(index)
+                        } else {
+                            Some(current)
+                        }
+                    }
+                    None => Some(index),
+                };
+            }
+
+            match next.and_then(|index| self.shards[index].rows.pop_front()) {
+                Some(row) => rows.push(row),
+                None => break,
+            }
+        }
+
+        rows
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::net::{Field, Format};
+
+    use super::*;
+
+    #[test]
+    fn test_cursor_merge() {
+        let rd = RowDescription::new(&[Field::bigint("id")]);
+        let mut cursor = Cursor::new(2, &[OrderBy::Asc(1)], Decoder::from(&rd));
+        cursor.row_description(rd);
+
+        let row = |id: i64| {
+            let mut dr = DataRow::new();
+            dr.add(id);
+            dr
+        };
+
+        // Shard 0 has even ids, shard 1 has odd ids.
+        assert_eq!(cursor.missing(0, Some(3)), Some(Some(3)));
+        for id in [0, 2, 4] {
+            cursor.add(0, row(id));
+        }
+        for id in [1, 3] {
+            cursor.add(1, row(id));
+        }
+        cursor.done(1);
+
+        let ids = |rows: Vec<DataRow>| {
+            rows.iter()
+                .map(|row| row.get::<i64>(0, Format::Text).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids(cursor.take(Some(3))), vec![0, 1, 2]);
+        // Shard 0 needs to return more rows, shard 1 is done.
+        assert_eq!(cursor.missing(0, Some(3)), Some(Some(2)));
+        assert_eq!(cursor.missing(1, Some(3)), None);
+
+        cursor.add(0, row(6));
+        cursor.done(0);
+        assert_eq!(ids(cursor.take(Some(3))), vec![3, 4, 6]);
+        assert!(cursor.exhausted());
+        assert!(cursor.take(None).is_empty());
+    }
+}
@@ -0,0 +1,148 @@
+//! Execute `EXPLAIN` on multiple shards.
+//!
+//! Shards are queried concurrently. Plans returned by each shard are placed under
+//! a header naming the shard, below a node describing how pgdog merges the results.
+
+use std::time::{Duration, Instant};
+
+use futures::future::try_join_all;
+
+use crate::{
+    frontend::router::parser::Explain,
+    net::messages::{DataRow, Field, FromBytes, Protocol, RowDescription, ToBytes},
+};
+
+use super::{join::Rows, Cluster, Error, Request};
+
+/// Plan returned by one shard.
+struct ShardPlan {
+    shard: usize,
+    addr: String,
+    lines: Vec<String>,
+}
+
+/// Execute `EXPLAIN` on all shards the explained query would be sent to.
+pub struct ExplainExecutor<'a> {
+    cluster: &'a Cluster,
+    plan: &'a Explain,
+}
+
+impl<'a> ExplainExecutor<'a> {
+    /// Create new executor for the `EXPLAIN` plan.
+    pub fn new(cluster: &'a Cluster, plan: &'a Explain) -> Self {
+        Self { cluster, plan }
+    }
+
+    /// Execute `EXPLAIN` and return the combined plan.
+    pub async fn execute(&self, request: &Request) -> Result<Rows, Error> {
+        let start = Instant::now();
+        let route = self.plan.route();
+
+        let plans = (0..self.cluster.shards().len())
+            .filter(|shard| route.shard().includes(*shard))
+            .map(|shard| async move {
+                let mut server = if route.is_read() {
+                    self.cluster.replica(shard, request).await?
+                } else {
+                    self.cluster.primary(shard, request).await?
+                };
+
+                let mut lines = vec![];
+                for message in server.execute_checked(self.plan.query()).await? {
+                    if message.code() == 'D' {
+                        let row = DataRow::from_bytes(message.to_bytes()?)?;
+                        lines.push(row.get_text(0).unwrap_or_default());
+                    }
+                }
+
+                Ok::<_, Error>(ShardPlan {
+                    shard,
+                    addr: server.addr().to_string(),
+                    lines,
+                })
+            });
+        let plans = try_join_all(plans).await?;
+
+        let elapsed = if self.plan.analyze() {
+            Some(start.elapsed())
+        } else {
+            None
+        };
+
+        let mut rows = Rows {
+            rd: RowDescription::new(&[Field::text("QUERY PLAN")]),
+            rows: vec![],
+        };
+
+        for line in combine(self.plan, &plans, elapsed) {
+            let mut row = DataRow::new();
+            row.add(line);
+            rows.rows.push(row);
+        }
+
+        Ok(rows)
+    }
+}
+
+/// Combine shard plans into one plan.
+fn combine(plan: &Explain, shards: &[ShardPlan], elapsed: Option<Duration>) -> Vec<String> {
+    let mut lines = plan.merge();
+
+    for shard in shards {
+        lines.push(format!("  ->  Shard {}  ({})", shard.shard, shard.addr));
+        for line in &shard.lines {
+            lines.push(format!("        {}", line));
+        }
+    }
+
+    if let Some(elapsed) = elapsed {
+        lines.push(format!(
+            "PgDog Execution Time: {:.3} ms",
+            elapsed.as_secs_f64() * 1000.0
+        ));
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use pg_query::{parse, NodeEnum};
+
+    use crate::frontend::router::parser::Route;
+
+    use super::*;
+
+    #[test]
+    fn test_combine() {
+        let query = "EXPLAIN ANALYZE SELECT * FROM sharded";
+        let ast = parse(query).unwrap();
+        let stmt = ast.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+        let Some(NodeEnum::ExplainStmt(ref stmt)) = stmt.node else {
+            panic!("not an explain");
+        };
+        let plan = Explain::new(stmt, query, Route::read(None)).unwrap();
+
+        let shards = (0..2)
+            .map(|shard| ShardPlan {
+                shard,
+                addr: format!("127.0.0.1:5432, shard_{}", shard),
+                lines: vec![
+                    "Seq Scan on sharded  (cost=0.00..1.00 rows=1 width=8)".into(),
+                    "Planning Time: 0.010 ms".into(),
+                ],
+            })
+            .collect::<Vec<_>>();
+
+        let lines = combine(&plan, &shards, Some(Duration::from_millis(5)));
+        assert_eq!(lines[0], "PgDog Merge  (shard=all, role=replica)");
+        assert_eq!(lines[1], "  ->  Shard 0  (127.0.0.1:5432, shard_0)");
+        assert_eq!(
+            lines[2],
+            "        Seq Scan on sharded  (cost=0.00..1.00 rows=1 width=8)"
+        );
+        assert_eq!(lines[4], "  ->  Shard 1  (127.0.0.1:5432, shard_1)");
+        assert_eq!(lines.last().unwrap(), "PgDog Execution Time: 5.000 ms");
+        assert_eq!(lines.len(), 8);
+    }
+}
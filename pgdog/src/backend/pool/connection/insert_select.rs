@@ -0,0 +1,148 @@
+//! Execute `INSERT ... SELECT` across shards.
+//!
+//! The `SELECT` part of the statement runs on the source shard(s) through a cursor,
+//! and rows are fetched in batches. Each batch is re-sharded using the target table's
+//! sharding key and inserted into each shard in its own transaction before
+//! the next batch is fetched, so only one batch is held in memory at a time.
+//!
+//! One connection per shard is used for both reading and inserting rows. They are all
+//! checked out before anything is executed, in shard order, so concurrent statements
+//! don't wait on each other for connections they're holding.
+//!
+//! Transactions are committed one shard at a time, so a failure during commit
+//! can leave some shards with the new rows.
+
+use tracing::debug;
+
+use crate::{
+    backend::pool::Guard,
+    frontend::router::parser::{insert_select::INSERT_SELECT_BATCH, InsertSelect},
+    net::messages::{CommandComplete, DataRow, FromBytes, Protocol, ToBytes},
+};
+
+use super::{Cluster, Error, Request};
+
+/// Cursor used to read rows from the source shard.
+static CURSOR: &str = "__pgdog_insert_select";
+
+/// `INSERT ... SELECT` executor.
+pub struct InsertSelectExecutor<'a> {
+    cluster: &'a Cluster,
+    plan: &'a InsertSelect,
+}
+
+impl<'a> InsertSelectExecutor<'a> {
+    /// Create new executor for the plan.
+    pub fn new(cluster: &'a Cluster, plan: &'a InsertSelect) -> Self {
+        Self { cluster, plan }
+    }
+
+    /// Execute the plan, returning the number of inserted rows.
+    pub async fn execute(&self, request: &Request) -> Result<usize, Error> {
+        let mut servers = vec![];
+        for shard in self.cluster.shards() {
+            let mut server = shard.primary(request).await?;
+            server.execute_checked("BEGIN").await?;
+            servers.push(server);
+        }
+
+        let mut inserted = 0;
+
+        for source in 0..servers.len() {
+            if !self.plan.source().includes(source) {
+                continue;
+            }
+
+            servers[source]
+                .execute_checked(format!(
+                    "DECLARE {} NO SCROLL CURSOR FOR {}",
+                    CURSOR,
+                    self.plan.select()
+                ))
+                .await?;
+
+            loop {
+                let (fetched, rows) = self.fetch(&mut servers[source]).await?;
+
+                for (target, rows) in rows.into_iter().enumerate() {
+                    if rows.is_empty() {
+                        continue;
+                    }
+
+                    inserted += self.insert(&mut servers[target], &rows).await?;
+                    debug!("inserted {} rows into shard {}", rows.len(), target);
+                }
+
+                if fetched < INSERT_SELECT_BATCH {
+                    break;
+                }
+            }
+
+            servers[source]
+                .execute_checked(format!("CLOSE {}", CURSOR))
+                .await?;
+        }
+
+        // Uncommitted transactions are rolled back
+        // when the connections are returned to the pool.
+        for server in servers.iter_mut() {
+            server.execute_checked("COMMIT").await?;
+        }
+
+        Ok(inserted)
+    }
+
+    /// Fetch the next batch of rows from the cursor and group them by target shard.
+    async fn fetch(&self, source: &mut Guard) -> Result<(usize, Vec<Vec<DataRow>>), Error> {
+        let mut rows = vec![vec![]; self.cluster.shards().len()];
+        let mut fetched = 0;
+
+        let messages = source
+            .execute_checked(format!("FETCH {} FROM {}", INSERT_SELECT_BATCH, CURSOR))
+            .await?;
+
+        for message in messages {
+            if message.code() != 'D' {
+                continue;
+            }
+            fetched += 1;
+
+            let row = DataRow::from_bytes(message.to_bytes()?)?;
+            if row.len() != self.plan.columns() {
+                return Err(Error::Router(format!(
+                    "INSERT has {} target columns but SELECT returned {}",
+                    self.plan.columns(),
+                    row.len()
+                )));
+            }
+
+            let target = self
+                .plan
+                .shard(&row)
+                .map_err(|err| Error::Router(err.to_string()))?;
+            rows.get_mut(target)
+                .ok_or(Error::Router(format!("shard {} doesn't exist", target)))?
+                .push(row);
+        }
+
+        Ok((fetched, rows))
+    }
+
+    /// Insert a batch of re-sharded rows into their shard.
+    async fn insert(&self, server: &mut Guard, rows: &[DataRow]) -> Result<usize, Error> {
+        let query = self
+            .plan
+            .insert(rows)
+            .map_err(|err| Error::Router(err.to_string()))?;
+        let mut inserted = 0;
+
+        for message in server.execute_checked(query).await? {
+            if message.code() == 'C' {
+                let cc = CommandComplete::from_bytes(message.to_bytes()?)?;
+                inserted += cc.rows()?.unwrap_or(0);
+            }
+        }
+
+        Ok(inserted)
+    }
+}
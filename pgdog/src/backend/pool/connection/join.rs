@@ -0,0 +1,498 @@
+//! Execute two-table equi-joins across shards.
+//!
+//! Rows are read from the outer side of the join through a cursor, one batch at a time.
+//! For each batch, the executor will:
+//!
+//! 1. [`Lookup`] rows on the inner side using the batch's join keys
+//! 2. [`HashJoin`] the batch with the inner rows and project the target list
+//!
+//! The joined rows are sent to the client before the next batch is fetched.
+//! Join keys are compared by value, so `1` and `1.0` are equal.
+//!
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    backend::pool::Guard,
+    frontend::router::{
+        parser::{Join, JoinSide, JoinTarget, Shard},
+        sharding::ContextBuilder,
+    },
+    net::messages::{
+        DataRow, Field, FromBytes, Message, Parameter, Protocol, RowDescription, ToBytes,
+    },
+};
+
+use super::{Cluster, Error, Request};
+
+/// Maximum number of rows fetched from the outer side at a time.
+pub const JOIN_BATCH: usize = 1000;
+
+/// Cursor used to read the outer side.
+static CURSOR: &str = "__pgdog_join";
+
+/// `int8`, `int2`, `int4`, `oid`.
+const INTEGERS: [i32; 4] = [20, 21, 23, 26];
+/// `numeric`
+const NUMERIC: i32 = 1700;
+/// `float4`, `float8`.
+const FLOATS: [i32; 2] = [700, 701];
+
+/// Rows returned by a plan node.
+#[derive(Debug, Default, Clone)]
+pub struct Rows {
+    /// Row description.
+    pub rd: RowDescription,
+    /// Data rows.
+    pub rows: Vec<DataRow>,
+}
+
+impl Rows {
+    fn extend(&mut self, messages: Vec<Message>) -> Result<(), Error> {
+        for message in messages {
+            match message.code() {
+                'T' => self.rd = RowDescription::from_bytes(message.to_bytes()?)?,
+                'D' => self.rows.push(DataRow::from_bytes(message.to_bytes()?)?),
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn key(&self, column: &str) -> Result<usize, Error> {
+        self.rd.field_index(column).ok_or(Error::Router(format!(
+            "join column \"{}\" not found",
+            column
+        )))
+    }
+
+    /// Join key of each row, compared by value. Rows with `NULL` keys don't match anything.
+    fn keys(&self, column: &str) -> Result<Vec<Option<String>>, Error> {
+        let index = self.key(column)?;
+        let type_oid = self.rd.fields[index].type_oid;
+
+        Ok(self
+            .rows
+            .iter()
+            .map(|row| {
+                if row.is_null(index) {
+                    None
+                } else {
+                    row.get_text(index).and_then(|value| key(&value, type_oid))
+                }
+            })
+            .collect())
+    }
+}
+
+/// Get a connection to a shard for one side of the join.
+async fn server(
+    cluster: &Cluster,
+    side: &JoinSide,
+    shard: usize,
+    request: &Request,
+) -> Result<Guard, Error> {
+    if side.route.is_read() {
+        cluster.replica(shard, request).await
+    } else {
+        cluster.primary(shard, request).await
+    }
+}
+
+/// Shards that have rows for this side of the join.
+fn shards(cluster: &Cluster, side: &JoinSide) -> Vec<usize> {
+    let shards = (0..cluster.shards().len()).filter(|shard| side.route.shard().includes(*shard));
+
+    // All shards have the same rows.
+    if side.unsharded {
+        shards.take(1).collect()
+    } else {
+        shards.collect()
+    }
+}
+
+/// Fetch rows matching join keys from one side of the join.
+struct Lookup<'a> {
+    cluster: &'a Cluster,
+    side: &'a JoinSide,
+}
+
+impl Lookup<'_> {
+    /// Get the row description without fetching any rows.
+    async fn describe(&self, request: &Request) -> Result<RowDescription, Error> {
+        let shard = shards(self.cluster, self.side)
+            .first()
+            .copied()
+            .ok_or(Error::Router("join has no shards".into()))?;
+        let mut rows = Rows::default();
+        let mut server = server(self.cluster, self.side, shard, request).await?;
+        rows.extend(
+            server
+                .execute_params(&self.side.lookup, &[array(&[])])
+                .await?,
+        )?;
+
+        Ok(rows.rd)
+    }
+
+    async fn execute(
+        &self,
+        request: &Request,
+        keys: &[&str],
+        rd: &RowDescription,
+    ) -> Result<Rows, Error> {
+        let index = rd
+            .field_index(&self.side.column)
+            .ok_or(Error::Router(format!(
+                "join column \"{}\" not found",
+                self.side.column
+            )))?;
+
+        // Integer columns can't be equal to fractions, and Postgres won't parse them.
+        let keys = if INTEGERS.contains(&rd.fields[index].type_oid) {
+            keys.iter()
+                .copied()
+                .filter(|key| !key.contains('.'))
+                .collect::<Vec<_>>()
+        } else {
+            keys.to_vec()
+        };
+
+        let shards = self.cluster.shards().len();
+        let mut by_shard: Vec<Vec<&str>> = vec![vec![]; shards];
+
+        // Send keys only to shards that can have matching rows.
+        if let Some(ref table) = self.side.sharded {
+            for key in keys.iter().copied() {
+                let ctx = ContextBuilder::new(table)
+                    .data(key)
+                    .shards(shards)
+                    .build()
+                    .map_err(|err| Error::Router(err.to_string()))?;
+                match ctx.apply().map_err(|err| Error::Router(err.to_string()))? {
+                    Shard::Direct(shard) if shard < shards => by_shard[shard].push(key),
+                    _ => by_shard.iter_mut().for_each(|s| s.push(key)),
+                }
+            }
+        } else {
+            by_shard
+                .iter_mut()
+                .for_each(|s| s.extend(keys.iter().copied()));
+        }
+
+        let mut rows = Rows {
+            rd: rd.clone(),
+            rows: vec![],
+        };
+
+        for shard in self::shards(self.cluster, self.side) {
+            let keys = &by_shard[shard];
+            if keys.is_empty() {
+                continue;
+            }
+
+            let mut server = server(self.cluster, self.side, shard, request).await?;
+            let messages = server
+                .execute_params(&self.side.lookup, &[array(keys)])
+                .await?;
+            rows.extend(messages)?;
+        }
+
+        Ok(rows)
+    }
+}
+
+/// Join rows from both sides on the join key.
+struct HashJoin<'a> {
+    plan: &'a Join,
+}
+
+impl HashJoin<'_> {
+    /// Output columns, as (side, column index, field).
+    fn columns(&self, rds: [&RowDescription; 2]) -> Result<Vec<(usize, usize, Field)>, Error> {
+        let mut columns = vec![];
+        let all = |side: usize, columns: &mut Vec<(usize, usize, Field)>| {
+            for (index, field) in rds[side].fields.iter().enumerate() {
+                columns.push((side, index, field.clone()));
+            }
+        };
+
+        for target in self.plan.targets() {
+            match target {
+                JoinTarget::Star => {
+                    all(0, &mut columns);
+                    all(1, &mut columns);
+                }
+                JoinTarget::Table(side) => all(*side, &mut columns),
+                JoinTarget::Column { side, column, name } => {
+                    let index = rds[*side]
+                        .field_index(column)
+                        .ok_or(Error::Router(format!("column \"{}\" not found", column)))?;
+                    let mut field = rds[*side].fields[index].clone();
+                    field.name = name.clone();
+                    columns.push((*side, index, field));
+                }
+            }
+        }
+
+        Ok(columns)
+    }
+
+    fn execute(&self, outer: &Rows, inner: &Rows) -> Result<Rows, Error> {
+        let outer_position = self.plan.outer_position();
+        let rds = if outer_position == 0 {
+            [&outer.rd, &inner.rd]
+        } else {
+            [&inner.rd, &outer.rd]
+        };
+        let columns = self.columns(rds)?;
+
+        let outer_keys = outer.keys(&self.plan.outer().column)?;
+        let inner_keys = inner.keys(&self.plan.inner().column)?;
+
+        let mut hash: HashMap<&str, Vec<&DataRow>> = HashMap::new();
+        for (row, key) in outer.rows.iter().zip(&outer_keys) {
+            if let Some(key) = key {
+                hash.entry(key.as_str()).or_default().push(row);
+            }
+        }
+
+        let mut rows = vec![];
+        for (inner_row, key) in inner.rows.iter().zip(&inner_keys) {
+            let Some(matches) = key.as_deref().and_then(|key| hash.get(key)) else {
+                continue;
+            };
+
+            for outer_row in matches {
+                let sides = if outer_position == 0 {
+                    [*outer_row, inner_row]
+                } else {
+                    [inner_row, *outer_row]
+                };
+                let mut row = DataRow::new();
+                for (side, index, _) in &columns {
+                    let data = sides[*side]
+                        .data(*index)
+                        .ok_or(Error::Router("join row is missing columns".into()))?;
+                    row.add(data.clone());
+                }
+                rows.push(row);
+            }
+        }
+
+        Ok(Rows {
+            rd: RowDescription::new(
+                &columns
+                    .into_iter()
+                    .map(|(_, _, field)| field)
+                    .collect::<Vec<_>>(),
+            ),
+            rows,
+        })
+    }
+}
+
+/// Join executor.
+pub struct JoinExecutor<'a> {
+    cluster: &'a Cluster,
+    plan: &'a Join,
+    request: Request,
+    /// Outer shards that haven't been read yet.
+    shards: VecDeque<usize>,
+    /// Connection with the cursor open on the outer shard being read.
+    outer: Option<Guard>,
+    /// Inner side's row description.
+    inner_rd: Option<RowDescription>,
+    /// Row description of the joined rows was returned.
+    described: bool,
+}
+
+impl<'a> JoinExecutor<'a> {
+    /// Create new executor for the join plan.
+    pub fn new(cluster: &'a Cluster, plan: &'a Join, request: &Request) -> Self {
+        Self {
+            cluster,
+            plan,
+            request: *request,
+            shards: shards(cluster, plan.outer()).into(),
+            outer: None,
+            inner_rd: None,
+            described: false,
+        }
+    }
+
+    /// Fetch the next batch of joined rows.
+    ///
+    /// The first batch is returned even if it's empty, so the row description is known.
+    /// Returns `None` when all rows have been joined.
+    pub async fn next(&mut self) -> Result<Option<Rows>, Error> {
+        loop {
+            if self.outer.is_none() {
+                let Some(shard) = self.shards.pop_front() else {
+                    return Ok(None);
+                };
+                let side = self.plan.outer();
+                let mut server = server(self.cluster, side, shard, &self.request).await?;
+                server.execute_checked("BEGIN").await?;
+                server
+                    .execute_checked(format!(
+                        "DECLARE {} NO SCROLL CURSOR FOR {}",
+                        CURSOR, side.query
+                    ))
+                    .await?;
+                self.outer = Some(server);
+            }
+
+            let mut outer = Rows::default();
+            if let Some(ref mut server) = self.outer {
+                outer.extend(
+                    server
+                        .execute_checked(format!("FETCH {} FROM {}", JOIN_BATCH, CURSOR))
+                        .await?,
+                )?;
+
+                // Closes the cursor.
+                if outer.rows.len() < JOIN_BATCH {
+                    server.execute_checked("COMMIT").await?;
+                    self.outer = None;
+                }
+            }
+
+            if outer.rows.is_empty() && self.described {
+                continue;
+            }
+
+            let lookup = Lookup {
+                cluster: self.cluster,
+                side: self.plan.inner(),
+            };
+
+            let inner_rd = match self.inner_rd {
+                Some(ref rd) => rd,
+                None => self.inner_rd.insert(lookup.describe(&self.request).await?),
+            };
+
+            let mut keys = outer
+                .keys(&self.plan.outer().column)?
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+
+            let inner = lookup
+                .execute(
+                    &self.request,
+                    &keys.iter().map(|key| key.as_str()).collect::<Vec<_>>(),
+                    inner_rd,
+                )
+                .await?;
+
+            self.described = true;
+
+            return HashJoin { plan: self.plan }
+                .execute(&outer, &inner)
+                .map(Some);
+        }
+    }
+}
+
+/// Join key compared by value, e.g. `1`, `1.0` and `01` are all `1`.
+///
+/// Returns `None` if the value can't be equal to anything, e.g. `NaN`.
+fn key(value: &str, type_oid: i32) -> Option<String> {
+    if INTEGERS.contains(&type_oid) || type_oid == NUMERIC {
+        decimal(value)
+    } else if FLOATS.contains(&type_oid) {
+        // Floats are never formatted with an exponent.
+        value
+            .parse::<f64>()
+            .ok()
+            .filter(|float| float.is_finite())
+            .and_then(|float| decimal(&float.to_string()))
+    } else {
+        Some(value.to_owned())
+    }
+}
+
+/// Canonical decimal, e.g. `-01.50` is `-1.5`.
+fn decimal(value: &str) -> Option<String> {
+    let value = value.trim();
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+
+    if (integer.is_empty() && fraction.is_empty())
+        || !integer.bytes().all(|b| b.is_ascii_digit())
+        || !fraction.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+
+    let integer = match integer.trim_start_matches('0') {
+        "" => "0",
+        integer => integer,
+    };
+    let fraction = fraction.trim_end_matches('0');
+
+    let mut key = String::new();
+    if negative && (integer != "0" || !fraction.is_empty()) {
+        key.push('-');
+    }
+    key.push_str(integer);
+    if !fraction.is_empty() {
+        key.push('.');
+        key.push_str(fraction);
+    }
+
+    Some(key)
+}
+
+/// Encode join keys as a text array parameter.
+fn array(keys: &[&str]) -> Parameter {
+    let elements = keys
+        .iter()
+        .map(|key| format!("\"{}\"", key.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect::<Vec<_>>()
+        .join(",");
+    let data = format!("{{{}}}", elements).into_bytes();
+
+    Parameter {
+        len: data.len() as i32,
+        data,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_array() {
+        let param = array(&["1", "two \"2\"", "back\\slash"]);
+        assert_eq!(
+            std::str::from_utf8(&param.data).unwrap(),
+            r#"{"1","two \"2\"","back\\slash"}"#
+        );
+        assert_eq!(param.len, param.data.len() as i32);
+
+        let param = array(&[]);
+        assert_eq!(param.data, b"{}");
+    }
+
+    #[test]
+    fn test_key() {
+        assert_eq!(key("1", 23).unwrap(), "1");
+        assert_eq!(key("1.0", NUMERIC).unwrap(), "1");
+        assert_eq!(key("-01.50", NUMERIC).unwrap(), "-1.5");
+        assert_eq!(key("-0.00", NUMERIC).unwrap(), "0");
+        assert_eq!(key("1e3", 701).unwrap(), "1000");
+        assert_eq!(key("0.25", 700).unwrap(), "0.25");
+        assert!(key("NaN", NUMERIC).is_none());
+        assert!(key("Infinity", 701).is_none());
+        assert_eq!(key("1.0", 25).unwrap(), "1.0");
+    }
+}
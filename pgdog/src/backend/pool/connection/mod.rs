@@ -2,7 +2,7 @@
 
 use mirror::{MirrorHandler, MirrorRequest};
 use tokio::time::sleep;
-use tracing::debug;
+use tracing::{debug, error};
 
 use crate::{
     admin::backend::Backend,
@@ -10,31 +10,46 @@ use crate::{
         databases::databases,
         reload_notify,
         replication::{Buffer, ReplicationConfig},
+        ProtocolMessage,
     },
-    config::PoolerMode,
+    config::{config, PoolerMode},
     frontend::{
-        router::{parser::Shard, CopyRow, Route},
+        router::{
+            parser::{Explain, Fetch, InsertSelect, Join, Shard},
+            CopyRow, Route,
+        },
         Router,
     },
-    net::{Bind, Message, ParameterStatus, Parameters},
+    net::{
+        Bind, CommandComplete, DataRow, Decoder, Execute, Flush, FromBytes, Message,
+        ParameterStatus, Parameters, PortalSuspended, Protocol, RowDescription, Sync, ToBytes,
+    },
     state::State,
 };
 
 use super::{
     super::{pool::Guard, Error},
-    Address, Cluster, Request, ShardingSchema,
+    Address, Cluster, Lsn, Request, ShardingSchema,
 };
 
-use std::{mem::replace, time::Duration};
+use std::{collections::HashMap, mem::replace, time::Duration};
 
 pub mod aggregate;
 pub mod binding;
 pub mod buffer;
+pub mod cursor;
+pub mod explain;
+pub mod insert_select;
+pub mod join;
 pub mod mirror;
 pub mod multi_shard;
 
 use aggregate::Aggregates;
 use binding::Binding;
+use cursor::Cursor;
+use explain::ExplainExecutor;
+use insert_select::InsertSelectExecutor;
+use join::{JoinExecutor, Rows};
 use mirror::Mirror;
 use multi_shard::MultiShard;
 
@@ -47,6 +62,14 @@ pub struct Connection {
     cluster: Option<Cluster>,
     mirrors: Vec<MirrorHandler>,
     locked: bool,
+    /// Cursors open on multiple shards.
+    cursors: HashMap<String, Cursor>,
+    /// Portals executed with a row limit on multiple shards.
+    portals: HashMap<String, Cursor>,
+    /// Primary WAL position after the client's last write, per shard.
+    lsns: HashMap<usize, Lsn>,
+    /// Shards the client is writing to.
+    writes: Option<Shard>,
 }
 
 impl Connection {
@@ -63,6 +86,10 @@ impl Connection {
             database: database.to_owned(),
             mirrors: vec![],
             locked: false,
+            cursors: HashMap::new(),
+            portals: HashMap::new(),
+            lsns: HashMap::new(),
+            writes: None,
         };
 
         if !admin {
@@ -131,11 +158,93 @@ impl Connection {
         }
     }
 
+    /// Request a replica that replayed the client's writes to the shard.
+    fn read_request(&self, request: &Request, shard: usize) -> Request {
+        match self.cluster() {
+            Ok(cluster) if cluster.read_your_writes() => {
+                request.lsn(self.lsns.get(&shard).copied())
+            }
+            _ => *request,
+        }
+    }
+
+    /// The client wrote to the primaries and the LSN needs to be recorded
+    /// before the connection is released.
+    pub(crate) fn lsn_pending(&self) -> bool {
+        self.writes.is_some()
+            && self
+                .cluster()
+                .map(|cluster| cluster.read_your_writes())
+                .unwrap_or(false)
+    }
+
+    /// Remember how far the primaries got after the client wrote to them,
+    /// so the next reads go to replicas that replayed those writes.
+    ///
+    /// If that fails, reads go to any replica.
+    pub(crate) async fn record_lsn(&mut self) {
+        if !self.lsn_pending() {
+            self.writes = None;
+            return;
+        }
+        let Some(writes) = self.writes.take() else {
+            return;
+        };
+
+        if let Err(err) = self.current_lsns(&writes).await {
+            error!("couldn't record lsn: {}", err);
+            match writes {
+                Shard::Direct(shard) => {
+                    self.lsns.remove(&shard);
+                }
+                Shard::Multi(shards) => shards.iter().for_each(|shard| {
+                    self.lsns.remove(shard);
+                }),
+                Shard::All => self.lsns.clear(),
+            }
+        }
+    }
+
+    /// Get the current LSN from the primaries the client wrote to.
+    async fn current_lsns(&mut self, writes: &Shard) -> Result<(), Error> {
+        match &mut self.binding {
+            Binding::Server(Some(server)) => {
+                if let (Shard::Direct(shard), Some(lsn)) = (writes, Lsn::current(server).await?) {
+                    self.lsns.insert(*shard, lsn);
+                }
+            }
+
+            Binding::MultiShard(servers, _) => {
+                let shards = (0..).filter(|shard| match writes {
+                    Shard::Multi(numbers) => numbers.contains(shard),
+                    _ => true,
+                });
+                for (shard, server) in shards.zip(servers.iter_mut()) {
+                    if let Some(lsn) = Lsn::current(server).await? {
+                        self.lsns.insert(shard, lsn);
+                    }
+                }
+            }
+
+            _ => (),
+        }
+
+        Ok(())
+    }
+
     /// Try to get a connection for the given route.
     async fn try_conn(&mut self, request: &Request, route: &Route) -> Result<(), Error> {
+        let request = &request.critical(route.is_critical());
+        self.writes = if route.is_write() {
+            Some(route.shard().clone())
+        } else {
+            None
+        };
+
         if let Shard::Direct(shard) = route.shard() {
             let mut server = if route.is_read() {
-                self.cluster()?.replica(*shard, request).await?
+                let request = self.read_request(request, *shard);
+                self.cluster()?.replica(*shard, &request).await?
             } else {
                 self.cluster()?.primary(*shard, request).await?
             };
@@ -170,7 +279,7 @@ impl Connection {
                     }
                 };
                 let mut server = if route.is_read() {
-                    shard.replica(request).await?
+                    shard.replica(&self.read_request(request, i)).await?
                 } else {
                     shard.primary(request).await?
                 };
@@ -189,6 +298,282 @@ impl Connection {
         Ok(())
     }
 
+    /// Execute `INSERT ... SELECT` across shards, returning the number of inserted rows.
+    ///
+    /// This uses its own connections and doesn't affect the binding.
+    pub(crate) async fn insert_select(
+        &self,
+        request: &Request,
+        plan: &InsertSelect,
+    ) -> Result<usize, Error> {
+        InsertSelectExecutor::new(self.cluster()?, plan)
+            .execute(request)
+            .await
+    }
+
+    /// Execute a join between tables that aren't on the same shards.
+    ///
+    /// Rows are returned in batches, see [`JoinExecutor::next`].
+    /// This uses its own connections and doesn't affect the binding.
+    pub(crate) fn join<'a>(
+        &'a self,
+        request: &Request,
+        plan: &'a Join,
+    ) -> Result<JoinExecutor<'a>, Error> {
+        Ok(JoinExecutor::new(self.cluster()?, plan, request))
+    }
+
+    /// Execute `EXPLAIN` on multiple shards, combining their plans.
+    ///
+    /// This uses its own connections and doesn't affect the binding.
+    pub(crate) async fn explain(&self, request: &Request, plan: &Explain) -> Result<Rows, Error> {
+        ExplainExecutor::new(self.cluster()?, plan)
+            .execute(request)
+            .await
+    }
+
+    /// Fetch rows from a cursor open on multiple shards.
+    ///
+    /// Rows are requested from each shard only if needed, so they
+    /// can be returned in the cursor's order. Rows buffered by pgdog
+    /// are subject to the multi-shard row limit.
+    pub(crate) async fn fetch(&mut self, fetch: &Fetch) -> Result<Rows, Error> {
+        let Binding::MultiShard(ref mut servers, _) = self.binding else {
+            return Err(Error::NotConnected);
+        };
+        let limit = config().config.general.multi_shard_row_limit;
+
+        let cursor = self
+            .cursors
+            .entry(fetch.cursor.clone())
+            .or_insert_with(|| Cursor::new(servers.len(), &fetch.order_by, Decoder::new()));
+        let name = format!("\"{}\"", fetch.cursor.replace('"', "\"\""));
+
+        for (shard, server) in servers.iter_mut().enumerate() {
+            let Some(mut missing) = cursor.missing(shard, fetch.count) else {
+                continue;
+            };
+
+            // Request one row over the limit, so we know it was exceeded.
+            if let (None, Some(limit)) = (missing, limit) {
+                missing = Some((limit + 1).saturating_sub(cursor.buffered()).max(1));
+            }
+
+            let query = match missing {
+                Some(rows) => format!("FETCH FORWARD {} FROM {}", rows, name),
+                None => format!("FETCH ALL FROM {}", name),
+            };
+
+            let mut rows = 0;
+            for message in server.execute_checked(query.as_str()).await? {
+                match message.code() {
+                    'T' => cursor.row_description(RowDescription::from_bytes(message.to_bytes()?)?),
+                    'D' => {
+                        cursor.add(shard, DataRow::from_bytes(message.to_bytes()?)?);
+                        rows += 1;
+                    }
+                    _ => (),
+                }
+            }
+
+            if let Some(limit) = limit {
+                if cursor.buffered() > limit {
+                    return Err(Error::MultiShardRowLimit(limit));
+                }
+            }
+
+            // Shard returned fewer rows than requested.
+            if missing.map(|missing| rows < missing).unwrap_or(true) {
+                cursor.done(shard);
+            }
+        }
+
+        Ok(Rows {
+            rd: cursor.rd().clone(),
+            rows: cursor.take(fetch.count),
+        })
+    }
+
+    /// Forget a cursor open on multiple shards. An empty name forgets all of them.
+    pub(crate) fn close_cursor(&mut self, name: &str) {
+        if name.is_empty() {
+            self.cursors.clear();
+        } else {
+            self.cursors.remove(name);
+        }
+    }
+
+    /// Forget all cursors and portals, e.g. when the transaction is finished.
+    pub(crate) fn reset_cursors(&mut self) {
+        self.cursors.clear();
+        self.portals.clear();
+    }
+
+    /// Execute a portal with a row limit on multiple shards.
+    ///
+    /// Each shard is asked for enough rows to return the next batch in order, and
+    /// rows it returned in excess are kept for the next `Execute`. Returns the messages
+    /// to send to the client, or `None` if the request should be sent to the servers as-is.
+    pub(crate) async fn execute_portal(
+        &mut self,
+        buffer: &crate::frontend::Buffer,
+    ) -> Result<Option<Vec<Message>>, Error> {
+        let Binding::MultiShard(ref mut servers, ref state) = self.binding else {
+            return Ok(None);
+        };
+
+        let Some(position) = buffer.iter().position(|message| {
+            matches!(message, ProtocolMessage::Execute(execute) if execute.max_rows() > 0)
+        }) else {
+            return Ok(None);
+        };
+        let (prefix, suffix) = buffer.split_at(position);
+        let ProtocolMessage::Execute(ref execute) = suffix[0] else {
+            return Ok(None);
+        };
+
+        // Only Sync and Flush are expected after Execute.
+        let sync = suffix[1..]
+            .iter()
+            .any(|message| matches!(message, ProtocolMessage::Sync(_)));
+        if !suffix[1..]
+            .iter()
+            .all(|message| matches!(message, ProtocolMessage::Sync(_)) || message.code() == 'H')
+            || !state.route().aggregate().is_empty()
+        {
+            return Ok(None);
+        }
+
+        let portal = execute.portal().to_owned();
+        let count = execute.max_rows() as usize;
+        let limit = config().config.general.multi_shard_row_limit;
+
+        // Binding the portal again starts it over.
+        if prefix.iter().any(
+            |message| matches!(message, ProtocolMessage::Bind(bind) if bind.portal() == portal),
+        ) {
+            self.portals.remove(&portal);
+        }
+
+        let cursor = self.portals.entry(portal.clone()).or_insert_with(|| {
+            Cursor::new(
+                servers.len(),
+                state.route().order_by(),
+                state.decoder().clone(),
+            )
+        });
+
+        let mut messages = vec![];
+        let mut error = None;
+
+        for (shard, server) in servers.iter_mut().enumerate() {
+            let missing = cursor.missing(shard, Some(count)).flatten();
+            if missing.is_none() && prefix.is_empty() {
+                continue;
+            }
+
+            for message in prefix {
+                server.send_one(message).await?;
+            }
+            server
+                .send_one(&ProtocolMessage::Execute(Execute::new_portal_max_rows(
+                    &portal,
+                    missing.unwrap_or(count) as i32,
+                )))
+                .await?;
+            server.send_one(&Flush.into()).await?;
+            server.flush().await?;
+
+            loop {
+                let message = server.read().await?;
+                match message.code() {
+                    'D' => {
+                        cursor.add(shard, DataRow::from_bytes(message.to_bytes()?)?);
+                        if let Some(limit) = limit {
+                            if cursor.buffered() > limit {
+                                return Err(Error::MultiShardRowLimit(limit));
+                            }
+                        }
+                    }
+                    'T' => {
+                        cursor.row_description(RowDescription::from_bytes(message.to_bytes()?)?);
+                        if shard == 0 {
+                            messages.push(message);
+                        }
+                    }
+                    '1' | '2' | '3' | 'n' | 't' => {
+                        if shard == 0 {
+                            messages.push(message);
+                        }
+                    }
+                    'C' | 'I' => {
+                        cursor.done(shard);
+                        break;
+                    }
+                    's' => break,
+                    'E' => {
+                        error.get_or_insert(message);
+                        break;
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        let failed = error.is_some();
+        let exhausted = if let Some(error) = error {
+            messages.push(error);
+            true
+        } else {
+            let rows = cursor.take(Some(count));
+            let returned = rows.len();
+            for row in rows {
+                messages.push(row.message()?);
+            }
+
+            if cursor.exhausted() {
+                messages.push(CommandComplete::new(format!("SELECT {}", returned)).message()?);
+                true
+            } else {
+                messages.push(PortalSuspended.message()?);
+                false
+            }
+        };
+
+        if exhausted {
+            self.portals.remove(&portal);
+        }
+
+        // Shards that returned an error ignore everything until Sync,
+        // so sync them even if the client didn't ask for it yet.
+        if sync || failed {
+            let mut ready = None;
+            for server in servers.iter_mut() {
+                server.send_one(&Sync.into()).await?;
+                server.flush().await?;
+                loop {
+                    let message = server.read().await?;
+                    if message.code() == 'Z' {
+                        ready.get_or_insert(message);
+                        break;
+                    }
+                }
+            }
+
+            if let Some(ready) = ready {
+                // Portals are closed when the transaction is finished.
+                if !ready.in_transaction() {
+                    self.portals.clear();
+                }
+                if sync {
+                    messages.push(ready);
+                }
+            }
+        }
+
+        Ok(Some(messages))
+    }
+
     /// Get server parameters.
     pub(crate) async fn parameters(
         &mut self,
@@ -216,6 +601,7 @@ impl Connection {
     /// Disconnect from a server.
     pub(crate) fn disconnect(&mut self) {
         self.binding.disconnect();
+        self.reset_cursors();
     }
 
     /// Close the connection without banning the pool.
@@ -223,6 +609,13 @@ impl Connection {
         self.binding.force_close()
     }
 
+    /// Release the server(s) after a failed read, so it can be retried on another one.
+    /// Servers that errored are closed and their pools banned.
+    pub(crate) fn retry(&mut self) {
+        self.binding.retry();
+        self.reset_cursors();
+    }
+
     /// Read a message from the server connection.
     ///
     /// Only await this future inside a `select!`. One of the conditions
@@ -3,6 +3,7 @@
 use context::Context;
 
 use crate::{
+    config::config,
     frontend::{router::Route, PreparedStatements},
     net::{
         messages::{
@@ -22,6 +23,7 @@ mod test;
 #[derive(Default, Debug)]
 struct Counters {
     rows: usize,
+    data_rows: usize,
     ready_for_query: usize,
     command_complete_count: usize,
     empty_query_response: usize,
@@ -42,6 +44,8 @@ pub(super) struct MultiShard {
     shards: usize,
     /// Route the query is taking.
     route: Route,
+    /// Maximum number of rows returned by all shards.
+    row_limit: Option<usize>,
 
     /// Counters
     counters: Counters,
@@ -57,6 +61,7 @@ impl MultiShard {
         Self {
             shards,
             route: route.clone(),
+            row_limit: config().config.general.multi_shard_row_limit,
             counters: Counters::default(),
             ..Default::default()
         }
@@ -142,6 +147,13 @@ impl MultiShard {
             }
 
             'D' => {
+                self.counters.data_rows += 1;
+                if let Some(limit) = self.row_limit {
+                    if self.counters.data_rows > limit {
+                        return Err(super::Error::MultiShardRowLimit(limit));
+                    }
+                }
+
                 if !self.route.should_buffer() && self.counters.row_description % self.shards == 0 {
                     forward = Some(message);
                 } else {
@@ -207,6 +219,16 @@ impl MultiShard {
         }
     }
 
+    /// Route the query is taking.
+    pub(super) fn route(&self) -> &Route {
+        &self.route
+    }
+
+    /// Decoder for rows returned by the shards.
+    pub(super) fn decoder(&self) -> &Decoder {
+        &self.decoder
+    }
+
     pub(super) fn set_context<'a>(&mut self, message: impl Into<Context<'a>>) {
         let context = message.into();
         match context {
@@ -59,3 +59,25 @@ fn test_rd_before_dr() {
     // Buffer is empty.
     assert!(multi_shard.message().is_none());
 }
+
+#[test]
+fn test_row_limit() {
+    let mut multi_shard = MultiShard::new(2, &Route::read(None));
+    multi_shard.row_limit = Some(2);
+    let rd = RowDescription::new(&[Field::bigint("id")]);
+    let mut dr = DataRow::new();
+    dr.add(1i64);
+
+    for _ in 0..2 {
+        multi_shard.forward(rd.message().unwrap()).unwrap();
+    }
+
+    for _ in 0..2 {
+        assert!(multi_shard.forward(dr.message().unwrap()).is_ok());
+    }
+
+    assert!(matches!(
+        multi_shard.forward(dr.message().unwrap()),
+        Err(crate::backend::Error::MultiShardRowLimit(2))
+    ));
+}
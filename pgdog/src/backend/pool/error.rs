@@ -47,4 +47,16 @@ pub enum Error {
 
     #[error("router error")]
     Router,
+
+    #[error("replica lag")]
+    ReplicaLag,
+
+    #[error("invalid lsn")]
+    Lsn,
+
+    #[error("replicas haven't replayed the client's writes")]
+    ReplicaBehind,
+
+    #[error("connect timeout")]
+    ConnectTimeout,
 }
@@ -0,0 +1,290 @@
+//! Automatic failover.
+//!
+//! When a shard's primary is banned and unreachable for longer than `failover_threshold`,
+//! and a majority of the `failover_nodes` pgdog nodes agree (see [`crate::net::discovery`]),
+//! the leader, i.e. the node with the lowest ID that voted, promotes the replica
+//! that replayed the most WAL with `pg_promote()`. Writes are paused while the replica is promoted.
+//! Other nodes route writes to the replica once they see it's no longer in recovery.
+//!
+//! The old primary is banned until it's unbanned manually. Other replicas
+//! aren't reconfigured to follow the new primary, and the configuration
+//! file should be updated before the next reload.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use tokio::spawn;
+use tokio::time::{interval, timeout, Instant};
+use tracing::{debug, error, info, warn};
+
+use crate::backend::{databases::databases, Server};
+use crate::config::Role;
+use crate::net::discovery::Listener;
+
+use super::{Error, Lsn, Pool, Request, Shard};
+
+/// How often primaries are checked.
+static INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the replica has to finish promotion.
+static PROMOTE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Failover controller.
+pub struct Failover {
+    /// How long the primary has to be down.
+    threshold: Duration,
+    /// Number of pgdog nodes voting on failover.
+    nodes: usize,
+    /// Primaries that are down, and since when.
+    down: HashMap<String, Instant>,
+}
+
+impl Failover {
+    /// Launch the failover controller.
+    pub fn launch(threshold: Duration, nodes: usize) {
+        let failover = Self {
+            threshold,
+            nodes,
+            down: HashMap::new(),
+        };
+
+        info!(
+            "failover enabled, threshold is {}ms, {} node(s) voting",
+            threshold.as_millis(),
+            nodes
+        );
+
+        spawn(async move {
+            failover.run().await;
+        });
+    }
+
+    async fn run(mut self) {
+        let mut tick = interval(INTERVAL);
+
+        loop {
+            tick.tick().await;
+            self.check().await;
+        }
+    }
+
+    /// Check all primaries and fail over those that are down.
+    async fn check(&mut self) {
+        let databases = databases();
+        let mut checked = HashSet::new();
+
+        for cluster in databases.all().values() {
+            for shard in cluster.shards() {
+                let Some(primary) = shard.primary_pool() else {
+                    continue;
+                };
+
+                // Users share the same primary.
+                let addr = primary.addr().to_string();
+                if !checked.insert(addr.clone()) {
+                    continue;
+                }
+
+                if !primary.banned() || Self::reachable(&primary).await {
+                    if self.down.remove(&addr).is_some() {
+                        info!("primary is back up [{}]", addr);
+                        Listener::get().primary_down(&addr, false);
+                    }
+                    continue;
+                }
+
+                let since = *self.down.entry(addr.clone()).or_insert_with(|| {
+                    warn!("primary is down [{}]", addr);
+                    Instant::now()
+                });
+                Listener::get().primary_down(&addr, true);
+
+                if since.elapsed() < self.threshold {
+                    continue;
+                }
+
+                if !Listener::get().quorum(&addr, self.nodes) {
+                    debug!(
+                        "waiting for other nodes to agree the primary is down [{}]",
+                        addr
+                    );
+                    continue;
+                }
+
+                let leader = Listener::get().leader(&addr);
+
+                match Self::promote(shard, &primary, leader).await {
+                    Ok(true) => {
+                        self.down.remove(&addr);
+                        Listener::get().primary_down(&addr, false);
+                    }
+                    Ok(false) => (),
+                    Err(err) => error!("failover error: {} [{}]", err, addr),
+                }
+            }
+        }
+    }
+
+    /// Check that we can connect to the primary.
+    async fn reachable(primary: &Pool) -> bool {
+        let connect_timeout = primary.lock().config.connect_timeout;
+        matches!(
+            timeout(
+                connect_timeout,
+                Server::connect(primary.addr(), primary.server_options()),
+            )
+            .await,
+            Ok(Ok(_))
+        )
+    }
+
+    /// Promote the most advanced replica to primary.
+    ///
+    /// Only the leader promotes a replica. If another node promoted one already,
+    /// writes are routed to it.
+    async fn promote(
+        shard: &Shard,
+        primary: &Pool,
+        leader: bool,
+    ) -> Result<bool, crate::backend::Error> {
+        let mut candidates = vec![];
+
+        for replica in shard.replicas.pools() {
+            if replica.banned() {
+                continue;
+            }
+
+            if replica.role() == Role::Primary && replica.addr() != primary.addr() {
+                Self::switch(primary, replica);
+                return Ok(true);
+            }
+
+            let lsn = match replica.get(&Request::default()).await {
+                Ok(mut server) => Lsn::last_replayed(&mut server).await,
+                Err(err) => Err(err.into()),
+            };
+
+            match lsn {
+                Ok(Some(lsn)) => candidates.push((lsn, replica.clone())),
+                // Promoted by another node.
+                Ok(None) => {
+                    info!("replica was promoted by another node [{}]", replica.addr());
+                    Self::switch(primary, replica);
+                    return Ok(true);
+                }
+                Err(err) => warn!(
+                    "replica unavailable for failover: {} [{}]",
+                    err,
+                    replica.addr()
+                ),
+            }
+        }
+
+        if !leader {
+            debug!(
+                "waiting for the leader to promote a replica [{}]",
+                primary.addr()
+            );
+            return Ok(false);
+        }
+
+        let Some((lsn, replica)) = most_advanced(candidates) else {
+            error!("no replicas available for failover [{}]", primary.addr());
+            return Ok(false);
+        };
+
+        let old = Self::pools(primary);
+
+        info!(
+            "promoting replica at {} to primary [{}]",
+            lsn,
+            replica.addr()
+        );
+
+        // Pause writes while the replica is promoted.
+        old.iter().for_each(|pool| pool.pause());
+
+        let promoted = Self::pg_promote(&replica).await;
+
+        if let Ok(true) = promoted {
+            Self::switch(primary, &replica);
+        }
+
+        old.iter().for_each(|pool| pool.resume());
+
+        promoted
+    }
+
+    /// Route writes to the promoted replica.
+    fn switch(primary: &Pool, replica: &Pool) {
+        for pool in Self::pools(primary) {
+            pool.set_role(Role::Replica);
+            pool.ban(Error::ManualBan);
+        }
+
+        for pool in Self::pools(replica) {
+            pool.set_role(Role::Primary);
+        }
+
+        warn!(
+            "failover complete, primary is now [{}], update the configuration before reloading",
+            replica.addr()
+        );
+    }
+
+    /// Call `pg_promote()` on the replica.
+    async fn pg_promote(replica: &Pool) -> Result<bool, crate::backend::Error> {
+        let mut server = replica.get(&Request::default()).await?;
+        let query = format!(
+            "SELECT pg_promote(true, {})::text",
+            PROMOTE_TIMEOUT.as_secs()
+        );
+        let promoted = server
+            .fetch_all::<String>(query.as_str())
+            .await?
+            .first()
+            .map(|promoted| promoted == "true")
+            .unwrap_or(false);
+
+        if !promoted {
+            error!("replica wasn't promoted [{}]", replica.addr());
+        }
+
+        Ok(promoted)
+    }
+
+    /// Pools connected to the same database, for all users.
+    fn pools(pool: &Pool) -> Vec<Pool> {
+        databases()
+            .all()
+            .values()
+            .flat_map(|cluster| cluster.shards())
+            .flat_map(|shard| shard.pools())
+            .filter(|other| other.addr().to_string() == pool.addr().to_string())
+            .collect()
+    }
+}
+
+/// Replica that replayed the most WAL.
+fn most_advanced<T>(candidates: Vec<(Lsn, T)>) -> Option<(Lsn, T)> {
+    candidates.into_iter().max_by_key(|(lsn, _)| *lsn)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_most_advanced() {
+        let candidates = vec![
+            ("0/16B3748".parse::<Lsn>().unwrap(), "one"),
+            ("1/0".parse::<Lsn>().unwrap(), "two"),
+            ("0/FFFFFFFF".parse::<Lsn>().unwrap(), "three"),
+        ];
+        let (lsn, replica) = most_advanced(candidates).unwrap();
+        assert_eq!(replica, "two");
+        assert_eq!(lsn.to_string(), "1/0");
+
+        assert!(most_advanced::<()>(vec![]).is_none());
+    }
+}
@@ -2,13 +2,17 @@
 
 use std::cmp::max;
 use std::collections::VecDeque;
+use std::time::Duration;
 
 use crate::backend::{stats::Counts as BackendCounts, Server};
+use crate::config::Role;
 use crate::net::messages::BackendKeyData;
 
 use tokio::time::Instant;
 
-use super::{Ban, Config, Error, Mapping, Oids, Pool, Request, Stats, Taken, Waiter};
+use super::{
+    Ban, CircuitBreaker, Config, Error, Lsn, Mapping, Oids, Pool, Request, Stats, Taken, Waiter,
+};
 
 /// Pool internals protected by a mutex.
 #[derive(Default)]
@@ -24,6 +28,8 @@ pub(super) struct Inner {
     pub(super) waiting: VecDeque<Waiter>,
     /// Pool ban status.
     pub(super) ban: Option<Ban>,
+    /// Decides how long bans last.
+    pub(super) breaker: CircuitBreaker,
     /// Pool is online and available to clients.
     pub(super) online: bool,
     /// Pool is paused.
@@ -41,6 +47,17 @@ pub(super) struct Inner {
     pub(super) stats: Stats,
     /// OIDs.
     pub(super) oids: Option<Oids>,
+    /// Replication lag, measured by the healthcheck.
+    pub(super) replica_lag: Option<Duration>,
+    /// WAL position, written on primaries and replayed on replicas.
+    pub(super) lsn: Option<Lsn>,
+    /// When the WAL position was measured.
+    pub(super) lsn_checked_at: Option<Instant>,
+    /// Role detected by the healthcheck, if configured with `role = "auto"`,
+    /// or changed by failover.
+    pub(super) detected_role: Option<Role>,
+    /// When the detected role last changed.
+    pub(super) role_changed_at: Option<Instant>,
     /// The pool has been changed and connections should be returned
     /// to the new pool.
     moved: Option<Pool>,
@@ -68,6 +85,7 @@ impl Inner {
             config,
             waiting: VecDeque::new(),
             ban: None,
+            breaker: CircuitBreaker::default(),
             online: false,
             paused: false,
             force_close: 0,
@@ -76,6 +94,11 @@ impl Inner {
             errors: 0,
             stats: Stats::default(),
             oids: None,
+            replica_lag: None,
+            lsn: None,
+            lsn_checked_at: None,
+            detected_role: None,
+            role_changed_at: None,
             moved: None,
             id,
         }
@@ -151,6 +174,7 @@ impl Inner {
             if !ban.expired(now) {
                 self.ban = Some(ban);
             } else {
+                self.breaker.half_open();
                 unbanned = true;
             }
         }
@@ -198,6 +222,30 @@ impl Inner {
         removed
     }
 
+    /// Current role of the database. `Auto` if it's not known yet.
+    #[inline]
+    pub(super) fn role(&self) -> Role {
+        self.detected_role.unwrap_or(self.config.role)
+    }
+
+    /// Set the detected role. Returns the previous one.
+    pub(super) fn set_role(&mut self, role: Role, now: Instant) -> Option<Role> {
+        let previous = self.detected_role.replace(role);
+        if previous != Some(role) {
+            self.role_changed_at = Some(now);
+        }
+        previous
+    }
+
+    /// Forget the detected role of a database configured with `role = "auto"`,
+    /// e.g. because it's unreachable. It's detected again by the next healthcheck.
+    pub(super) fn clear_role(&mut self) {
+        if self.config.role == Role::Auto {
+            self.detected_role = None;
+            self.role_changed_at = None;
+        }
+    }
+
     /// Pool configuration options.
     #[inline]
     pub(super) fn config(&self) -> &Config {
@@ -233,8 +281,8 @@ impl Inner {
                     server: id,
                     client: waiter.request.id,
                 });
-                self.stats.counts.server_assignment_count += 1;
-                self.stats.counts.wait_time += now.duration_since(waiter.request.created_at);
+                self.stats
+                    .wait(now.duration_since(waiter.request.created_at), now);
             }
         } else {
             self.conns.push(conn);
@@ -292,7 +340,7 @@ impl Inner {
         self.taken.check_in(server.id());
 
         // Update stats
-        self.stats.counts = self.stats.counts + stats;
+        self.stats.check_in(stats, now);
 
         // Ban the pool from serving more clients.
         if server.error() {
@@ -301,6 +349,8 @@ impl Inner {
             return result;
         }
 
+        self.breaker.success();
+
         // Pool is offline or paused, connection should be closed.
         if !self.online || self.paused {
             result.replenish = false;
@@ -358,13 +408,23 @@ impl Inner {
     #[inline]
     pub fn maybe_ban(&mut self, now: Instant, reason: Error) -> bool {
         if self.config.bannable || reason == Error::ManualBan {
+            let ban_timeout = self.breaker.open(
+                reason,
+                self.config.ban_initial_timeout,
+                self.config.ban_timeout(),
+            );
             let ban = Ban {
                 created_at: now,
                 reason,
-                ban_timeout: self.config.ban_timeout(),
+                ban_timeout,
             };
             self.ban = Some(ban);
 
+            // Lagging replicas are still replicas.
+            if reason != Error::ReplicaLag {
+                self.clear_role();
+            }
+
             // Tell every waiting client that this pool is busted.
             self.close_waiters(Error::Banned);
             true
@@ -388,6 +448,7 @@ impl Inner {
             if ban.reason == Error::ManualBan {
                 self.ban = Some(ban);
             } else {
+                self.breaker.half_open();
                 unbanned = true;
             }
         }
@@ -396,6 +457,7 @@ impl Inner {
     }
 
     pub fn unban(&mut self) -> bool {
+        self.breaker.close();
         self.ban.take().is_some()
     }
 
@@ -417,6 +479,7 @@ mod test {
 
     use tokio::sync::oneshot::channel;
 
+    use crate::backend::pool::CircuitState;
     use crate::net::messages::BackendKeyData;
 
     use super::*;
@@ -435,12 +498,19 @@ mod test {
         // The ban list.
         let banned = inner.maybe_ban(Instant::now(), Error::CheckoutTimeout);
         assert!(banned);
-        let unbanned = inner.check_ban(Instant::now() + Duration::from_secs(100));
+        let unbanned = inner.check_ban(Instant::now() + Duration::from_secs(4));
         assert!(!unbanned);
         assert!(inner.banned());
-        let unbanned = inner.check_ban(Instant::now() + Duration::from_secs(301));
+        let unbanned = inner.check_ban(Instant::now() + Duration::from_secs(6));
         assert!(unbanned);
         assert!(!inner.banned());
+        assert_eq!(inner.breaker.state(), CircuitState::HalfOpen);
+
+        // Failed again, banned for longer.
+        inner.maybe_ban(Instant::now(), Error::CheckoutTimeout);
+        assert_eq!(inner.ban.unwrap().ban_timeout, Duration::from_secs(10));
+        assert!(!inner.check_ban(Instant::now() + Duration::from_secs(6)));
+        assert!(inner.check_ban(Instant::now() + Duration::from_secs(11)));
         let unbanned = inner.maybe_unban();
         assert!(!unbanned);
         assert!(!inner.banned());
@@ -0,0 +1,80 @@
+//! Write-ahead log position.
+
+use std::{fmt::Display, str::FromStr};
+
+use crate::backend::Server;
+
+use super::Error;
+
+/// Log sequence number, e.g. `16/B374D848`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Lsn(u64);
+
+impl Lsn {
+    /// Current write position on the primary.
+    pub async fn current(server: &mut Server) -> Result<Option<Lsn>, super::super::Error> {
+        Ok(server
+            .fetch_all::<String>("SELECT pg_current_wal_lsn()::text")
+            .await?
+            .first()
+            .and_then(|lsn| lsn.parse().ok()))
+    }
+
+    /// Last position replayed by the replica.
+    pub async fn last_replayed(server: &mut Server) -> Result<Option<Lsn>, super::super::Error> {
+        Ok(server
+            .fetch_all::<String>("SELECT pg_last_wal_replay_lsn()::text")
+            .await?
+            .first()
+            .and_then(|lsn| lsn.parse().ok()))
+    }
+
+    /// Position replayed by replicas, or written by primaries.
+    pub async fn position(server: &mut Server) -> Result<Option<Lsn>, super::super::Error> {
+        Ok(server
+            .fetch_all::<String>(
+                "SELECT (CASE WHEN pg_is_in_recovery() THEN pg_last_wal_replay_lsn()
+                ELSE pg_current_wal_lsn() END)::text",
+            )
+            .await?
+            .first()
+            .and_then(|lsn| lsn.parse().ok()))
+    }
+}
+
+impl FromStr for Lsn {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (high, low) = s.trim().split_once('/').ok_or(Error::Lsn)?;
+        let high = u32::from_str_radix(high, 16).map_err(|_| Error::Lsn)?;
+        let low = u32::from_str_radix(low, 16).map_err(|_| Error::Lsn)?;
+
+        Ok(Self(((high as u64) << 32) | low as u64))
+    }
+}
+
+impl Display for Lsn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:X}/{:X}", self.0 >> 32, self.0 as u32)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lsn() {
+        let lsn: Lsn = "16/B374D848".parse().unwrap();
+        assert_eq!(lsn.0, (0x16 << 32) | 0xB374D848);
+        assert_eq!(lsn.to_string(), "16/B374D848");
+
+        let earlier: Lsn = "0/16B3748".parse().unwrap();
+        assert!(earlier < lsn);
+        assert_eq!(earlier.to_string(), "0/16B3748");
+
+        assert!("16B374D848".parse::<Lsn>().is_err());
+        assert!("16/xyz".parse::<Lsn>().is_err());
+    }
+}
@@ -2,15 +2,18 @@
 
 pub mod address;
 pub mod ban;
+pub mod circuit_breaker;
 pub mod cleanup;
 pub mod cluster;
 pub mod comms;
 pub mod config;
 pub mod connection;
 pub mod error;
+pub mod failover;
 pub mod guard;
 pub mod healthcheck;
 pub mod inner;
+pub mod lsn;
 pub mod mapping;
 pub mod monitor;
 pub mod oids;
@@ -30,6 +33,7 @@ pub use connection::Connection;
 pub use error::Error;
 pub use guard::Guard;
 pub use healthcheck::Healtcheck;
+pub use lsn::Lsn;
 use monitor::Monitor;
 pub use oids::Oids;
 pub use pool_impl::Pool;
@@ -40,6 +44,7 @@ pub use state::State;
 pub use stats::Stats;
 
 use ban::Ban;
+pub use circuit_breaker::{BanRecord, CircuitBreaker, CircuitState};
 use comms::Comms;
 use inner::Inner;
 use mapping::Mapping;
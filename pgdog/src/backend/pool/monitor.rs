@@ -34,17 +34,31 @@
 
 use std::time::Duration;
 
-use super::{Error, Guard, Healtcheck, Oids, Pool, Request};
-use crate::backend::Server;
+use super::{Error, Guard, Healtcheck, Lsn, Oids, Pool, Request, Shard};
+use crate::backend::{databases::databases, Server};
+use crate::config::Role;
 
 use tokio::time::{interval, sleep, timeout, Instant};
 use tokio::{select, task::spawn};
 use tracing::info;
 
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 
 static MAINTENANCE: Duration = Duration::from_millis(333);
 
+/// Replication lag in milliseconds, measured as the time since the replica
+/// replayed the last transaction, unless it's caught up with the primary.
+static REPLICA_LAG: &str = "SELECT CASE
+    WHEN NOT pg_is_in_recovery() THEN 0
+    WHEN {caught_up} THEN 0
+    ELSE COALESCE((EXTRACT(EPOCH FROM now() - pg_last_xact_replay_timestamp()) * 1000)::bigint, 0)
+END";
+
+/// The replica replayed everything it received and is still streaming from the primary.
+/// Used when the primary's position isn't known.
+static STREAMING_CAUGHT_UP: &str = "pg_last_wal_receive_lsn() = pg_last_wal_replay_lsn()
+        AND EXISTS (SELECT 1 FROM pg_stat_wal_receiver WHERE status = 'streaming')";
+
 /// Pool maintenance.
 ///
 /// See [`crate::backend::pool::monitor`] module documentation
@@ -81,7 +95,13 @@ impl Monitor {
         let (delay, replication_mode) = {
             let lock = pool.lock();
             let config = lock.config();
-            (config.idle_healthcheck_delay(), config.replication_mode)
+            // Detect the role right away, we can't serve writes until then.
+            let delay = if config.role == Role::Auto {
+                Duration::ZERO
+            } else {
+                config.idle_healthcheck_delay()
+            };
+            (delay, config.replication_mode)
         };
 
         if !replication_mode {
@@ -169,7 +189,9 @@ impl Monitor {
 
                     // If the server is okay, remove the ban if it had one.
                     if let Ok(true) = Self::healthcheck(&pool).await {
-                        unbanned = pool.lock().maybe_unban();
+                        let mut guard = pool.lock();
+                        unbanned = guard.maybe_unban();
+                        guard.breaker.success();
                     }
                 }
 
@@ -275,7 +297,9 @@ impl Monitor {
     async fn healthcheck(pool: &Pool) -> Result<bool, Error> {
         let (conn, healthcheck_timeout, connect_timeout) = {
             let mut guard = pool.lock();
-            if !guard.online || guard.banned() {
+            // Lagging replicas are checked until they catch up.
+            let lagging = guard.ban.map(|ban| ban.reason == Error::ReplicaLag);
+            if !guard.online || lagging == Some(false) {
                 return Ok(false);
             }
             (
@@ -287,15 +311,17 @@ impl Monitor {
 
         // Have an idle connection, use that for the healthcheck.
         if let Some(conn) = conn {
-            Healtcheck::mandatory(
-                &mut Guard::new(pool.clone(), conn, Instant::now()),
+            let mut conn = Guard::new(pool.clone(), conn, Instant::now());
+            Self::unreachable(
                 pool,
-                healthcheck_timeout,
-            )
-            .healthcheck()
-            .await?;
-
-            Ok(true)
+                Healtcheck::mandatory(&mut conn, pool, healthcheck_timeout)
+                    .healthcheck()
+                    .await,
+            )?;
+
+            Self::detect_role(&mut conn, pool).await;
+            Self::record_lsn(&mut conn, pool).await;
+            Self::replica_lag(&mut conn, pool).await
         } else {
             // Create a new one and close it. once done.
             info!("creating new healthcheck connection [{}]", pool.addr());
@@ -306,16 +332,24 @@ impl Monitor {
             .await
             {
                 Ok(Ok(mut server)) => {
-                    Healtcheck::mandatory(&mut server, pool, healthcheck_timeout)
-                        .healthcheck()
-                        .await?
+                    Self::unreachable(
+                        pool,
+                        Healtcheck::mandatory(&mut server, pool, healthcheck_timeout)
+                            .healthcheck()
+                            .await,
+                    )?;
+                    Self::detect_role(&mut server, pool).await;
+                    Self::record_lsn(&mut server, pool).await;
+                    Self::replica_lag(&mut server, pool).await?;
                 }
                 Ok(Err(err)) => {
                     error!("healthcheck error: {} [{}]", err, pool.addr());
+                    Self::forget(pool);
                 }
 
                 Err(_) => {
                     error!("healthcheck timeout [{}]", pool.addr());
+                    Self::forget(pool);
                 }
             }
 
@@ -323,6 +357,168 @@ impl Monitor {
         }
     }
 
+    /// The role and position of a database that failed the healthcheck aren't known anymore.
+    fn unreachable<T>(pool: &Pool, result: Result<T, Error>) -> Result<T, Error> {
+        if result.is_err() {
+            Self::forget(pool);
+        }
+        result
+    }
+
+    fn forget(pool: &Pool) {
+        let mut guard = pool.lock();
+        guard.clear_role();
+        guard.lsn = None;
+        guard.lsn_checked_at = None;
+    }
+
+    /// Record the WAL position, so replica healthchecks can measure lag
+    /// against the primary and reads can pick replicas that replayed the client's writes,
+    /// without checking out connections.
+    pub(super) async fn record_lsn(server: &mut Server, pool: &Pool) {
+        let lsn = match Lsn::position(server).await {
+            Ok(lsn) => lsn,
+            Err(err) => {
+                error!("wal position check error: {} [{}]", err, pool.addr());
+                None
+            }
+        };
+
+        pool.set_lsn(lsn);
+    }
+
+    /// Detect if the database is a primary or a replica,
+    /// if it's configured with `role = "auto"`.
+    pub(super) async fn detect_role(server: &mut Server, pool: &Pool) {
+        if pool.lock().config.role != Role::Auto {
+            return;
+        }
+
+        let role = match server
+            .fetch_all::<String>("SELECT pg_is_in_recovery()::text")
+            .await
+        {
+            Ok(recovery) => match recovery.first().map(|recovery| recovery.as_str()) {
+                Some("true") => Role::Replica,
+                Some("false") => Role::Primary,
+                _ => return,
+            },
+            Err(err) => {
+                error!("role detection error: {} [{}]", err, pool.addr());
+                return;
+            }
+        };
+
+        let previous = pool.lock().set_role(role, Instant::now());
+
+        if role == Role::Primary && previous != Some(role) {
+            for other in Self::shard(pool)
+                .iter()
+                .flat_map(|shard| shard.pools())
+                .filter(|other| other.addr() != pool.addr() && other.role() == Role::Primary)
+            {
+                warn!(
+                    "both [{}] and [{}] are primaries, using the most recently detected one",
+                    other.addr(),
+                    pool.addr()
+                );
+            }
+        }
+
+        match previous {
+            Some(previous) if previous != role => {
+                warn!(
+                    "role changed from {} to {} [{}]",
+                    previous,
+                    role,
+                    pool.addr()
+                );
+            }
+            None => info!("detected role {} [{}]", role, pool.addr()),
+            _ => (),
+        }
+    }
+
+    /// Measure replication lag and ban the replica
+    /// if it's too far behind the primary.
+    pub(super) async fn replica_lag(server: &mut Server, pool: &Pool) -> Result<bool, Error> {
+        if pool.lock().role() != Role::Replica {
+            return Ok(true);
+        }
+
+        // Replicas that replayed everything the primary wrote aren't lagging.
+        let caught_up = match Self::primary_lsn(pool) {
+            Some(lsn) => format!("pg_last_wal_replay_lsn() >= '{}'::pg_lsn", lsn),
+            None => STREAMING_CAUGHT_UP.to_string(),
+        };
+        let query = REPLICA_LAG.replace("{caught_up}", &caught_up);
+
+        let lag = match server.fetch_all::<i64>(query.as_str()).await {
+            Ok(lag) => lag
+                .first()
+                .map(|lag| Duration::from_millis(*lag.max(&0) as u64)),
+            Err(err) => {
+                error!("replica lag check error: {} [{}]", err, pool.addr());
+                None
+            }
+        };
+
+        Ok(Self::record_lag(pool, lag))
+    }
+
+    /// Shard the database belongs to.
+    fn shard(pool: &Pool) -> Option<Shard> {
+        databases()
+            .all()
+            .values()
+            .flat_map(|cluster| cluster.shards())
+            .find(|shard| {
+                shard
+                    .pools()
+                    .iter()
+                    .any(|other| other.addr() == pool.addr())
+            })
+            .cloned()
+    }
+
+    /// Write position of the primary in the replica's shard,
+    /// as of the primary's last healthcheck.
+    fn primary_lsn(pool: &Pool) -> Option<Lsn> {
+        Self::shard(pool)?.primary_pool()?.lsn()
+    }
+
+    /// Record replication lag and ban the replica if it's over the limit.
+    ///
+    /// Returns `false` if the replica is lagging.
+    pub(super) fn record_lag(pool: &Pool, lag: Option<Duration>) -> bool {
+        let max_replica_lag = {
+            let mut guard = pool.lock();
+            guard.replica_lag = lag;
+            guard.config.max_replica_lag
+        };
+
+        match (lag, max_replica_lag) {
+            (Some(lag), Some(max_replica_lag)) if lag > max_replica_lag => {
+                let banned = {
+                    let mut guard = pool.lock();
+                    let banned = guard.banned();
+                    guard.maybe_ban(Instant::now(), Error::ReplicaLag) && !banned
+                };
+
+                if banned {
+                    warn!(
+                        "replica is {}ms behind the primary, banning [{}]",
+                        lag.as_millis(),
+                        pool.addr()
+                    );
+                }
+
+                false
+            }
+            _ => true,
+        }
+    }
+
     async fn stats(pool: Pool) {
         let duration = Duration::from_secs(15);
         let comms = pool.comms();
@@ -6,18 +6,18 @@ use std::time::Duration;
 
 use once_cell::sync::Lazy;
 use parking_lot::{lock_api::MutexGuard, Mutex, RawMutex};
-use tokio::time::Instant;
+use tokio::time::{timeout, Instant};
 use tracing::{error, info};
 
 use crate::backend::{Server, ServerOptions};
-use crate::config::PoolerMode;
+use crate::config::{PoolerMode, Role};
 use crate::net::messages::BackendKeyData;
 use crate::net::Parameter;
 
 use super::inner::CheckInResult;
 use super::{
-    Address, Comms, Config, Error, Guard, Healtcheck, Inner, Monitor, Oids, PoolConfig, Request,
-    State, Waiting,
+    Address, CircuitState, Comms, Config, Error, Guard, Healtcheck, Inner, Lsn, Monitor, Oids,
+    PoolConfig, Request, State, Waiting,
 };
 
 static ID_COUNTER: Lazy<Arc<AtomicU64>> = Lazy::new(|| Arc::new(AtomicU64::new(0)));
@@ -99,7 +99,7 @@ impl Pool {
         let pool = self.clone();
 
         // Fast path, idle connection probably available.
-        let (server, granted_at, paused) = {
+        let (server, granted_at, paused, probe) = {
             // Ask for time before we acquire the lock
             // and only if we actually waited for a connection.
             let granted_at = request.created_at;
@@ -121,14 +121,19 @@ impl Pool {
                 return Err(Error::Banned);
             }
 
+            // Only a few clients are let through after a ban.
+            let probe = guard.breaker.state() == CircuitState::HalfOpen;
+            if !guard.breaker.checkout() {
+                return Err(Error::Banned);
+            }
+
             let conn = guard.take(request);
 
             if conn.is_some() {
-                guard.stats.counts.wait_time += elapsed;
-                guard.stats.counts.server_assignment_count += 1;
+                guard.stats.wait(elapsed, Instant::now());
             }
 
-            (conn, granted_at, guard.paused)
+            (conn, granted_at, guard.paused, probe)
         };
 
         if paused {
@@ -151,7 +156,16 @@ impl Pool {
             // Slow path, pool is empty, will create new connection
             // or wait for one to be returned if the pool is maxed out.
             let waiting = Waiting::new(pool, request)?;
-            waiting.wait().await?
+            match waiting.wait().await {
+                Ok(checkout) => checkout,
+                Err(err) => {
+                    // Probe failed, the pool is still down.
+                    if probe {
+                        self.ban(err);
+                    }
+                    return Err(err);
+                }
+            }
         };
 
         return self
@@ -313,6 +327,18 @@ impl Pool {
         self.addr() == destination.addr()
     }
 
+    /// Connect to the server outside of the pool, e.g. while it's paused.
+    pub(crate) async fn standalone(&self) -> Result<Server, crate::backend::Error> {
+        let connect_timeout = self.lock().config.connect_timeout;
+
+        timeout(
+            connect_timeout,
+            Server::connect(self.addr(), self.server_options()),
+        )
+        .await
+        .map_err(|_| Error::ConnectTimeout)?
+    }
+
     /// Pause pool, closing all open connections.
     pub fn pause(&self) {
         let mut guard = self.lock();
@@ -400,6 +426,49 @@ impl Pool {
         ServerOptions { params }
     }
 
+    /// Current role of the database.
+    pub fn role(&self) -> Role {
+        self.lock().role()
+    }
+
+    /// Change the role of the database, e.g. after failover.
+    pub(crate) fn set_role(&self, role: Role) {
+        self.lock().set_role(role, Instant::now());
+    }
+
+    /// When the role of the database was last detected to have changed.
+    /// `None` if it's configured.
+    pub fn role_changed_at(&self) -> Option<Instant> {
+        self.lock().role_changed_at
+    }
+
+    /// WAL position recorded by the last healthcheck or replica checkout.
+    pub fn lsn(&self) -> Option<Lsn> {
+        self.lock().lsn
+    }
+
+    /// Record the WAL position measured just now.
+    pub(crate) fn set_lsn(&self, lsn: Option<Lsn>) {
+        let mut guard = self.lock();
+        guard.lsn = lsn;
+        guard.lsn_checked_at = Some(Instant::now());
+    }
+
+    /// Load balancing weight.
+    pub fn weight(&self) -> usize {
+        self.lock().config.weight
+    }
+
+    /// Change the load balancing weight, e.g. to drain traffic gradually.
+    pub fn set_weight(&self, weight: usize) {
+        self.lock().config.weight = weight;
+    }
+
+    /// Moving average of checkout wait and query latency.
+    pub fn latency(&self) -> Duration {
+        self.lock().stats.latency(Instant::now())
+    }
+
     /// Pool state.
     pub fn state(&self) -> State {
         State::get(self)
@@ -8,14 +8,18 @@ use std::{
     time::Duration,
 };
 
-use rand::seq::SliceRandom;
-use tokio::time::timeout;
-use tracing::error;
+use rand::{seq::SliceRandom, Rng};
+use tokio::time::{sleep, timeout, Instant};
+use tracing::{debug, error};
 
-use crate::config::LoadBalancingStrategy;
+use crate::config::{LoadBalancingStrategy, Role};
 use crate::net::messages::BackendKeyData;
 
-use super::{Error, Guard, Pool, PoolConfig, Request};
+use super::{Error, Guard, Lsn, Pool, PoolConfig, Request};
+
+/// How often to check if replicas caught up with the client's writes.
+/// Positions measured more recently than that are used instead of asking the replica.
+static LSN_POLL: Duration = Duration::from_millis(10);
 
 /// Replicas pools.
 #[derive(Clone, Default, Debug)]
@@ -28,6 +32,8 @@ pub struct Replicas {
     pub(super) round_robin: Arc<AtomicUsize>,
     /// Chosen load balancing strategy.
     pub(super) lb_strategy: LoadBalancingStrategy,
+    /// How long to wait for replicas to replay the client's writes.
+    pub(super) lsn_timeout: Duration,
 }
 
 impl Replicas {
@@ -37,11 +43,17 @@ impl Replicas {
             .iter()
             .map(|c| c.config.checkout_timeout())
             .sum::<Duration>();
+        let lsn_timeout = addrs
+            .iter()
+            .map(|c| c.config.read_your_writes_timeout)
+            .max()
+            .unwrap_or_default();
         Self {
             pools: addrs.iter().map(Pool::new).collect(),
             checkout_timeout,
             round_robin: Arc::new(AtomicUsize::new(0)),
             lb_strategy,
+            lsn_timeout,
         }
     }
 
@@ -83,6 +95,11 @@ impl Replicas {
         self.len() == 0
     }
 
+    /// All databases were detected to be primaries.
+    pub fn all_primary(&self) -> bool {
+        self.pools.iter().all(|pool| pool.role() == Role::Primary)
+    }
+
     /// Create new identical replica pool.
     pub fn duplicate(&self) -> Replicas {
         Self {
@@ -90,6 +107,7 @@ impl Replicas {
             checkout_timeout: self.checkout_timeout,
             round_robin: Arc::new(AtomicUsize::new(0)),
             lb_strategy: self.lb_strategy,
+            lsn_timeout: self.lsn_timeout,
         }
     }
 
@@ -113,13 +131,23 @@ impl Replicas {
         primary: &Option<Pool>,
     ) -> Result<Guard, Error> {
         let mut unbanned = false;
+        let started = Instant::now();
         loop {
-            let mut candidates = self.pools.iter().collect::<Vec<_>>();
+            let mut candidates = self
+                .pools
+                .iter()
+                .filter(|pool| pool.role() != Role::Primary)
+                .collect::<Vec<_>>();
 
             if let Some(primary) = primary {
                 candidates.push(primary);
             }
 
+            // Roles changed since the shard checked.
+            if candidates.is_empty() {
+                return Err(Error::NoReplicas);
+            }
+
             use LoadBalancingStrategy::*;
 
             match self.lb_strategy {
@@ -134,13 +162,66 @@ impl Replicas {
                 LeastActiveConnections => {
                     candidates.sort_by_cached_key(|pool| pool.lock().idle());
                 }
+                WeightedRandom => {
+                    candidates = weighted_random(
+                        candidates
+                            .into_iter()
+                            .map(|pool| (pool.weight(), pool))
+                            .collect(),
+                    );
+                }
+                WeightedRoundRobin => {
+                    let weights = candidates
+                        .iter()
+                        .map(|pool| pool.weight())
+                        .collect::<Vec<_>>();
+                    let first = weighted_round_robin(
+                        &weights,
+                        self.round_robin.fetch_add(1, Ordering::Relaxed),
+                    );
+                    let mut reshuffled = vec![];
+                    reshuffled.extend_from_slice(&candidates[first..]);
+                    reshuffled.extend_from_slice(&candidates[..first]);
+                    // Drained replicas are used only if nothing else is available.
+                    reshuffled.sort_by_key(|pool| pool.weight() == 0);
+                    candidates = reshuffled;
+                }
+                LeastLatency => {
+                    // Power of two choices: pick the faster of two random replicas.
+                    candidates.shuffle(&mut rand::thread_rng());
+                    if candidates.len() > 1 && candidates[1].latency() < candidates[0].latency() {
+                        candidates.swap(0, 1);
+                    }
+                }
             }
 
             let mut banned = 0;
+            let mut behind = 0;
 
             for candidate in &candidates {
+                let is_primary = primary
+                    .as_ref()
+                    .map(|primary| primary.id() == candidate.id())
+                    .unwrap_or(false);
+                let lsn = request.lsn.filter(|_| !is_primary);
+
+                // Replicas that were behind a moment ago aren't asked again yet.
+                let replayed = lsn.and_then(|lsn| Self::known_replayed(candidate, lsn));
+                if replayed == Some(false) {
+                    behind += 1;
+                    continue;
+                }
+
                 match candidate.get(request).await {
-                    Ok(conn) => return Ok(conn),
+                    Ok(mut conn) => match lsn {
+                        Some(lsn) if replayed.is_none() => {
+                            if Self::replayed(&mut conn, candidate, lsn).await {
+                                return Ok(conn);
+                            }
+                            behind += 1;
+                        }
+                        _ => return Ok(conn),
+                    },
                     Err(Error::Offline) => continue,
                     Err(Error::Banned) => {
                         banned += 1;
@@ -152,6 +233,15 @@ impl Replicas {
                 }
             }
 
+            // Wait for replicas to replay the client's writes.
+            if behind > 0 {
+                if started.elapsed() >= self.lsn_timeout {
+                    return Err(Error::ReplicaBehind);
+                }
+                sleep(LSN_POLL).await;
+                continue;
+            }
+
             // All replicas are banned, unban everyone.
             if banned == candidates.len() && !unbanned {
                 candidates
@@ -165,4 +255,73 @@ impl Replicas {
 
         Err(Error::AllReplicasDown)
     }
+
+    /// Replica replayed the WAL up to this position, according to the position
+    /// recorded by the healthcheck or a previous checkout.
+    /// `None` if the position is too old to tell.
+    pub(super) fn known_replayed(pool: &Pool, lsn: Lsn) -> Option<bool> {
+        let guard = pool.lock();
+        match (guard.lsn, guard.lsn_checked_at) {
+            (Some(replayed), _) if replayed >= lsn => Some(true),
+            (_, Some(checked_at)) if checked_at.elapsed() < LSN_POLL => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Ask the replica if it replayed the WAL up to this position
+    /// and record how far it got.
+    async fn replayed(conn: &mut Guard, pool: &Pool, lsn: Lsn) -> bool {
+        match Lsn::position(conn).await {
+            Ok(replayed) => {
+                pool.set_lsn(replayed);
+                let replayed = replayed.map(|replayed| replayed >= lsn).unwrap_or(false);
+                if !replayed {
+                    debug!("replica hasn't replayed {} yet [{}]", lsn, conn.addr());
+                }
+                replayed
+            }
+            Err(err) => {
+                error!("{} [{}]", err, conn.addr());
+                false
+            }
+        }
+    }
+}
+
+/// Shuffle candidates, giving those with a higher weight a higher chance
+/// of going first. Candidates with zero weight go last.
+pub(super) fn weighted_random<T>(candidates: Vec<(usize, T)>) -> Vec<T> {
+    let mut rng = rand::thread_rng();
+    let mut keyed = candidates
+        .into_iter()
+        .map(|(weight, candidate)| {
+            let key = if weight == 0 {
+                -1.0
+            } else {
+                rng.gen::<f64>().powf(1.0 / weight as f64)
+            };
+            (key, candidate)
+        })
+        .collect::<Vec<_>>();
+    keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+    keyed.into_iter().map(|(_, candidate)| candidate).collect()
+}
+
+/// Position of the candidate whose turn it is. Each candidate
+/// gets as many turns in a row as its weight.
+pub(super) fn weighted_round_robin(weights: &[usize], counter: usize) -> usize {
+    let total = weights.iter().sum::<usize>();
+    if total == 0 {
+        return counter % weights.len();
+    }
+
+    let mut position = counter % total;
+    for (index, weight) in weights.iter().enumerate() {
+        if position < *weight {
+            return index;
+        }
+        position -= weight;
+    }
+
+    0
 }
@@ -2,11 +2,17 @@ use tokio::time::Instant;
 
 use crate::net::messages::BackendKeyData;
 
+use super::Lsn;
+
 /// Connection request.
 #[derive(Clone, Debug, Copy)]
 pub struct Request {
     pub id: BackendKeyData,
     pub created_at: Instant,
+    /// Replicas must have replayed the WAL up to this position.
+    pub lsn: Option<Lsn>,
+    /// Read can be sent to the primary if replicas are down.
+    pub critical: bool,
 }
 
 impl Request {
@@ -14,8 +20,22 @@ impl Request {
         Self {
             id,
             created_at: Instant::now(),
+            lsn: None,
+            critical: false,
         }
     }
+
+    /// Request a replica that replayed the WAL up to this position.
+    pub fn lsn(mut self, lsn: Option<Lsn>) -> Self {
+        self.lsn = lsn;
+        self
+    }
+
+    /// Mark the read as critical.
+    pub fn critical(mut self, critical: bool) -> Self {
+        self.critical = critical;
+        self
+    }
 }
 
 impl Default for Request {
@@ -1,7 +1,11 @@
 //! A shard is a collection of replicas and a primary.
 
+use std::cmp::Reverse;
+
+use tracing::debug;
+
 use crate::{
-    config::{LoadBalancingStrategy, ReadWriteSplit, Role},
+    config::{LoadBalancingStrategy, ReadWriteSplit, ReplicaFallback, Role},
     net::messages::BackendKeyData,
 };
 
@@ -33,10 +37,22 @@ impl Shard {
         }
     }
 
+    /// The primary database. Databases configured with `role = "auto"`,
+    /// or promoted by failover, are used if they were detected to be the primary.
+    ///
+    /// If more than one claims to be the primary, the most recently detected one is used.
+    pub fn primary_pool(&self) -> Option<Pool> {
+        self.primary
+            .iter()
+            .chain(self.replicas.pools())
+            .filter(|pool| pool.role() == Role::Primary)
+            .min_by_key(|pool| Reverse(pool.role_changed_at()))
+            .cloned()
+    }
+
     /// Get a connection to the shard primary database.
     pub async fn primary(&self, request: &Request) -> Result<Guard, Error> {
-        self.primary
-            .as_ref()
+        self.primary_pool()
             .ok_or(Error::NoPrimary)?
             .get_forced(request)
             .await
@@ -44,22 +60,87 @@ impl Shard {
 
     /// Get a connection to a shard replica, if any.
     pub async fn replica(&self, request: &Request) -> Result<Guard, Error> {
-        if self.replicas.is_empty() {
-            self.primary
-                .as_ref()
-                .ok_or(Error::NoDatabases)?
-                .get(request)
-                .await
+        let primary = self.primary_pool();
+
+        if self.replicas.is_empty() || self.replicas.all_primary() {
+            primary.ok_or(Error::NoDatabases)?.get(request).await
         } else {
             use ReadWriteSplit::*;
 
-            let primary = match self.rw_split {
-                IncludePrimary => &self.primary,
+            let candidate = match self.rw_split {
+                IncludePrimary => &primary,
                 ExcludePrimary => &None,
             };
 
-            self.replicas.get(request, primary).await
+            match self.replicas.get(request, candidate).await {
+                // Replicas are too far behind, use the primary.
+                Err(Error::ReplicaBehind) => {
+                    primary
+                        .ok_or(Error::ReplicaBehind)?
+                        .get_forced(request)
+                        .await
+                }
+                Err(
+                    err @ (Error::AllReplicasDown
+                    | Error::ReplicaCheckoutTimeout
+                    | Error::NoReplicas),
+                ) if self.rw_split == ExcludePrimary => self.fallback(request, primary, err).await,
+                result => result,
+            }
+        }
+    }
+
+    /// Read from the primary when replicas are down, if the fallback policy allows it.
+    async fn fallback(
+        &self,
+        request: &Request,
+        primary: Option<Pool>,
+        err: Error,
+    ) -> Result<Guard, Error> {
+        let Some(primary) = primary else {
+            return Err(err);
+        };
+
+        let allowed = {
+            let guard = primary.lock();
+            let fallback = match guard.config.replica_fallback {
+                ReplicaFallback::Fail => false,
+                ReplicaFallback::Primary => true,
+                ReplicaFallback::Critical => request.critical,
+            };
+            // Leave some connections for writes.
+            let reserved = guard.config.max * guard.config.primary_reserve / 100;
+            fallback && guard.checked_out() + reserved < guard.config.max
+        };
+
+        if !allowed {
+            return Err(err);
         }
+
+        debug!(
+            "replicas unavailable, reading from primary: {} [{}]",
+            err,
+            primary.addr()
+        );
+
+        primary.get(request).await
+    }
+
+    /// Reads marked critical can fall back to the primary.
+    pub fn critical_reads(&self) -> bool {
+        self.primary_pool()
+            .map(|primary| primary.lock().config.replica_fallback == ReplicaFallback::Critical)
+            .unwrap_or(false)
+    }
+
+    /// The shard has a primary, or a database that could become one.
+    pub fn has_primary(&self) -> bool {
+        self.primary.is_some()
+            || self
+                .replicas
+                .pools()
+                .iter()
+                .any(|pool| pool.lock().config.role == Role::Auto)
     }
 
     /// Move pool connections from self to destination.
@@ -121,12 +202,7 @@ impl Shard {
         if let Some(primary) = self.primary.clone() {
             pools.push((Role::Primary, primary));
         }
-        pools.extend(
-            self.replicas
-                .pools()
-                .iter()
-                .map(|p| (Role::Replica, p.clone())),
-        );
+        pools.extend(self.replicas.pools().iter().map(|p| (p.role(), p.clone())));
 
         pools
     }
@@ -182,6 +258,56 @@ mod test {
         shard.shutdown();
     }
 
+    #[tokio::test]
+    async fn test_replica_fallback() {
+        crate::logger();
+
+        let primary = &Some(PoolConfig {
+            address: Address::new_test(),
+            config: Config {
+                max: 5,
+                replica_fallback: ReplicaFallback::Critical,
+                primary_reserve: 20,
+                ..Default::default()
+            },
+        });
+
+        let replicas = &[PoolConfig {
+            address: Address::new_test(),
+            config: Config::default(),
+        }];
+
+        let shard = Shard::new(
+            primary,
+            replicas,
+            LoadBalancingStrategy::Random,
+            ReadWriteSplit::ExcludePrimary,
+        );
+        shard.launch();
+        shard.replicas.pools[0].ban(Error::ManualBan);
+
+        let err = shard.replica(&Request::default()).await.unwrap_err();
+        assert_eq!(err, Error::AllReplicasDown);
+
+        let request = Request::default().critical(true);
+        let primary_id = shard.primary.as_ref().unwrap().id();
+        let mut conns = vec![];
+        for _ in 0..4 {
+            let conn = shard.replica(&request).await.unwrap();
+            assert_eq!(conn.pool.id(), primary_id);
+            conns.push(conn);
+        }
+
+        // The last connection is reserved for writes.
+        assert_eq!(
+            shard.replica(&request).await.unwrap_err(),
+            Error::AllReplicasDown
+        );
+        shard.primary(&request).await.unwrap();
+
+        shard.shutdown();
+    }
+
     #[tokio::test]
     async fn test_include_primary() {
         crate::logger();
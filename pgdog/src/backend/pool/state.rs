@@ -3,7 +3,7 @@ use std::time::Duration;
 use crate::config::PoolerMode;
 use tokio::time::Instant;
 
-use super::{Ban, Config, Pool, Stats};
+use super::{Ban, BanRecord, CircuitState, Config, Pool, Stats};
 
 /// Pool state.
 #[derive(Debug)]
@@ -28,6 +28,10 @@ pub struct State {
     pub ban: Option<Ban>,
     /// Pool is banned.
     pub banned: bool,
+    /// Circuit breaker state.
+    pub circuit: CircuitState,
+    /// Recent bans, oldest first.
+    pub bans: Vec<BanRecord>,
     /// Errors.
     pub errors: usize,
     /// Out of sync
@@ -40,6 +44,8 @@ pub struct State {
     pub maxwait: Duration,
     /// Pool mode
     pub pooler_mode: PoolerMode,
+    /// Replication lag.
+    pub replica_lag: Option<Duration>,
 }
 
 impl State {
@@ -58,6 +64,8 @@ impl State {
             waiting: guard.waiting.len(),
             ban: guard.ban,
             banned: guard.ban.is_some(),
+            circuit: guard.breaker.state(),
+            bans: guard.breaker.history().iter().copied().collect(),
             errors: guard.errors,
             out_of_sync: guard.out_of_sync,
             re_synced: guard.re_synced,
@@ -69,6 +77,7 @@ impl State {
                 .map(|req| now.duration_since(req.request.created_at))
                 .unwrap_or(Duration::ZERO),
             pooler_mode: guard.config().pooler_mode,
+            replica_lag: guard.replica_lag,
         }
     }
 }
@@ -8,6 +8,15 @@ use std::{
     time::Duration,
 };
 
+use tokio::time::Instant;
+
+/// Weight of the latest sample in the latency moving average.
+const ALPHA: f64 = 0.2;
+
+/// Latency moving average halves every time this passes
+/// without the pool being used.
+const DECAY: Duration = Duration::from_secs(10);
+
 #[derive(Debug, Clone, Default, Copy)]
 pub struct Counts {
     pub xact_count: usize,
@@ -22,6 +31,7 @@ pub struct Counts {
     pub bind_count: usize,
     pub rollbacks: usize,
     pub healthchecks: usize,
+    pub retries: usize,
 }
 
 impl Sub for Counts {
@@ -43,6 +53,7 @@ impl Sub for Counts {
             bind_count: self.parse_count.saturating_sub(rhs.bind_count),
             rollbacks: self.rollbacks.saturating_sub(rhs.rollbacks),
             healthchecks: self.healthchecks.saturating_add(rhs.healthchecks),
+            retries: self.retries.saturating_sub(rhs.retries),
         }
     }
 }
@@ -64,6 +75,7 @@ impl Div<usize> for Counts {
             bind_count: self.parse_count.saturating_div(rhs),
             rollbacks: self.rollbacks.saturating_div(rhs),
             healthchecks: self.healthchecks.saturating_div(rhs),
+            retries: self.retries.saturating_div(rhs),
         }
     }
 }
@@ -85,6 +97,7 @@ impl Add<BackendCounts> for Counts {
             bind_count: self.bind_count + rhs.bind,
             rollbacks: self.rollbacks + rhs.rollbacks,
             healthchecks: self.healthchecks + rhs.healthchecks,
+            retries: self.retries + rhs.retries,
         }
     }
 }
@@ -119,6 +132,7 @@ impl Add for Counts {
             bind_count: self.parse_count.saturating_add(rhs.bind_count),
             rollbacks: self.rollbacks.saturating_add(rhs.rollbacks),
             healthchecks: self.healthchecks.saturating_add(rhs.healthchecks),
+            retries: self.retries.saturating_add(rhs.retries),
         }
     }
 }
@@ -130,6 +144,12 @@ pub struct Stats {
     last_counts: Counts,
     // Average counts.
     pub averages: Counts,
+    // Moving average of checkout wait time.
+    wait_latency: Duration,
+    // Moving average of query time.
+    query_latency: Duration,
+    // When the moving averages were last updated.
+    latency_updated: Option<Instant>,
 }
 
 impl Stats {
@@ -141,4 +161,85 @@ impl Stats {
             self.last_counts = self.counts;
         }
     }
+
+    /// Record how long a client waited for a connection.
+    pub fn wait(&mut self, wait: Duration, now: Instant) {
+        self.counts.wait_time += wait;
+        self.counts.server_assignment_count += 1;
+        self.wait_latency = ewma(self.latency_decayed(self.wait_latency, now), wait);
+        self.query_latency = self.latency_decayed(self.query_latency, now);
+        self.latency_updated = Some(now);
+    }
+
+    /// Record stats of a connection checked back into the pool.
+    pub fn check_in(&mut self, stats: BackendCounts, now: Instant) {
+        self.counts = self.counts + stats;
+
+        if let Some(query_time) = stats.query_time.checked_div(stats.queries as u32) {
+            self.query_latency = ewma(self.latency_decayed(self.query_latency, now), query_time);
+            self.wait_latency = self.latency_decayed(self.wait_latency, now);
+            self.latency_updated = Some(now);
+        }
+    }
+
+    /// Moving average of wait and query latency.
+    ///
+    /// It decays while the pool isn't used, so pools
+    /// that were slow eventually get traffic again.
+    pub fn latency(&self, now: Instant) -> Duration {
+        self.latency_decayed(self.wait_latency + self.query_latency, now)
+    }
+
+    fn latency_decayed(&self, latency: Duration, now: Instant) -> Duration {
+        let idle = self
+            .latency_updated
+            .map(|updated| now.saturating_duration_since(updated))
+            .unwrap_or_default();
+
+        if idle.is_zero() {
+            latency
+        } else {
+            latency.mul_f64(0.5_f64.powf(idle.as_secs_f64() / DECAY.as_secs_f64()))
+        }
+    }
+}
+
+/// Exponentially weighted moving average.
+fn ewma(average: Duration, sample: Duration) -> Duration {
+    average.mul_f64(1.0 - ALPHA) + sample.mul_f64(ALPHA)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_latency() {
+        let now = Instant::now();
+        let mut stats = Stats::default();
+        assert_eq!(stats.latency(now), Duration::ZERO);
+
+        for _ in 0..100 {
+            stats.wait(Duration::from_millis(100), now);
+        }
+        let latency = stats.latency(now);
+        assert!(latency > Duration::from_millis(99) && latency <= Duration::from_millis(100));
+
+        let queries = BackendCounts {
+            queries: 2,
+            query_time: Duration::from_millis(100),
+            ..Default::default()
+        };
+        stats.check_in(queries, now);
+        let with_queries = stats.latency(now) - latency;
+        assert!(
+            with_queries > Duration::from_micros(9_999)
+                && with_queries < Duration::from_micros(10_001)
+        );
+        assert_eq!(stats.counts.query_count, 2);
+
+        // Pool wasn't used for a while.
+        let later = stats.latency(now + DECAY);
+        assert!(later < stats.latency(now).mul_f64(0.51));
+    }
 }
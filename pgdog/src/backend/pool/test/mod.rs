@@ -137,6 +137,82 @@ async fn test_bans() {
     assert!(conn.is_err());
 }
 
+#[tokio::test]
+async fn test_replica_lag() {
+    let pool = pool();
+    let mut config = *pool.lock().config();
+    config.role = crate::config::Role::Replica;
+    config.max_replica_lag = Some(Duration::from_millis(1));
+    pool.update_config(config);
+
+    // The database isn't in recovery, so it's not lagging.
+    let mut conn = pool.get(&Request::default()).await.unwrap();
+    assert!(Monitor::replica_lag(&mut conn, &pool).await.unwrap());
+    drop(conn);
+
+    assert_eq!(pool.state().replica_lag, Some(Duration::ZERO));
+    assert!(!pool.banned());
+
+    // Too far behind.
+    assert!(!Monitor::record_lag(&pool, Some(Duration::from_millis(5))));
+    assert_eq!(pool.state().replica_lag, Some(Duration::from_millis(5)));
+    assert!(pool.banned());
+
+    // Caught up, unbanned by the next healthcheck.
+    assert!(Monitor::record_lag(&pool, Some(Duration::ZERO)));
+    assert!(pool.lock().maybe_unban());
+    assert!(!pool.banned());
+}
+
+#[tokio::test]
+async fn test_record_lsn() {
+    let pool = pool();
+    let mut conn = pool.get(&Request::default()).await.unwrap();
+
+    Monitor::record_lsn(&mut conn, &pool).await;
+    assert!(pool.lsn().is_some());
+    assert!(pool.lock().lsn_checked_at.is_some());
+}
+
+#[tokio::test]
+async fn test_detect_role() {
+    let pool = pool();
+    let mut config = *pool.lock().config();
+    config.role = crate::config::Role::Auto;
+    pool.update_config(config);
+    assert_eq!(pool.role(), crate::config::Role::Auto);
+
+    let mut conn = pool.get(&Request::default()).await.unwrap();
+    Monitor::detect_role(&mut conn, &pool).await;
+    drop(conn);
+
+    assert_eq!(pool.role(), crate::config::Role::Primary);
+
+    // A banned database isn't known to be the primary anymore.
+    pool.ban(Error::CheckoutTimeout);
+    assert_eq!(pool.role(), crate::config::Role::Auto);
+}
+
+#[tokio::test]
+async fn test_primary_pool() {
+    let primary = pool();
+    let replica = pool();
+    let mut replicas = Replicas::new(&[], crate::config::LoadBalancingStrategy::Random);
+    replicas.pools.push(replica.clone());
+    let shard = Shard {
+        primary: Some(primary.clone()),
+        replicas,
+        ..Default::default()
+    };
+
+    // The configured primary.
+    assert_eq!(shard.primary_pool().unwrap().id(), primary.id());
+
+    // The most recently detected primary.
+    replica.set_role(crate::config::Role::Primary);
+    assert_eq!(shard.primary_pool().unwrap().id(), replica.id());
+}
+
 #[tokio::test]
 async fn test_offline() {
     let pool = pool();
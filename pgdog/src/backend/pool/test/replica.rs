@@ -1,3 +1,4 @@
+use crate::backend::pool::replicas::{weighted_random, weighted_round_robin};
 use crate::config::LoadBalancingStrategy;
 
 // use super::pool;
@@ -5,6 +6,10 @@ use super::*;
 use tokio::spawn;
 
 fn replicas() -> Replicas {
+    replicas_with(LoadBalancingStrategy::Random)
+}
+
+fn replicas_with(lb_strategy: LoadBalancingStrategy) -> Replicas {
     let one = PoolConfig {
         address: Address {
             host: "127.0.0.1".into(),
@@ -21,7 +26,7 @@ fn replicas() -> Replicas {
     };
     let mut two = one.clone();
     two.address.host = "localhost".into();
-    let replicas = Replicas::new(&[one, two], LoadBalancingStrategy::Random);
+    let replicas = Replicas::new(&[one, two], lb_strategy);
     replicas.pools().iter().for_each(|p| p.launch());
     replicas
 }
@@ -62,3 +67,78 @@ async fn test_replicas() {
     replicas.get(&Request::default(), &None).await.unwrap();
     assert!(replicas.pools.iter().all(|pool| !pool.banned()));
 }
+
+#[tokio::test]
+async fn test_replicas_lsn() {
+    let replicas = replicas();
+    let mut conn = replicas.get(&Request::default(), &None).await.unwrap();
+    let lsn = Lsn::current(&mut conn).await.unwrap().unwrap();
+    drop(conn);
+
+    // Not in recovery, so it's always caught up.
+    let request = Request::default().lsn(Some(lsn));
+    replicas.get(&request, &None).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_replicas_known_lsn() {
+    let replicas = replicas();
+    let pool = &replicas.pools()[0];
+    let lsn: Lsn = "0/16B3748".parse().unwrap();
+    let ahead: Lsn = "16/B374D848".parse().unwrap();
+
+    assert_eq!(Replicas::known_replayed(pool, lsn), None);
+
+    pool.set_lsn(Some(lsn));
+    assert_eq!(Replicas::known_replayed(pool, lsn), Some(true));
+    assert_eq!(Replicas::known_replayed(pool, ahead), Some(false));
+
+    // Too old to tell if the replica is still behind.
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(Replicas::known_replayed(pool, ahead), None);
+    assert_eq!(Replicas::known_replayed(pool, lsn), Some(true));
+}
+
+#[test]
+fn test_weighted_round_robin() {
+    let weights = [3, 1, 0];
+    let turns = (0..8)
+        .map(|counter| weighted_round_robin(&weights, counter))
+        .collect::<Vec<_>>();
+    assert_eq!(turns, vec![0, 0, 0, 1, 0, 0, 0, 1]);
+
+    // All replicas drained, fall back to round robin.
+    assert_eq!(weighted_round_robin(&[0, 0], 3), 1);
+}
+
+#[test]
+fn test_weighted_random() {
+    let mut first = [0; 3];
+    for _ in 0..10_000 {
+        let shuffled = weighted_random(vec![(1, 0), (9, 1), (0, 2)]);
+        assert_eq!(shuffled.len(), 3);
+        assert_eq!(shuffled[2], 2);
+        first[shuffled[0]] += 1;
+    }
+    assert!(first[1] > first[0] * 5);
+    assert_eq!(first[2], 0);
+}
+
+#[tokio::test]
+async fn test_replicas_least_latency() {
+    let replicas = replicas_with(LoadBalancingStrategy::LeastLatency);
+
+    // First replica is slow.
+    for _ in 0..100 {
+        replicas.pools[0]
+            .lock()
+            .stats
+            .wait(Duration::from_secs(1), tokio::time::Instant::now());
+    }
+    assert!(replicas.pools[0].latency() > replicas.pools[1].latency());
+
+    for _ in 0..100 {
+        let conn = replicas.get(&Request::default(), &None).await.unwrap();
+        assert_eq!(conn.addr(), replicas.pools[1].addr());
+    }
+}
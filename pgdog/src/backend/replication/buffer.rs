@@ -79,14 +79,17 @@ impl Buffer {
                     }
                     XLogPayload::Update(update) => {
                         let (table, columns) = self.sharding_key(update.oid)?;
-                        let column = self
+                        let key = self
                             .replication_config
                             .sharded_column(table, &columns)
-                            .and_then(|column| update.column(column.position))
-                            .and_then(|column| column.as_str());
-                        if let Some(column) = column {
+                            .and_then(|column| {
+                                column
+                                    .key(update.column(column.position)?.as_str()?)
+                                    .map(|key| key.into_owned())
+                            });
+                        if let Some(key) = key {
                             let shard =
-                                shard_str(column, &self.sharding_schema, &vec![], CENTROID_PROBES);
+                                shard_str(&key, &self.sharding_schema, &vec![], CENTROID_PROBES);
                             if self.shard == shard {
                                 self.message = Some(xlog_data);
                                 return self.flush();
@@ -98,14 +101,17 @@ impl Buffer {
                     }
                     XLogPayload::Insert(insert) => {
                         let (table, columns) = self.sharding_key(insert.oid)?;
-                        let column = self
+                        let key = self
                             .replication_config
                             .sharded_column(table, &columns)
-                            .and_then(|column| insert.column(column.position))
-                            .and_then(|column| column.as_str());
-                        if let Some(column) = column {
+                            .and_then(|column| {
+                                column
+                                    .key(insert.column(column.position)?.as_str()?)
+                                    .map(|key| key.into_owned())
+                            });
+                        if let Some(key) = key {
                             let shard =
-                                shard_str(column, &self.sharding_schema, &vec![], CENTROID_PROBES);
+                                shard_str(&key, &self.sharding_schema, &vec![], CENTROID_PROBES);
                             if self.shard == shard {
                                 self.message = Some(xlog_data);
                                 return self.flush();
@@ -1,9 +1,10 @@
 //! Tables sharded in the database.
 use crate::{
-    config::{DataType, ShardedTable},
+    config::{json_keys, DataType, ShardedTable},
+    frontend::router::sharding::json,
     net::messages::Vector,
 };
-use std::{collections::HashSet, sync::Arc};
+use std::{borrow::Cow, collections::HashSet, sync::Arc};
 
 #[derive(Debug, Clone, Default)]
 pub struct ShardedTables {
@@ -42,6 +43,24 @@ impl ShardedTables {
             .find(|t| t.name.as_deref() == Some(name))
     }
 
+    /// Rows of both tables with the same sharding key are stored on the same shard.
+    ///
+    /// Tables are co-located when they belong to the same database and are
+    /// sharded on a key with the same data type, using the same hash function,
+    /// centroids and JSON path. Tables sharded by vector similarity are never co-located.
+    pub fn co_located(&self, a: &str, b: &str) -> bool {
+        match (self.table(a), self.table(b)) {
+            (Some(a), Some(b)) => {
+                a.database == b.database
+                    && a.data_type == b.data_type
+                    && a.data_type != DataType::Vector
+                    && a.centroids == b.centroids
+                    && a.json_path == b.json_path
+            }
+            _ => false,
+        }
+    }
+
     /// Find out which column (if any) is sharded in the given table.
     pub fn sharded_column(&self, table: &str, columns: &[&str]) -> Option<ShardedColumn> {
         let with_names = self
@@ -60,6 +79,7 @@ impl ShardedTables {
                     position,
                     centroids: sharded_table.centroids.clone(),
                     centroid_probes: sharded_table.centroid_probes,
+                    json_path: sharded_table.json_path.clone(),
                 })
         };
 
@@ -91,6 +111,7 @@ pub struct ShardedColumn {
     pub position: usize,
     pub centroids: Vec<Vector>,
     pub centroid_probes: usize,
+    pub json_path: Option<String>,
 }
 
 impl ShardedColumn {
@@ -103,6 +124,16 @@ impl ShardedColumn {
                 position: index,
                 centroids: table.centroids.clone(),
                 centroid_probes: table.centroid_probes,
+                json_path: table.json_path.clone(),
             })
     }
+
+    /// Get the sharding key from the column value,
+    /// extracting it from JSON if needed.
+    pub fn key<'a>(&self, value: &'a str) -> Option<Cow<'a, str>> {
+        match self.json_path.as_deref() {
+            Some(path) => json::extract(value.as_bytes(), &json_keys(Some(path))).map(Cow::Owned),
+            None => Some(Cow::Borrowed(value)),
+        }
+    }
 }
@@ -0,0 +1,76 @@
+//! Functions defined in the database.
+use super::Error;
+use crate::{backend::Server, net::messages::DataRow};
+
+/// Get all user-defined functions.
+static FUNCTIONS: &str = include_str!("functions.sql");
+
+/// Function volatility, as declared in `pg_proc.provolatile`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Volatility {
+    Immutable,
+    Stable,
+    /// Function can modify the database.
+    #[default]
+    Volatile,
+}
+
+impl From<&str> for Volatility {
+    fn from(value: &str) -> Self {
+        match value {
+            "i" => Self::Immutable,
+            "s" => Self::Stable,
+            _ => Self::Volatile,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub schema: String,
+    pub name: String,
+    pub volatility: Volatility,
+}
+
+impl Function {
+    /// Load all user-defined functions from server.
+    pub async fn load(server: &mut Server) -> Result<Vec<Function>, Error> {
+        Ok(server.fetch_all(FUNCTIONS).await?)
+    }
+}
+
+impl From<DataRow> for Function {
+    fn from(value: DataRow) -> Self {
+        Self {
+            schema: value.get_text(0).unwrap_or_default(),
+            name: value.get_text(1).unwrap_or_default(),
+            volatility: Volatility::from(value.get_text(2).unwrap_or_default().as_str()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::backend::pool::test::pool;
+    use crate::backend::pool::Request;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_load_functions() {
+        let pool = pool();
+        let mut conn = pool.get(&Request::default()).await.unwrap();
+        conn.execute("CREATE OR REPLACE FUNCTION pgdog_test_volatile() RETURNS INT AS 'SELECT 1' LANGUAGE sql VOLATILE")
+            .await
+            .unwrap();
+        let functions = Function::load(&mut conn).await.unwrap();
+        let function = functions
+            .iter()
+            .find(|function| function.name == "pgdog_test_volatile")
+            .unwrap();
+        assert_eq!(function.volatility, Volatility::Volatile);
+        assert!(functions
+            .iter()
+            .all(|function| function.schema != "pg_catalog"));
+    }
+}
@@ -0,0 +1,14 @@
+SELECT n.nspname                                                 AS "schema",
+       p.proname                                                  AS "name",
+       p.provolatile::text                                        AS "volatility"
+FROM   pg_catalog.pg_proc p
+       LEFT JOIN pg_catalog.pg_namespace n
+              ON n.oid = p.pronamespace
+WHERE  NOT EXISTS (SELECT 1
+                   FROM   pg_catalog.pg_aggregate a
+                   WHERE  a.aggfnoid = p.oid)
+       AND n.nspname <> 'pg_catalog'
+       AND n.nspname !~ '^pg_toast'
+       AND n.nspname <> 'information_schema'
+ORDER  BY 1,
+          2;
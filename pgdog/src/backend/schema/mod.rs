@@ -1,11 +1,13 @@
 //! Schema operations.
 pub mod columns;
+pub mod function;
 pub mod relation;
 
 use std::sync::Arc;
 use std::{collections::HashMap, ops::Deref};
-use tracing::debug;
+use tracing::{debug, warn};
 
+pub use function::{Function, Volatility};
 pub use relation::Relation;
 
 use super::{pool::Request, Cluster, Error, Server};
@@ -16,6 +18,7 @@ static SETUP: &str = include_str!("setup.sql");
 struct Inner {
     search_path: Vec<String>,
     relations: HashMap<(String, String), Relation>,
+    functions: HashMap<String, Volatility>,
 }
 
 /// Load schema from database.
@@ -47,9 +50,19 @@ impl Schema {
             .map(|p| p.trim().replace("\"", ""))
             .collect();
 
+        // Volatility of functions is unknown if they can't be loaded.
+        let functions = match Function::load(server).await {
+            Ok(functions) => Self::functions(functions),
+            Err(err) => {
+                warn!("couldn't load functions: {} [{}]", err, server.addr());
+                HashMap::new()
+            }
+        };
+
         let inner = Inner {
             search_path,
             relations,
+            functions,
         };
 
         Ok(Self {
@@ -156,6 +169,29 @@ impl Schema {
             .collect()
     }
 
+    /// Volatility of a function, by name. Overloaded functions,
+    /// or functions with the same name in different schemas,
+    /// are as volatile as the most volatile of them.
+    pub fn volatility(&self, name: &str) -> Option<Volatility> {
+        self.inner.functions.get(name).copied()
+    }
+
+    /// Number of loaded functions.
+    pub fn functions_len(&self) -> usize {
+        self.inner.functions.len()
+    }
+
+    fn functions(functions: Vec<Function>) -> HashMap<String, Volatility> {
+        let mut result: HashMap<String, Volatility> = HashMap::new();
+        for function in functions {
+            result
+                .entry(function.name)
+                .and_modify(|volatility| *volatility = function.volatility.max(*volatility))
+                .or_insert(function.volatility);
+        }
+        result
+    }
+
     /// Get search path components.
     pub fn search_path(&self) -> &[String] {
         &self.inner.search_path
@@ -172,10 +208,51 @@ impl Deref for Schema {
 
 #[cfg(test)]
 mod test {
+    use std::{collections::HashMap, sync::Arc};
+
     use crate::backend::pool::Request;
 
     use super::super::pool::test::pool;
-    use super::Schema;
+    use super::{Function, Inner, Schema, Volatility};
+
+    impl Schema {
+        pub fn new_test(functions: &[(&str, Volatility)]) -> Self {
+            let functions = functions
+                .iter()
+                .map(|(name, volatility)| Function {
+                    schema: "public".into(),
+                    name: name.to_string(),
+                    volatility: *volatility,
+                })
+                .collect();
+
+            Self {
+                inner: Arc::new(Inner {
+                    functions: Self::functions(functions),
+                    search_path: vec![],
+                    relations: HashMap::new(),
+                }),
+            }
+        }
+    }
+
+    #[test]
+    fn test_volatility() {
+        let schema = Schema::new_test(&[
+            ("create_order", Volatility::Volatile),
+            ("lookup", Volatility::Stable),
+            ("lookup", Volatility::Immutable),
+            ("overloaded", Volatility::Immutable),
+            ("overloaded", Volatility::Volatile),
+        ]);
+        assert_eq!(
+            schema.volatility("create_order"),
+            Some(Volatility::Volatile)
+        );
+        assert_eq!(schema.volatility("lookup"), Some(Volatility::Stable));
+        assert_eq!(schema.volatility("overloaded"), Some(Volatility::Volatile));
+        assert_eq!(schema.volatility("random"), None);
+    }
 
     #[tokio::test]
     async fn test_schema() {
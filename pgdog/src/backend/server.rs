@@ -20,8 +20,9 @@ use crate::{
     frontend::Buffer,
     net::{
         messages::{
-            hello::SslReply, Authentication, BackendKeyData, ErrorResponse, FromBytes, Message,
-            ParameterStatus, Password, Protocol, Query, ReadyForQuery, Startup, Terminate, ToBytes,
+            hello::SslReply, Authentication, BackendKeyData, Bind, Describe, ErrorResponse,
+            Execute, FromBytes, Message, Parameter as BindParameter, ParameterStatus, Parse,
+            Password, Protocol, Query, ReadyForQuery, Startup, Terminate, ToBytes,
         },
         Parameter, Sync,
     },
@@ -536,6 +537,51 @@ impl Server {
         }
     }
 
+    /// Execute a query with parameters using the extended protocol
+    /// and return the result, including the row description.
+    pub async fn execute_params(
+        &mut self,
+        query: &str,
+        params: &[BindParameter],
+    ) -> Result<Vec<Message>, Error> {
+        if !self.in_sync() {
+            return Err(Error::NotInSync);
+        }
+
+        #[cfg(debug_assertions)]
+        debug!("{} [{}]", query, self.addr());
+
+        let messages: Vec<ProtocolMessage> = vec![
+            Parse::new_anonymous(query).into(),
+            Bind::new_params("", params).into(),
+            Describe::new_portal("").into(),
+            Execute::new().into(),
+            Sync.into(),
+        ];
+
+        self.send(&messages.into()).await?;
+
+        let mut messages = vec![];
+        let mut error = None;
+
+        // Read until ReadyForQuery, even if there is an error,
+        // so the connection stays in sync.
+        loop {
+            let message = self.read().await?;
+            match message.code() {
+                'Z' => break,
+                'E' => error = Some(ErrorResponse::from_bytes(message.to_bytes()?)?),
+                _ => messages.push(message),
+            }
+        }
+
+        if let Some(error) = error {
+            Err(Error::ExecutionError(Box::new(error)))
+        } else {
+            Ok(messages)
+        }
+    }
+
     /// Execute a query and return all rows.
     pub async fn fetch_all<T: From<DataRow>>(
         &mut self,
@@ -62,6 +62,7 @@ pub struct Counts {
     pub parse: usize,
     pub bind: usize,
     pub healthchecks: usize,
+    pub retries: usize,
 }
 
 impl Add for Counts {
@@ -83,6 +84,7 @@ impl Add for Counts {
             parse: self.parse.saturating_add(rhs.parse),
             bind: self.bind.saturating_add(rhs.bind),
             healthchecks: self.healthchecks.saturating_add(rhs.healthchecks),
+            retries: self.retries.saturating_add(rhs.retries),
         }
     }
 }
@@ -252,6 +254,13 @@ impl Stats {
         self.update();
     }
 
+    /// Track reads retried on another server after this one failed.
+    pub fn retry(&mut self) {
+        self.total.retries += 1;
+        self.last_checkout.retries += 1;
+        self.update();
+    }
+
     /// Update server stats globally.
     pub fn update(&self) {
         update(self.id, *self)
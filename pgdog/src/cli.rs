@@ -1,8 +1,14 @@
 use std::path::PathBuf;
 
 use clap::{Parser, Subcommand};
+use serde::Deserialize;
+use serde_json::Value;
 use std::fs::read_to_string;
 
+use crate::backend::databases::from_config;
+use crate::config::ConfigAndUsers;
+use crate::frontend::router::RouteInfo;
+
 /// pgDog is a PostgreSQL pooler, proxy, load balancer and
 /// query router.
 #[derive(Parser, Debug)]
@@ -47,6 +53,21 @@ pub enum Commands {
     },
 
     Schema,
+
+    /// Print the route the query parser picks for each query in a file,
+    /// without connecting to any databases.
+    Route {
+        /// Name of the database in pgdog.toml.
+        #[arg(short, long)]
+        database: String,
+        /// User connecting to the database. Default: first user configured for it.
+        #[arg(short, long)]
+        user: Option<String>,
+        /// File with one query per line. A line can also be a JSON object,
+        /// e.g. `{"query": "SELECT * FROM users WHERE id = $1", "params": [1]}`.
+        #[arg(short, long)]
+        path: PathBuf,
+    },
 }
 
 /// Fingerprint some queries.
@@ -77,3 +98,124 @@ fingerprint = "{}" #[{}]"#,
 
     Ok(())
 }
+
+/// Query read from a file passed to the `route` command.
+#[derive(Deserialize, Debug, PartialEq)]
+struct RouteQuery {
+    query: String,
+    #[serde(default)]
+    params: Vec<Value>,
+}
+
+impl RouteQuery {
+    /// Parse a line of the file. Returns `None` for empty lines and comments.
+    fn parse(line: &str) -> Result<Option<Self>, serde_json::Error> {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with("--") {
+            Ok(None)
+        } else if line.starts_with('{') {
+            Ok(Some(serde_json::from_str(line)?))
+        } else {
+            Ok(Some(Self {
+                query: line.trim_end_matches(';').to_owned(),
+                params: vec![],
+            }))
+        }
+    }
+
+    /// Parameters in text format, `None` for `NULL`.
+    fn params(&self) -> Vec<Option<String>> {
+        self.params
+            .iter()
+            .map(|param| match param {
+                Value::Null => None,
+                Value::String(string) => Some(string.clone()),
+                value => Some(value.to_string()),
+            })
+            .collect()
+    }
+}
+
+/// Route queries using the loaded configuration.
+///
+/// Returns `false` if any of the queries couldn't be read or routed.
+pub fn route(
+    config: &ConfigAndUsers,
+    database: &str,
+    user: Option<&str>,
+    path: PathBuf,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let databases = from_config(config);
+    let cluster = databases
+        .all()
+        .iter()
+        .filter(|(u, _)| u.database == database)
+        .filter(|(u, _)| user.map(|user| u.user == user).unwrap_or(true))
+        .min_by(|(a, _), (b, _)| a.user.cmp(&b.user))
+        .map(|(_, cluster)| cluster.clone())
+        .ok_or_else(|| format!("database \"{}\" not found in configuration", database))?;
+
+    let queries = read_to_string(path)?;
+    let mut ok = true;
+    for (number, line) in queries.lines().enumerate() {
+        let query = match RouteQuery::parse(line) {
+            Ok(Some(query)) => query,
+            Ok(None) => continue,
+            Err(err) => {
+                println!("line {}: {}\n", number + 1, err);
+                ok = false;
+                continue;
+            }
+        };
+
+        println!("{}", query.query);
+        match RouteInfo::new(&cluster, &query.query, &query.params()) {
+            Ok(info) => {
+                println!("  -> {}", info.route);
+                if let Some((key, source)) = info.key {
+                    println!("  -> sharding key: {} ({})", key, source);
+                }
+            }
+            Err(err) => {
+                println!("  -> error: {}", err);
+                ok = false;
+            }
+        }
+        println!();
+    }
+
+    Ok(ok)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_route_query_parse() {
+        assert_eq!(RouteQuery::parse("  ").unwrap(), None);
+        assert_eq!(RouteQuery::parse("-- comment").unwrap(), None);
+
+        let query = RouteQuery::parse("SELECT 1;").unwrap().unwrap();
+        assert_eq!(query.query, "SELECT 1");
+        assert!(query.params().is_empty());
+
+        let query = RouteQuery::parse(
+            r#"{"query": "SELECT * FROM sharded WHERE id = $1 AND email = $2", "params": [5, "test"]}"#,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            query.params(),
+            vec![Some("5".to_string()), Some("test".to_string())]
+        );
+
+        let query = RouteQuery::parse(r#"{"query": "SELECT $1", "params": [null]}"#)
+            .unwrap()
+            .unwrap();
+        assert_eq!(query.params(), vec![None]);
+
+        assert!(RouteQuery::parse("{not json").is_err());
+    }
+}
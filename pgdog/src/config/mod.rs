@@ -300,6 +300,10 @@ pub struct General {
     /// Maximum duration of a ban.
     #[serde(default = "General::ban_timeout")]
     pub ban_timeout: u64,
+    /// Duration of the first ban, doubled every time the pool fails
+    /// again right after a ban, up to `ban_timeout`.
+    #[serde(default = "General::ban_initial_timeout")]
+    pub ban_initial_timeout: u64,
     /// Rollback timeout.
     #[serde(default = "General::rollback_timeout")]
     pub rollback_timeout: u64,
@@ -356,6 +360,50 @@ pub struct General {
     pub mirror_queue: usize,
     #[serde(default)]
     pub auth_type: AuthType,
+    /// What to do with UPDATE/DELETE sent to all shards.
+    #[serde(default)]
+    pub all_shards_writes: AllShardsWrites,
+    /// Maximum number of rows a multi-shard query can return.
+    pub multi_shard_row_limit: Option<usize>,
+    /// Volatile functions that are safe to call on replicas.
+    #[serde(default)]
+    pub read_functions: Vec<String>,
+    /// Functions that must be called on the primary, regardless of volatility.
+    #[serde(default)]
+    pub write_functions: Vec<String>,
+    /// Replicas lagging behind the primary by more than this are banned (ms).
+    pub max_replica_lag: Option<u64>,
+    /// Send reads only to replicas that replayed the client's writes.
+    #[serde(default)]
+    pub read_your_writes: bool,
+    /// How long to wait for replicas to replay the client's writes
+    /// before sending the read to the primary (ms).
+    #[serde(default = "General::read_your_writes_timeout")]
+    pub read_your_writes_timeout: u64,
+    /// Promote a replica when the primary is down.
+    #[serde(default)]
+    pub failover: bool,
+    /// How long the primary has to be down before failing over (ms).
+    #[serde(default = "General::failover_threshold")]
+    pub failover_threshold: u64,
+    /// Number of pgdog nodes voting on failover.
+    /// A majority of them has to agree the primary is down.
+    #[serde(default = "General::failover_nodes")]
+    pub failover_nodes: usize,
+    /// How many times to retry a read on another server if the first one fails.
+    #[serde(default)]
+    pub read_retries: usize,
+    /// What to do with reads when all replicas are down.
+    #[serde(default)]
+    pub replica_fallback: ReplicaFallback,
+    /// Percentage of the primary pool reserved for writes
+    /// when reads fall back to the primary.
+    #[serde(default = "General::primary_reserve")]
+    pub primary_reserve: usize,
+    /// How long CUTOVER waits for transactions to finish
+    /// and for replication to catch up (ms).
+    #[serde(default = "General::default_cutover_timeout")]
+    pub cutover_timeout: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -417,6 +465,18 @@ pub enum ReadWriteStrategy {
     Aggressive,
 }
 
+/// UPDATE and DELETE statements on sharded tables
+/// that would be sent to all shards.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum AllShardsWrites {
+    #[default]
+    Allow,
+    /// Require a `/* pgdog_all_shards */` comment.
+    Comment,
+    Reject,
+}
+
 impl Default for General {
     fn default() -> Self {
         Self {
@@ -430,6 +490,7 @@ impl Default for General {
             idle_healthcheck_interval: Self::idle_healthcheck_interval(),
             idle_healthcheck_delay: Self::idle_healthcheck_delay(),
             ban_timeout: Self::ban_timeout(),
+            ban_initial_timeout: Self::ban_initial_timeout(),
             rollback_timeout: Self::rollback_timeout(),
             load_balancing_strategy: Self::load_balancing_strategy(),
             read_write_strategy: ReadWriteStrategy::default(),
@@ -451,6 +512,20 @@ impl Default for General {
             idle_timeout: Self::idle_timeout(),
             mirror_queue: Self::mirror_queue(),
             auth_type: AuthType::default(),
+            all_shards_writes: AllShardsWrites::default(),
+            multi_shard_row_limit: None,
+            read_functions: vec![],
+            write_functions: vec![],
+            max_replica_lag: None,
+            read_your_writes: false,
+            read_your_writes_timeout: Self::read_your_writes_timeout(),
+            failover: false,
+            failover_threshold: Self::failover_threshold(),
+            failover_nodes: Self::failover_nodes(),
+            read_retries: 0,
+            replica_fallback: ReplicaFallback::default(),
+            primary_reserve: Self::primary_reserve(),
+            cutover_timeout: Self::default_cutover_timeout(),
         }
     }
 }
@@ -468,6 +543,26 @@ impl General {
         2
     }
 
+    fn read_your_writes_timeout() -> u64 {
+        1_000
+    }
+
+    fn failover_threshold() -> u64 {
+        30_000
+    }
+
+    fn failover_nodes() -> usize {
+        1
+    }
+
+    fn primary_reserve() -> usize {
+        20
+    }
+
+    fn default_cutover_timeout() -> u64 {
+        30_000
+    }
+
     fn default_pool_size() -> usize {
         10
     }
@@ -492,6 +587,10 @@ impl General {
         Duration::from_secs(300).as_millis() as u64
     }
 
+    fn ban_initial_timeout() -> u64 {
+        Duration::from_secs(5).as_millis() as u64
+    }
+
     fn rollback_timeout() -> u64 {
         5_000
     }
@@ -537,6 +636,11 @@ impl General {
         Duration::from_millis(self.shutdown_timeout)
     }
 
+    /// Get cutover timeout as a duration.
+    pub fn cutover_timeout(&self) -> Duration {
+        Duration::from_millis(self.cutover_timeout)
+    }
+
     /// Get TLS config, if any.
     pub fn tls(&self) -> Option<(&PathBuf, &PathBuf)> {
         if let Some(cert) = &self.tls_certificate {
@@ -581,6 +685,22 @@ pub enum LoadBalancingStrategy {
     Random,
     RoundRobin,
     LeastActiveConnections,
+    WeightedRandom,
+    WeightedRoundRobin,
+    LeastLatency,
+}
+
+/// What to do with reads when all replicas are down.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Ord, PartialOrd, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplicaFallback {
+    /// Return an error to the client.
+    #[default]
+    Fail,
+    /// Send reads to the primary.
+    Primary,
+    /// Send only reads marked with `/* pgdog_critical */` to the primary.
+    Critical,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Copy)]
@@ -631,6 +751,17 @@ pub struct Database {
     pub mirror_of: Option<String>,
     /// Read-only mode.
     pub read_only: Option<bool>,
+    /// Reject multi-shard queries that can't be merged correctly.
+    #[serde(default)]
+    pub strict: bool,
+    /// Load balancing weight, used by the weighted strategies. Defaults to 1.
+    pub weight: Option<usize>,
+    /// Read retries, overriding `read_retries`.
+    pub read_retries: Option<usize>,
+    /// Replica fallback policy, overriding `replica_fallback`.
+    pub replica_fallback: Option<ReplicaFallback>,
+    /// Primary pool reserved for writes, overriding `primary_reserve`.
+    pub primary_reserve: Option<usize>,
 }
 
 impl Database {
@@ -652,6 +783,8 @@ pub enum Role {
     #[default]
     Primary,
     Replica,
+    /// Detected with `pg_is_in_recovery()` by the healthcheck.
+    Auto,
 }
 
 impl std::fmt::Display for Role {
@@ -659,6 +792,7 @@ impl std::fmt::Display for Role {
         match self {
             Self::Primary => write!(f, "primary"),
             Self::Replica => write!(f, "replica"),
+            Self::Auto => write!(f, "auto"),
         }
     }
 }
@@ -832,6 +966,10 @@ pub struct ShardedTable {
     /// How many centroids to probe.
     #[serde(default)]
     pub centroid_probes: usize,
+    /// The sharding key is stored in a JSON column, at this path,
+    /// e.g. `tenant_id` for `data->>'tenant_id'`. Nested keys are separated by dots.
+    #[serde(default)]
+    pub json_path: Option<String>,
 }
 
 impl ShardedTable {
@@ -862,6 +1000,18 @@ impl ShardedTable {
 
         Ok(())
     }
+
+    /// Keys leading to the sharding key inside a JSON column.
+    /// Empty if the column isn't JSON.
+    pub fn json_path(&self) -> Vec<&str> {
+        json_keys(self.json_path.as_deref())
+    }
+}
+
+/// Keys in a JSON path, e.g. `org.id`.
+pub fn json_keys(path: Option<&str>) -> Vec<&str> {
+    path.map(|path| path.split('.').collect())
+        .unwrap_or_default()
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default, Copy)]
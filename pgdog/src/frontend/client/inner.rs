@@ -33,6 +33,9 @@ pub(super) struct Inner {
     pub(super) start_transaction: Option<BufferedQuery>,
     /// Client-wide comms.
     pub(super) comms: Comms,
+    /// How many times the current request can still be retried.
+    /// Zero once anything was sent to the client.
+    pub(super) retries: usize,
 }
 
 impl Inner {
@@ -63,6 +66,7 @@ impl Inner {
             stats: Stats::new(),
             start_transaction: None,
             comms: client.comms.clone(),
+            retries: 0,
         })
     }
 
@@ -101,6 +105,7 @@ impl Inner {
     /// Reset query router context.
     pub(super) fn reset_router(&mut self) {
         self.router.reset();
+        self.backend.reset_cursors();
     }
 
     /// Client is connected to server(s).
@@ -8,17 +8,19 @@ use bytes::BytesMut;
 use timeouts::Timeouts;
 use tokio::time::timeout;
 use tokio::{select, spawn};
-use tracing::{debug, error, info, trace};
+use tracing::{debug, error, info, trace, warn};
 
 use super::{Buffer, Command, Comms, Error, PreparedStatements};
 use crate::auth::{md5, scram::Server};
+use crate::backend::pool::connection::join::Rows;
 use crate::backend::{
     databases,
     pool::{Connection, Request},
-    ProtocolMessage,
+    Error as BackendError, ProtocolMessage,
 };
 use crate::config::{self, AuthType};
 use crate::frontend::buffer::BufferedQuery;
+use crate::frontend::router::parser::{Explain, Fetch, InsertSelect, Join};
 #[cfg(debug_assertions)]
 use crate::frontend::QueryLogger;
 use crate::net::messages::{
@@ -49,6 +51,8 @@ pub struct Client {
     shard: Option<usize>,
     prepared_statements: PreparedStatements,
     in_transaction: bool,
+    /// Transaction was aborted by pgdog and its server connections were closed.
+    aborted: bool,
     timeouts: Timeouts,
     request_buffer: Buffer,
     stream_buffer: BytesMut,
@@ -197,6 +201,7 @@ impl Client {
             connect_params: params,
             prepared_statements: PreparedStatements::new(),
             in_transaction: false,
+            aborted: false,
             timeouts: Timeouts::from_config(&config.config.general),
             request_buffer: Buffer::new(),
             stream_buffer: BytesMut::new(),
@@ -238,6 +243,7 @@ impl Client {
             admin: false,
             shard: None,
             in_transaction: false,
+            aborted: false,
             timeouts: Timeouts::from_config(&config().config.general),
             request_buffer: Buffer::new(),
             stream_buffer: BytesMut::new(),
@@ -282,7 +288,19 @@ impl Client {
 
                 // Async messages.
                 message = timeout(query_timeout, inner.backend.read()) => {
-                    let message = message??;
+                    let message = match message? {
+                        Ok(message) => message,
+                        Err(err) if err.aborted() => {
+                            self.send_backend_error(inner.get(), err).await?;
+                            continue;
+                        }
+                        Err(err) => {
+                            if self.retry(inner.get(), &err).await? {
+                                continue;
+                            }
+                            return Err(err.into());
+                        }
+                    };
                     let disconnect = self.server_message(inner.get(), message).await?;
                     if disconnect {
                         break;
@@ -368,8 +386,68 @@ impl Client {
             }
         };
 
+        // Server connections of an aborted transaction are gone,
+        // it can only be ended.
+        if self.aborted {
+            match command {
+                Some(Command::RollbackTransaction) | Some(Command::CommitTransaction) => {
+                    inner.start_transaction = None;
+                    self.end_transaction(true).await?;
+                    self.in_transaction = false;
+                    self.aborted = false;
+                    inner.reset_router();
+                    inner.done(false);
+                }
+                _ => {
+                    self.stream
+                        .error(ErrorResponse::in_failed_transaction(), true)
+                        .await?;
+                    inner.reset_router();
+                    inner.done(true);
+                }
+            }
+            return Ok(false);
+        }
+
         self.streaming = matches!(command, Some(Command::StartReplication));
 
+        // INSERT ... SELECT into a sharded table uses its own
+        // server connections to re-shard rows returned by the SELECT.
+        if let Some(Command::InsertSelect(plan)) = command {
+            let plan = plan.clone();
+            self.insert_select(inner, &plan).await?;
+            return Ok(false);
+        }
+
+        // Joins between tables on different shards are executed
+        // by pgdog using its own server connections.
+        if let Some(Command::Join(plan)) = command {
+            let plan = plan.clone();
+            self.join(inner, &plan).await?;
+            return Ok(false);
+        }
+
+        // EXPLAIN of queries sent to multiple shards is
+        // combined by pgdog.
+        if let Some(Command::Explain(plan)) = command {
+            let plan = plan.clone();
+            self.explain(inner, &plan).await?;
+            return Ok(false);
+        }
+
+        // Rows fetched from cursors open on multiple shards
+        // are merged by pgdog.
+        if let Some(Command::Fetch(fetch)) = command {
+            let fetch = fetch.clone();
+            self.fetch(inner, &fetch).await?;
+            return Ok(false);
+        }
+
+        let close_cursor = match command {
+            Some(Command::CloseCursor(name)) => Some(name.clone()),
+            _ => None,
+        };
+
         if !connected {
             // Simulate transaction starting
             // until client sends an actual query.
@@ -454,6 +532,11 @@ impl Client {
             };
         }
 
+        // CLOSE is sent to the servers as well.
+        if let Some(name) = close_cursor {
+            inner.backend.close_cursor(&name);
+        }
+
         // We don't start a transaction on the servers until
         // a client is actually executing something.
         //
@@ -470,6 +553,43 @@ impl Client {
             }
         }
 
+        // Portals executed with a row limit on multiple shards
+        // are merged by pgdog.
+        let messages = match inner.backend.execute_portal(&self.request_buffer).await {
+            Ok(messages) => messages,
+            Err(err) if err.aborted() => {
+                self.send_backend_error(inner, err).await?;
+                return Ok(false);
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if let Some(messages) = messages {
+            for message in messages {
+                if self.server_message(inner.get(), message).await? {
+                    return Ok(true);
+                }
+            }
+            return Ok(false);
+        }
+
+        // Reads outside of transactions can be retried on another server
+        // if this one fails before sending anything to the client.
+        let route = inner.router.route();
+        inner.retries = if route.is_retryable()
+            && !self.in_transaction
+            && !self.streaming
+            && !self.request_buffer.copy()
+            && inner.transaction_mode()
+        {
+            inner
+                .backend
+                .cluster()
+                .map(|cluster| cluster.read_retries())
+                .unwrap_or_default()
+        } else {
+            0
+        };
+
         inner
             .handle_buffer(&self.request_buffer, self.streaming)
             .await?;
@@ -492,6 +612,9 @@ impl Client {
         let message = message.backend();
         let has_more_messages = inner.backend.has_more_messages();
 
+        // Client received something, retrying is no longer safe.
+        inner.retries = 0;
+
         // Messages that we need to send to the client immediately.
         // ReadyForQuery (B) | CopyInResponse (B) | ErrorResponse(B) | NoticeResponse(B)
         let flush =
@@ -513,8 +636,15 @@ impl Client {
         // before flushing data to client.
         // Flushing can take a minute and we don't want to block
         // the connection from being reused.
+        let mut flushed = false;
         if inner.backend.done() {
             let changed_params = inner.backend.changed_params();
+            // Don't make the client wait for the LSN.
+            if inner.backend.lsn_pending() {
+                self.stream.send_flush(&message).await?;
+                flushed = true;
+            }
+            inner.backend.record_lsn().await;
             if inner.transaction_mode() {
                 inner.disconnect();
             }
@@ -538,7 +668,9 @@ impl Client {
 
         trace!("[{}] <- {:#?}", self.addr, message);
 
-        if flush {
+        if flushed {
+            // Already sent.
+        } else if flush {
             self.stream.send_flush(&message).await?;
         } else {
             self.stream.send(&message).await?;
@@ -552,6 +684,45 @@ impl Client {
         Ok(false)
     }
 
+    /// Send the request to another server if the one it was sent to failed
+    /// before anything was sent to the client.
+    async fn retry(
+        &mut self,
+        mut inner: InnerBorrow<'_>,
+        err: &BackendError,
+    ) -> Result<bool, Error> {
+        if inner.retries == 0 {
+            return Ok(false);
+        }
+        inner.retries -= 1;
+
+        warn!("retrying read on another server: {} [{}]", err, self.addr);
+
+        // Release the failed server, banning its pool.
+        inner.backend.retry();
+
+        let request = Request::new(self.id);
+        if let Err(err) = inner.connect(&request).await {
+            error!("read retry failed: {} [{}]", err, self.addr);
+            return Ok(false);
+        }
+
+        let query_timeout = self.timeouts.query_timeout(&inner.stats.state);
+        timeout(query_timeout, inner.backend.link_client(&self.params)).await??;
+
+        for msg in self.request_buffer.iter() {
+            if let ProtocolMessage::Bind(bind) = msg {
+                inner.backend.bind(bind)?
+            }
+        }
+
+        inner
+            .handle_buffer(&self.request_buffer, self.streaming)
+            .await?;
+
+        Ok(true)
+    }
+
     /// Buffer extended protocol messages until client requests a sync.
     ///
     /// This ensures we don't check out a connection from the pool until the client
@@ -615,6 +786,32 @@ impl Client {
         Ok(())
     }
 
+    /// Send an error from pgdog's own server connections to the client
+    /// and finish the request.
+    ///
+    /// Aborted queries close server connections that are still sending rows.
+    /// A transaction the query was part of is aborted too,
+    /// and the client has to roll it back.
+    async fn send_backend_error(
+        &mut self,
+        mut inner: InnerBorrow<'_>,
+        err: BackendError,
+    ) -> Result<(), Error> {
+        error!("{} [{}]", err, self.addr);
+        if err.aborted() {
+            inner.backend.force_close();
+            self.aborted = self.in_transaction;
+        }
+        let err = match err {
+            BackendError::ExecutionError(err) => *err,
+            err => ErrorResponse::from_err(&err),
+        };
+        self.stream.error(err, self.in_transaction).await?;
+        inner.reset_router();
+        inner.done(self.in_transaction);
+        Ok(())
+    }
+
     /// Tell the client we finished a transaction (without doing any work).
     ///
     /// This avoids connecting to servers when clients start and commit transactions
@@ -637,6 +834,135 @@ impl Client {
         Ok(())
     }
 
+    /// Handle INSERT ... SELECT into a sharded table.
+    async fn insert_select(
+        &mut self,
+        mut inner: InnerBorrow<'_>,
+        plan: &InsertSelect,
+    ) -> Result<(), Error> {
+        let request = Request::new(self.id);
+        match inner.backend.insert_select(&request, plan).await {
+            Ok(rows) => {
+                self.stream
+                    .send(&CommandComplete::new(format!("INSERT 0 {}", rows)))
+                    .await?;
+                self.stream
+                    .send_flush(&ReadyForQuery::in_transaction(self.in_transaction))
+                    .await?;
+            }
+            Err(err) => return self.send_backend_error(inner, err).await,
+        }
+        inner.reset_router();
+        inner.done(self.in_transaction);
+        debug!("insert select");
+        Ok(())
+    }
+
+    /// Execute a join across shards and send the rows to the client.
+    ///
+    /// Rows are sent one batch at a time, as they are joined.
+    async fn join(&mut self, mut inner: InnerBorrow<'_>, plan: &Join) -> Result<(), Error> {
+        let request = Request::new(self.id);
+        let mut sent = 0;
+        let mut described = false;
+        let result = match inner.backend.join(&request, plan) {
+            Ok(mut executor) => loop {
+                match executor.next().await {
+                    Ok(Some(rows)) => {
+                        if !described {
+                            self.stream.send(&rows.rd).await?;
+                            described = true;
+                        }
+                        sent += rows.rows.len();
+                        self.stream.send_many(&rows.rows).await?;
+                    }
+                    Ok(None) => break Ok(()),
+                    Err(err) => break Err(err),
+                }
+            },
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok(()) => {
+                self.stream
+                    .send(&CommandComplete::new(format!("SELECT {}", sent)))
+                    .await?;
+                self.stream
+                    .send_flush(&ReadyForQuery::in_transaction(self.in_transaction))
+                    .await?;
+            }
+            Err(err) => return self.send_backend_error(inner, err).await,
+        }
+        inner.reset_router();
+        inner.done(self.in_transaction);
+        debug!("join");
+        Ok(())
+    }
+
+    /// Execute EXPLAIN on multiple shards and send the combined plan to the client.
+    async fn explain(&mut self, inner: InnerBorrow<'_>, plan: &Explain) -> Result<(), Error> {
+        let request = Request::new(self.id);
+        let result = inner.backend.explain(&request, plan).await;
+        self.send_rows(inner, result).await?;
+        debug!("explain");
+        Ok(())
+    }
+
+    /// Send rows produced by pgdog to the client.
+    async fn send_rows(
+        &mut self,
+        mut inner: InnerBorrow<'_>,
+        result: Result<Rows, BackendError>,
+    ) -> Result<(), Error> {
+        match result {
+            Ok(rows) => {
+                self.stream.send(&rows.rd).await?;
+                for row in &rows.rows {
+                    self.stream.send(row).await?;
+                }
+                self.stream
+                    .send(&CommandComplete::new(format!("SELECT {}", rows.rows.len())))
+                    .await?;
+                self.stream
+                    .send_flush(&ReadyForQuery::in_transaction(self.in_transaction))
+                    .await?;
+            }
+            Err(err) => return self.send_backend_error(inner, err).await,
+        }
+        inner.reset_router();
+        inner.done(self.in_transaction);
+        Ok(())
+    }
+
+    /// Fetch rows from a cursor open on multiple shards and send them to the client.
+    async fn fetch(&mut self, mut inner: InnerBorrow<'_>, fetch: &Fetch) -> Result<(), Error> {
+        match inner.backend.fetch(fetch).await {
+            Ok(rows) => {
+                if fetch.is_move {
+                    self.stream
+                        .send(&CommandComplete::new(format!("MOVE {}", rows.rows.len())))
+                        .await?;
+                } else {
+                    self.stream.send(&rows.rd).await?;
+                    for row in &rows.rows {
+                        self.stream.send(row).await?;
+                    }
+                    self.stream
+                        .send(&CommandComplete::new(format!("FETCH {}", rows.rows.len())))
+                        .await?;
+                }
+                self.stream
+                    .send_flush(&ReadyForQuery::in_transaction(self.in_transaction))
+                    .await?;
+            }
+            Err(err) => return self.send_backend_error(inner, err).await,
+        }
+        inner.done(self.in_transaction);
+        debug!("fetch");
+        Ok(())
+    }
+
     /// Handle SET command.
     async fn set(&mut self, mut inner: InnerBorrow<'_>) -> Result<(), Error> {
         self.stream.send(&CommandComplete::new("SET")).await?;
@@ -379,6 +379,7 @@ async fn test_client_with_replicas() {
                 assert!(state.stats.counts.healthchecks <= idle + 1); // TODO: same
                 pool_sent -= (healthcheck_len_sent * state.stats.counts.healthchecks) as isize;
             }
+            Role::Auto => unreachable!("roles are configured"),
         }
     }
 
@@ -528,3 +529,47 @@ async fn test_transaction_state() {
     assert!(!client.in_transaction);
     assert!(!inner.router.routed());
 }
+
+#[tokio::test]
+async fn test_row_limit_in_transaction() {
+    let (mut conn, mut client, mut inner) = new_client!(false);
+
+    conn.write_all(&buffer!({ Query::new("BEGIN") }))
+        .await
+        .unwrap();
+    client.buffer().await.unwrap();
+    client.client_messages(inner.get()).await.unwrap();
+    read!(conn, ['C', 'Z']);
+
+    client
+        .send_backend_error(inner.get(), crate::backend::Error::MultiShardRowLimit(2))
+        .await
+        .unwrap();
+
+    // Transaction is aborted, not finished.
+    let messages = read!(conn, ['E', 'Z']);
+    let rfq = ReadyForQuery::from_bytes(messages[1].clone().freeze()).unwrap();
+    assert_eq!(rfq.status, 'E');
+    assert!(client.in_transaction);
+    assert!(!inner.backend.connected());
+
+    conn.write_all(&buffer!({ Query::new("SELECT 1") }))
+        .await
+        .unwrap();
+    client.buffer().await.unwrap();
+    client.client_messages(inner.get()).await.unwrap();
+    let messages = read!(conn, ['E', 'Z']);
+    let rfq = ReadyForQuery::from_bytes(messages[1].clone().freeze()).unwrap();
+    assert_eq!(rfq.status, 'E');
+    assert!(!inner.backend.connected());
+
+    conn.write_all(&buffer!({ Query::new("ROLLBACK") }))
+        .await
+        .unwrap();
+    client.buffer().await.unwrap();
+    client.client_messages(inner.get()).await.unwrap();
+    let messages = read!(conn, ['C', 'Z']);
+    let rfq = ReadyForQuery::from_bytes(messages[1].clone().freeze()).unwrap();
+    assert_eq!(rfq.status, 'I');
+    assert!(!client.in_transaction);
+}
@@ -6,12 +6,14 @@ pub mod error;
 pub mod parser;
 pub mod request;
 pub mod round_robin;
+pub mod route_info;
 pub mod search_path;
 pub mod sharding;
 
 pub use copy::CopyRow;
 pub use error::Error;
-pub use parser::{Command, QueryParser, Route};
+pub use parser::{Command, KeySource, QueryParser, Route};
+pub use route_info::RouteInfo;
 
 use super::Buffer;
 pub use context::RouterContext;
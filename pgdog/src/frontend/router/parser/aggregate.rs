@@ -10,6 +10,19 @@ pub struct AggregateTarget {
     function: AggregateFunction,
 }
 
+impl std::fmt::Display for AggregateTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let function = match self.function {
+            AggregateFunction::Count => "count",
+            AggregateFunction::Max => "max",
+            AggregateFunction::Min => "min",
+            AggregateFunction::Avg => "avg",
+            AggregateFunction::Sum => "sum",
+        };
+        write!(f, "{}(${})", function, self.column + 1)
+    }
+}
+
 impl AggregateTarget {
     pub fn function(&self) -> &AggregateFunction {
         &self.function
@@ -85,7 +98,7 @@ impl Aggregate {
 
                                     "sum" => targets.push(AggregateTarget {
                                         column: idx,
-                                        function: AggregateFunction::Max,
+                                        function: AggregateFunction::Sum,
                                     }),
 
                                     _ => {}
@@ -136,3 +149,38 @@ impl Aggregate {
         self.targets.len()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use pg_query::parse;
+
+    use super::*;
+
+    #[test]
+    fn test_aggregate_functions() {
+        let ast =
+            parse("SELECT sum(price), count(*), max(id), min(id), avg(price) FROM orders").unwrap();
+        let stmt = ast.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+        let Some(NodeEnum::SelectStmt(ref stmt)) = stmt.node else {
+            panic!("not a select");
+        };
+
+        let aggregate = Aggregate::parse(stmt).unwrap();
+        let functions = aggregate
+            .targets()
+            .iter()
+            .map(|target| (target.column(), target.function().clone()))
+            .collect::<Vec<_>>();
+
+        // avg() isn't merged across shards.
+        assert_eq!(
+            functions,
+            vec![
+                (0, AggregateFunction::Sum),
+                (1, AggregateFunction::Count),
+                (2, AggregateFunction::Max),
+                (3, AggregateFunction::Min),
+            ]
+        );
+    }
+}
@@ -14,6 +14,11 @@ pub enum Command {
     PreparedStatement(Prepare),
     Rewrite(String),
     Shards(usize),
+    InsertSelect(Box<InsertSelect>),
+    Join(Box<Join>),
+    Fetch(Box<Fetch>),
+    Explain(Box<Explain>),
+    CloseCursor(String),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -52,6 +57,28 @@ impl std::fmt::Display for SetVal {
 }
 
 impl Command {
+    /// Command name, used when showing routing decisions.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Query(_) => "Query",
+            Command::Copy(_) => "Copy",
+            Command::StartTransaction(_) => "StartTransaction",
+            Command::CommitTransaction => "CommitTransaction",
+            Command::RollbackTransaction => "RollbackTransaction",
+            Command::StartReplication => "StartReplication",
+            Command::ReplicationMeta => "ReplicationMeta",
+            Command::Set { .. } => "Set",
+            Command::PreparedStatement(_) => "PreparedStatement",
+            Command::Rewrite(_) => "Rewrite",
+            Command::Shards(_) => "Shards",
+            Command::InsertSelect(_) => "InsertSelect",
+            Command::Join(_) => "Join",
+            Command::Fetch(_) => "Fetch",
+            Command::Explain(_) => "Explain",
+            Command::CloseCursor(_) => "CloseCursor",
+        }
+    }
+
     pub(crate) fn dry_run(self) -> Self {
         match self {
             Command::Query(mut query) => {
@@ -59,7 +86,10 @@ impl Command {
                 Command::Query(query)
             }
 
-            Command::Copy(_) => Command::Query(Route::write(Some(0))),
+            Command::Copy(_)
+            | Command::InsertSelect(_)
+            | Command::Join(_)
+            | Command::Explain(_) => Command::Query(Route::write(Some(0))),
             _ => self,
         }
     }
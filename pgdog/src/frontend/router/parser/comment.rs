@@ -11,6 +11,8 @@ use super::Error;
 static SHARD: Lazy<Regex> = Lazy::new(|| Regex::new(r#"pgdog_shard: *([0-9]+)"#).unwrap());
 static SHARDING_KEY: Lazy<Regex> =
     Lazy::new(|| Regex::new(r#"pgdog_sharding_key: *([0-9a-zA-Z]+)"#).unwrap());
+static ALL_SHARDS: Lazy<Regex> = Lazy::new(|| Regex::new(r#"pgdog_all_shards\b"#).unwrap());
+static CRITICAL: Lazy<Regex> = Lazy::new(|| Regex::new(r#"pgdog_critical\b"#).unwrap());
 
 /// Extract shard number from a comment.
 ///
@@ -48,3 +50,43 @@ pub fn shard(query: &str, schema: &ShardingSchema) -> Result<Shard, Error> {
 
     Ok(Shard::All)
 }
+
+/// Get the routing hint from a comment, e.g. `pgdog_shard: 1`, if any.
+pub fn hint(query: &str) -> Result<Option<String>, Error> {
+    let tokens = scan(query).map_err(Error::PgQuery)?;
+
+    for token in tokens.tokens.iter() {
+        if token.token == Token::CComment as i32 {
+            let comment = &query[token.start as usize..token.end as usize];
+            for regex in [&*SHARDING_KEY, &*SHARD] {
+                if let Some(cap) = regex.find(comment) {
+                    return Ok(Some(cap.as_str().to_owned()));
+                }
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Check that the query has a `/* pgdog_all_shards */` comment,
+/// confirming it should be sent to all shards.
+pub fn all_shards(query: &str) -> Result<bool, Error> {
+    let tokens = scan(query).map_err(Error::PgQuery)?;
+
+    Ok(tokens.tokens.iter().any(|token| {
+        token.token == Token::CComment as i32
+            && ALL_SHARDS.is_match(&query[token.start as usize..token.end as usize])
+    }))
+}
+
+/// Check that the query has a `/* pgdog_critical */` comment,
+/// allowing the read to go to the primary if replicas are down.
+pub fn critical(query: &str) -> Result<bool, Error> {
+    let tokens = scan(query).map_err(Error::PgQuery)?;
+
+    Ok(tokens.tokens.iter().any(|token| {
+        token.token == Token::CComment as i32
+            && CRITICAL.is_match(&query[token.start as usize..token.end as usize])
+    }))
+}
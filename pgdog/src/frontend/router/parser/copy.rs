@@ -7,7 +7,7 @@ use crate::{
     config::ShardedTable,
     frontend::router::{
         parser::Shard,
-        sharding::{ContextBuilder, Tables},
+        sharding::{json, ContextBuilder, Tables},
         CopyRow,
     },
     net::messages::{CopyData, ToBytes},
@@ -206,12 +206,16 @@ impl CopyParser {
                                 .get(self.sharded_column)
                                 .ok_or(Error::NoShardingColumn)?;
 
-                            let ctx = ContextBuilder::new(table)
-                                .data(key)
-                                .shards(self.sharding_schema.shards)
-                                .build()?;
+                            if table.json_path.is_some() {
+                                json::shard(table, key.as_bytes(), self.sharding_schema.shards)?
+                            } else {
+                                let ctx = ContextBuilder::new(table)
+                                    .data(key)
+                                    .shards(self.sharding_schema.shards)
+                                    .build()?;
 
-                            ctx.apply()?
+                                ctx.apply()?
+                            }
                         } else {
                             Shard::All
                         };
@@ -239,12 +243,16 @@ impl CopyParser {
                                 .get(self.sharded_column)
                                 .ok_or(Error::NoShardingColumn)?;
                             if let Data::Column(key) = key {
-                                let ctx = ContextBuilder::new(table)
-                                    .data(&key[..])
-                                    .shards(self.sharding_schema.shards)
-                                    .build()?;
-
-                                ctx.apply()?
+                                if table.json_path.is_some() {
+                                    json::shard(table, &key[..], self.sharding_schema.shards)?
+                                } else {
+                                    let ctx = ContextBuilder::new(table)
+                                        .data(&key[..])
+                                        .shards(self.sharding_schema.shards)
+                                        .build()?;
+
+                                    ctx.apply()?
+                                }
                             } else {
                                 Shard::All
                             }
@@ -0,0 +1,140 @@
+//! Cursors declared on multiple shards.
+//!
+//! Rows returned by `FETCH` are merged by pgdog using the
+//! cursor's `ORDER BY`, so the client sees one cursor.
+use std::collections::HashMap;
+
+use pg_query::protobuf::{FetchDirection, FetchStmt};
+
+use super::{Error, OrderBy};
+
+/// `DECLARE ... CURSOR WITH HOLD`.
+const CURSOR_OPT_HOLD: i32 = 0x0020;
+
+/// `FETCH` (or `MOVE`) from a cursor open on multiple shards.
+#[derive(Debug, Clone)]
+pub struct Fetch {
+    /// Cursor name.
+    pub cursor: String,
+    /// Number of rows to fetch. `None` fetches all remaining rows.
+    pub count: Option<usize>,
+    /// Cursor's `ORDER BY`.
+    pub order_by: Vec<OrderBy>,
+    /// Rows are skipped, not returned.
+    pub is_move: bool,
+}
+
+/// Cursors open on multiple shards, by name.
+#[derive(Debug, Default, Clone)]
+pub struct Cursors {
+    cursors: HashMap<String, Vec<OrderBy>>,
+}
+
+impl Cursors {
+    /// Check the cursor can be opened on multiple shards.
+    pub fn check(options: i32) -> Result<(), Error> {
+        if options & CURSOR_OPT_HOLD != 0 {
+            return Err(Error::CursorUnsupported("WITH HOLD".into()));
+        }
+
+        Ok(())
+    }
+
+    /// Track a cursor open on multiple shards.
+    pub fn declare(&mut self, name: &str, order_by: Vec<OrderBy>) {
+        self.cursors.insert(name.to_owned(), order_by);
+    }
+
+    /// Stop tracking a cursor. An empty name closes all of them (`CLOSE ALL`).
+    /// Returns true if any tracked cursors were closed.
+    pub fn close(&mut self, name: &str) -> bool {
+        if name.is_empty() {
+            let closed = !self.cursors.is_empty();
+            self.cursors.clear();
+            closed
+        } else {
+            self.cursors.remove(name).is_some()
+        }
+    }
+
+    /// Plan a `FETCH` from a tracked cursor.
+    ///
+    /// Returns `None` if the cursor is not open on multiple shards.
+    pub fn fetch(&self, stmt: &FetchStmt) -> Result<Option<Fetch>, Error> {
+        let Some(order_by) = self.cursors.get(&stmt.portalname) else {
+            return Ok(None);
+        };
+
+        if stmt.direction() != FetchDirection::FetchForward || stmt.how_many < 1 {
+            return Err(Error::CursorUnsupported(
+                "fetching in any direction other than FORWARD".into(),
+            ));
+        }
+
+        Ok(Some(Fetch {
+            cursor: stmt.portalname.clone(),
+            count: if stmt.how_many == i64::MAX {
+                None
+            } else {
+                Some(stmt.how_many as usize)
+            },
+            order_by: order_by.clone(),
+            is_move: stmt.ismove,
+        }))
+    }
+
+    /// No cursors are tracked.
+    pub fn is_empty(&self) -> bool {
+        self.cursors.is_empty()
+    }
+
+    /// Forget all cursors, e.g. when the transaction ends.
+    pub fn clear(&mut self) {
+        self.cursors.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pg_query::{parse, NodeEnum};
+
+    use super::*;
+
+    fn fetch(cursors: &Cursors, query: &str) -> Result<Option<Fetch>, Error> {
+        let ast = parse(query).unwrap();
+        let stmt = ast.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+        match stmt.node {
+            Some(NodeEnum::FetchStmt(ref stmt)) => cursors.fetch(stmt),
+            _ => panic!("not a fetch"),
+        }
+    }
+
+    #[test]
+    fn test_fetch() {
+        let mut cursors = Cursors::default();
+        cursors.declare("c", vec![OrderBy::Asc(1)]);
+
+        let plan = fetch(&cursors, "FETCH 100 FROM c").unwrap().unwrap();
+        assert_eq!(plan.count, Some(100));
+        assert!(matches!(plan.order_by.as_slice(), [OrderBy::Asc(1)]));
+        assert!(!plan.is_move);
+
+        let plan = fetch(&cursors, "FETCH ALL FROM c").unwrap().unwrap();
+        assert_eq!(plan.count, None);
+
+        let plan = fetch(&cursors, "MOVE FORWARD 5 IN c").unwrap().unwrap();
+        assert_eq!(plan.count, Some(5));
+        assert!(plan.is_move);
+
+        assert_eq!(fetch(&cursors, "FETCH c").unwrap().unwrap().count, Some(1));
+        assert!(fetch(&cursors, "FETCH 1 FROM other").unwrap().is_none());
+        assert!(matches!(
+            fetch(&cursors, "FETCH BACKWARD 1 FROM c"),
+            Err(Error::CursorUnsupported(_))
+        ));
+
+        assert!(cursors.close("c"));
+        assert!(fetch(&cursors, "FETCH 1 FROM c").unwrap().is_none());
+        assert!(!cursors.close(""));
+    }
+}
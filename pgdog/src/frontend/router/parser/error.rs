@@ -4,6 +4,8 @@ use thiserror::Error;
 
 use crate::frontend::router::sharding;
 
+use super::Shard;
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("{0}")]
@@ -45,6 +47,9 @@ pub enum Error {
     #[error("set shard syntax error")]
     SetShard,
 
+    #[error("invalid pgdog.role \"{0}\", expected primary, replica or auto")]
+    SetRole(String),
+
     #[error("no multi tenant id")]
     MultiTenantId,
 
@@ -53,4 +58,37 @@ pub enum Error {
 
     #[error("missing parameter: ${0}")]
     MissingParameter(usize),
+
+    #[error("INSERT ... SELECT into sharded table \"{0}\" requires a column list")]
+    InsertSelectColumns(String),
+
+    #[error("INSERT ... SELECT into a sharded table doesn't support RETURNING")]
+    InsertSelectReturning,
+
+    #[error("INSERT ... SELECT returned NULL for sharding key \"{0}\"")]
+    InsertSelectNullKey(String),
+
+    #[error("INSERT ... SELECT sharding key \"{0}\" doesn't map to exactly one shard")]
+    InsertSelectShard(String),
+
+    #[error("INSERT ... SELECT across shards is only supported outside of transactions and over the simple protocol")]
+    InsertSelectUnsupported,
+
+    #[error("{0} can't be executed across shards in strict mode")]
+    Strict(String),
+
+    #[error("{0} on sharded table \"{1}\" would be sent to all shards, add /* pgdog_all_shards */ to the query to confirm")]
+    AllShardsWriteComment(String, String),
+
+    #[error("{0} on sharded table \"{1}\" would be sent to all shards")]
+    AllShardsWrite(String, String),
+
+    #[error("cursors open on multiple shards don't support {0}")]
+    CursorUnsupported(String),
+
+    #[error("statements in the query target different shards ({0} and {1}), send them separately")]
+    MultiStatementShards(Shard, Shard),
+
+    #[error("{0} can't be sent with other statements, send it separately")]
+    MultiStatementUnsupported(String),
 }
@@ -0,0 +1,191 @@
+//! `EXPLAIN` of queries sent to multiple shards.
+//!
+//! Each shard's plan is returned under a header naming the shard,
+//! below a synthetic node describing what pgdog does to merge the results.
+use pg_query::{
+    protobuf::{a_const::Val, ExplainStmt},
+    NodeEnum,
+};
+
+use super::Route;
+
+/// `EXPLAIN` execution plan.
+#[derive(Debug, Clone)]
+pub struct Explain {
+    /// The `EXPLAIN` statement, sent to each shard as-is.
+    query: String,
+    /// Route taken by the explained query.
+    route: Route,
+    /// `EXPLAIN ANALYZE`.
+    analyze: bool,
+}
+
+impl Explain {
+    /// Plan an `EXPLAIN` statement.
+    ///
+    /// Returns `None` if the output format is not text,
+    /// since we can't add our own nodes to it.
+    pub fn new(stmt: &ExplainStmt, query: &str, route: Route) -> Option<Self> {
+        let mut analyze = false;
+
+        for option in &stmt.options {
+            let Some(NodeEnum::DefElem(ref elem)) = option.node else {
+                continue;
+            };
+            let value = elem
+                .arg
+                .as_ref()
+                .and_then(|arg| arg.node.as_ref())
+                .and_then(|arg| match arg {
+                    NodeEnum::String(string) => Some(string.sval.to_lowercase()),
+                    NodeEnum::AConst(aconst) => match aconst.val {
+                        Some(Val::Boolval(ref boolean)) => Some(boolean.boolval.to_string()),
+                        Some(Val::Sval(ref string)) => Some(string.sval.to_lowercase()),
+                        _ => None,
+                    },
+                    _ => None,
+                });
+
+            match elem.defname.as_str() {
+                "analyze" => {
+                    analyze = !matches!(value.as_deref(), Some("false" | "off" | "0"));
+                }
+                "format" => {
+                    if value.as_deref() != Some("text") {
+                        return None;
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Some(Self {
+            query: query.to_owned(),
+            route,
+            analyze,
+        })
+    }
+
+    /// The `EXPLAIN` statement.
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Route taken by the explained query.
+    pub fn route(&self) -> &Route {
+        &self.route
+    }
+
+    /// `EXPLAIN ANALYZE` was requested.
+    pub fn analyze(&self) -> bool {
+        self.analyze
+    }
+
+    /// Lines of the top-level node describing the merge performed by pgdog.
+    pub fn merge(&self) -> Vec<String> {
+        let mut lines = vec![format!("PgDog Merge  ({})", self.route)];
+
+        if !self.route.order_by().is_empty() {
+            let keys = self
+                .route
+                .order_by()
+                .iter()
+                .map(|key| key.to_string())
+                .collect::<Vec<_>>();
+            lines.push(format!("  Sort Key: {}", keys.join(", ")));
+        }
+
+        let aggregate = self.route.aggregate();
+        if !aggregate.is_empty() {
+            let targets = aggregate
+                .targets()
+                .iter()
+                .map(|target| target.to_string())
+                .collect::<Vec<_>>();
+            lines.push(format!("  Aggregates: {}", targets.join(", ")));
+
+            if !aggregate.group_by().is_empty() {
+                let keys = aggregate
+                    .group_by()
+                    .iter()
+                    .map(|column| format!("${}", column + 1))
+                    .collect::<Vec<_>>();
+                lines.push(format!("  Group Key: {}", keys.join(", ")));
+            }
+        }
+
+        let limit = self.route.limit();
+        if let Some(limit) = limit.limit {
+            lines.push(format!("  Limit: {}", limit));
+        }
+        if let Some(offset) = limit.offset {
+            lines.push(format!("  Offset: {}", offset));
+        }
+
+        lines
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pg_query::parse;
+
+    use crate::frontend::router::parser::{Aggregate, Limit, OrderBy, Shard};
+
+    use super::*;
+
+    fn explain(query: &str, route: Route) -> Option<Explain> {
+        let ast = parse(query).unwrap();
+        let stmt = ast.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+        match stmt.node {
+            Some(NodeEnum::ExplainStmt(ref stmt)) => Explain::new(stmt, query, route),
+            _ => panic!("not an explain"),
+        }
+    }
+
+    #[test]
+    fn test_explain_options() {
+        assert!(!explain("EXPLAIN SELECT 1", Route::default())
+            .unwrap()
+            .analyze());
+        assert!(explain("EXPLAIN ANALYZE SELECT 1", Route::default())
+            .unwrap()
+            .analyze());
+        assert!(
+            explain("EXPLAIN (ANALYZE, BUFFERS) SELECT 1", Route::default())
+                .unwrap()
+                .analyze()
+        );
+        assert!(
+            !explain("EXPLAIN (ANALYZE false) SELECT 1", Route::default())
+                .unwrap()
+                .analyze()
+        );
+        assert!(explain("EXPLAIN (FORMAT JSON) SELECT 1", Route::default()).is_none());
+        assert!(explain("EXPLAIN (FORMAT TEXT) SELECT 1", Route::default()).is_some());
+    }
+
+    #[test]
+    fn test_explain_merge() {
+        let route = Route::select(
+            Shard::All,
+            vec![OrderBy::AscColumn("id".into()), OrderBy::Desc(2)],
+            Aggregate::new_count_group_by(0, &[1]),
+            Limit {
+                limit: Some(10),
+                offset: None,
+            },
+        );
+        let plan = explain("EXPLAIN SELECT 1", route).unwrap();
+        assert_eq!(
+            plan.merge(),
+            vec![
+                "PgDog Merge  (shard=all, role=replica)",
+                "  Sort Key: id, $2 DESC",
+                "  Aggregates: count($1)",
+                "  Group Key: $2",
+                "  Limit: 10",
+            ]
+        );
+    }
+}
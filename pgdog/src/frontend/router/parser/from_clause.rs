@@ -0,0 +1,237 @@
+//! Tables referenced in the `FROM` clause of a query
+//! and the column equalities joining them.
+use pg_query::{
+    protobuf::{AExprKind, BoolExprType, Node, RangeVar},
+    NodeEnum,
+};
+
+/// Table referenced in the `FROM` clause.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Relation<'a> {
+    /// Table name.
+    pub name: &'a str,
+    /// Name used to reference the table in the query.
+    pub alias: &'a str,
+}
+
+impl<'a> From<&'a RangeVar> for Relation<'a> {
+    fn from(range_var: &'a RangeVar) -> Self {
+        Self {
+            name: range_var.relname.as_str(),
+            alias: range_var
+                .alias
+                .as_ref()
+                .map(|alias| alias.aliasname.as_str())
+                .unwrap_or(range_var.relname.as_str()),
+        }
+    }
+}
+
+/// Qualified column, as (table or alias, column).
+pub type QualifiedColumn<'a> = (&'a str, &'a str);
+
+/// Parsed `FROM` clause.
+#[derive(Debug, Default)]
+pub struct FromClause<'a> {
+    relations: Vec<Relation<'a>>,
+    /// Columns known to be equal, e.g. `o.customer_id = c.id`.
+    equivalences: Vec<Vec<QualifiedColumn<'a>>>,
+}
+
+impl<'a> FromClause<'a> {
+    /// Parse the `FROM` clause, looking for column equalities
+    /// in `JOIN ... ON` and the `WHERE` clause.
+    pub fn new(from_clause: &'a [Node], where_clause: &'a Option<Box<Node>>) -> Self {
+        let mut from = Self::default();
+        let mut conjuncts = vec![];
+
+        for node in from_clause {
+            from.walk(node, &mut conjuncts);
+        }
+
+        if let Some(ref where_clause) = where_clause {
+            split(where_clause, &mut conjuncts);
+        }
+
+        for conjunct in conjuncts {
+            if let Some((left, right)) = equality(conjunct) {
+                from.equal(left, right);
+            }
+        }
+
+        from
+    }
+
+    /// Add the table modified by an `UPDATE ... FROM` or `DELETE ... USING`.
+    pub fn target(mut self, relation: &'a RangeVar) -> Self {
+        self.relations.insert(0, relation.into());
+        self
+    }
+
+    /// Tables referenced in the `FROM` clause.
+    pub fn relations(&self) -> &[Relation<'a>] {
+        &self.relations
+    }
+
+    /// Query is joining more than one table.
+    pub fn join(&self) -> bool {
+        self.relations.len() > 1
+    }
+
+    /// All columns equal to the given column, including itself.
+    pub fn equivalent<'b>(&'b self, table: &'b str, column: &'b str) -> Vec<QualifiedColumn<'b>> {
+        self.equivalences
+            .iter()
+            .find(|class| class.contains(&(table, column)))
+            .cloned()
+            .unwrap_or(vec![(table, column)])
+    }
+
+    /// Collect tables and join predicates.
+    fn walk(&mut self, node: &'a Node, conjuncts: &mut Vec<&'a Node>) {
+        match node.node {
+            Some(NodeEnum::RangeVar(ref range_var)) => self.relations.push(range_var.into()),
+
+            Some(NodeEnum::JoinExpr(ref join)) => {
+                if let Some(ref larg) = join.larg {
+                    self.walk(larg, conjuncts);
+                }
+                if let Some(ref rarg) = join.rarg {
+                    self.walk(rarg, conjuncts);
+                }
+                if let Some(ref quals) = join.quals {
+                    split(quals, conjuncts);
+                }
+            }
+
+            _ => (),
+        }
+    }
+
+    /// Record that two columns are equal, merging their equivalence classes.
+    fn equal(&mut self, left: QualifiedColumn<'a>, right: QualifiedColumn<'a>) {
+        let position = |column| {
+            self.equivalences
+                .iter()
+                .position(|class: &Vec<QualifiedColumn>| class.contains(&column))
+        };
+
+        match (position(left), position(right)) {
+            (Some(l), Some(r)) if l == r => (),
+            (Some(l), Some(r)) => {
+                let class = self.equivalences.remove(l.max(r));
+                self.equivalences[l.min(r)].extend(class);
+            }
+            (Some(l), None) => self.equivalences[l].push(right),
+            (None, Some(r)) => self.equivalences[r].push(left),
+            (None, None) => self.equivalences.push(vec![left, right]),
+        }
+    }
+}
+
+/// Split an expression into `AND` conjuncts.
+pub(super) fn split<'a>(node: &'a Node, conjuncts: &mut Vec<&'a Node>) {
+    if let Some(NodeEnum::BoolExpr(ref expr)) = node.node {
+        if expr.boolop() == BoolExprType::AndExpr {
+            for arg in &expr.args {
+                split(arg, conjuncts);
+            }
+            return;
+        }
+    }
+
+    conjuncts.push(node);
+}
+
+/// Get table reference and column name from a column reference.
+pub(super) fn column(node: &Node) -> Option<QualifiedColumn<'_>> {
+    let Some(NodeEnum::ColumnRef(ref column_ref)) = node.node else {
+        return None;
+    };
+
+    match column_ref.fields.as_slice() {
+        [table, column] => match (&table.node, &column.node) {
+            (Some(NodeEnum::String(table)), Some(NodeEnum::String(column))) => {
+                Some((table.sval.as_str(), column.sval.as_str()))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Get both sides of an `a.x = b.y` predicate.
+pub(super) fn equality(node: &Node) -> Option<(QualifiedColumn<'_>, QualifiedColumn<'_>)> {
+    let Some(NodeEnum::AExpr(ref expr)) = node.node else {
+        return None;
+    };
+
+    if expr.kind() != AExprKind::AexprOp {
+        return None;
+    }
+
+    match expr.name.first().and_then(|name| name.node.as_ref()) {
+        Some(NodeEnum::String(op)) if op.sval == "=" => (),
+        _ => return None,
+    }
+
+    Some((column(expr.lexpr.as_ref()?)?, column(expr.rexpr.as_ref()?)?))
+}
+
+#[cfg(test)]
+mod test {
+    use pg_query::parse;
+
+    use super::*;
+
+    #[test]
+    fn test_from_clause() {
+        let ast = parse(
+            "SELECT * FROM orders o
+                JOIN customers c ON o.customer_id = c.id
+                JOIN payments ON payments.order_id = o.id, items i
+            WHERE i.customer_id = c.id AND o.total > 5",
+        )
+        .unwrap();
+        let stmt = ast.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+        let Some(NodeEnum::SelectStmt(ref stmt)) = stmt.node else {
+            panic!("not a select");
+        };
+
+        let from = FromClause::new(&stmt.from_clause, &stmt.where_clause);
+        assert!(from.join());
+        assert_eq!(
+            from.relations(),
+            &[
+                Relation {
+                    name: "orders",
+                    alias: "o"
+                },
+                Relation {
+                    name: "customers",
+                    alias: "c"
+                },
+                Relation {
+                    name: "payments",
+                    alias: "payments"
+                },
+                Relation {
+                    name: "items",
+                    alias: "i"
+                },
+            ]
+        );
+
+        let mut equivalent = from.equivalent("c", "id");
+        equivalent.sort();
+        assert_eq!(
+            equivalent,
+            vec![("c", "id"), ("i", "customer_id"), ("o", "customer_id")]
+        );
+        assert_eq!(
+            from.equivalent("payments", "order_id"),
+            vec![("payments", "order_id"), ("o", "id")]
+        );
+        assert_eq!(from.equivalent("o", "total"), vec![("o", "total")]);
+    }
+}
@@ -1,8 +1,13 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use once_cell::sync::Lazy;
 use pg_query::{protobuf, Node, NodeEnum};
 
+use crate::{
+    backend::{schema::Volatility, Schema},
+    config::General,
+};
+
 static WRITE_ONLY: Lazy<HashMap<&'static str, LockingBehavior>> = Lazy::new(|| {
     HashMap::from([
         ("pg_advisory_lock", LockingBehavior::Lock),
@@ -31,6 +36,8 @@ pub enum LockingBehavior {
 pub struct FunctionBehavior {
     pub writes: bool,
     pub locking_behavior: LockingBehavior,
+    /// Calls a function listed in `read_functions`, which could be volatile.
+    pub volatile: bool,
 }
 
 impl FunctionBehavior {
@@ -40,6 +47,33 @@ impl FunctionBehavior {
             ..Default::default()
         }
     }
+
+    /// Combine with the behavior of another function called by the same statement.
+    pub fn merge(&mut self, other: FunctionBehavior) {
+        self.writes |= other.writes;
+        self.volatile |= other.volatile;
+        self.locking_behavior = match (self.locking_behavior, other.locking_behavior) {
+            (LockingBehavior::Lock, _) | (_, LockingBehavior::Lock) => LockingBehavior::Lock,
+            (LockingBehavior::Unlock, _) | (_, LockingBehavior::Unlock) => LockingBehavior::Unlock,
+            _ => LockingBehavior::None,
+        };
+    }
+}
+
+/// Functions listed in `write_functions` and `read_functions`.
+#[derive(Debug, Default)]
+pub struct FunctionLists<'a> {
+    write: HashSet<&'a str>,
+    read: HashSet<&'a str>,
+}
+
+impl<'a> FunctionLists<'a> {
+    pub fn new(general: &'a General) -> Self {
+        Self {
+            write: general.write_functions.iter().map(|s| s.as_str()).collect(),
+            read: general.read_functions.iter().map(|s| s.as_str()).collect(),
+        }
+    }
 }
 
 pub struct Function<'a> {
@@ -58,12 +92,27 @@ impl<'a> Function<'a> {
     }
 
     /// This function likely writes.
-    pub fn behavior(&self) -> FunctionBehavior {
+    ///
+    /// Functions declared `VOLATILE` in the database are sent to the primary,
+    /// unless they are listed in `read_functions`.
+    pub fn behavior(&self, schema: &Schema, lists: &FunctionLists) -> FunctionBehavior {
         if let Some(locks) = WRITE_ONLY.get(&self.name) {
-            FunctionBehavior {
+            return FunctionBehavior {
                 writes: true,
                 locking_behavior: *locks,
+                ..Default::default()
+            };
+        }
+
+        if lists.write.contains(self.name) {
+            FunctionBehavior::writes_only()
+        } else if lists.read.contains(self.name) {
+            FunctionBehavior {
+                volatile: true,
+                ..Default::default()
             }
+        } else if schema.volatility(self.name) == Some(Volatility::Volatile) {
+            FunctionBehavior::writes_only()
         } else {
             FunctionBehavior::default()
         }
@@ -74,11 +123,7 @@ impl<'a> TryFrom<&'a Node> for Function<'a> {
     type Error = ();
     fn try_from(value: &'a Node) -> Result<Self, Self::Error> {
         match &value.node {
-            Some(NodeEnum::FuncCall(func)) => {
-                if let Some(node) = func.funcname.last() {
-                    return Self::from_string(&node.node);
-                }
-            }
+            Some(NodeEnum::FuncCall(func)) => return Self::try_from(func.as_ref()),
 
             Some(NodeEnum::TypeCast(cast)) => {
                 if let Some(node) = cast.arg.as_ref() {
@@ -99,6 +144,16 @@ impl<'a> TryFrom<&'a Node> for Function<'a> {
     }
 }
 
+impl<'a> TryFrom<&'a protobuf::FuncCall> for Function<'a> {
+    type Error = ();
+    fn try_from(value: &'a protobuf::FuncCall) -> Result<Self, Self::Error> {
+        match value.funcname.last() {
+            Some(node) => Self::from_string(&node.node),
+            None => Err(()),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use pg_query::parse;
@@ -116,10 +171,49 @@ mod test {
                 for node in &stmt.target_list {
                     let func = Function::try_from(node).unwrap();
                     assert!(func.name.contains("advisory_lock"));
+                    assert!(
+                        func.behavior(&Schema::default(), &FunctionLists::default())
+                            .writes
+                    );
                 }
             }
 
             _ => panic!("not a select"),
         }
     }
+
+    #[test]
+    fn test_function_volatility() {
+        let schema = Schema::new_test(&[
+            ("create_order", Volatility::Volatile),
+            ("get_order", Volatility::Stable),
+        ]);
+
+        let lists = FunctionLists::default();
+
+        let func = Function {
+            name: "create_order",
+        };
+        assert!(func.behavior(&schema, &lists).writes);
+        assert!(!func.behavior(&Schema::default(), &lists).writes);
+
+        let func = Function { name: "get_order" };
+        assert!(!func.behavior(&schema, &lists).writes);
+
+        let func = Function { name: "random" };
+        assert!(!func.behavior(&schema, &lists).writes);
+
+        // Configured lists take precedence over volatility.
+        let mut general = crate::config::General::default();
+        general.read_functions = vec!["create_order".into()];
+        general.write_functions = vec!["get_order".into()];
+        let lists = FunctionLists::new(&general);
+        let func = Function {
+            name: "create_order",
+        };
+        assert!(!func.behavior(&schema, &lists).writes);
+        assert!(func.behavior(&schema, &lists).volatile);
+        let func = Function { name: "get_order" };
+        assert!(func.behavior(&schema, &lists).writes);
+    }
 }
@@ -5,7 +5,7 @@ use crate::{
     backend::ShardingSchema,
     frontend::router::{
         round_robin,
-        sharding::{ContextBuilder, Tables, Value as ShardingValue},
+        sharding::{json, ContextBuilder, Tables, Value as ShardingValue},
     },
     net::Bind,
 };
@@ -70,8 +70,14 @@ impl<'a> Insert<'a> {
         let key = table.and_then(|table| tables.key(table, &columns));
 
         if let Some(key) = key {
+            let json_column = key.table.json_path.is_some();
+
             if let Some(bind) = bind {
                 if let Ok(Some(param)) = bind.parameter(key.position) {
+                    if json_column {
+                        return Ok(json::shard(key.table, param.data(), schema.shards)?);
+                    }
+
                     let value = ShardingValue::from_param(&param, key.table.data_type)?;
                     let ctx = ContextBuilder::new(key.table)
                         .value(value)
@@ -89,7 +95,7 @@ impl<'a> Insert<'a> {
 
                 if let Some(value) = tuples.first().and_then(|tuple| tuple.get(key.position)) {
                     match value {
-                        Value::Integer(int) => {
+                        Value::Integer(int) if !json_column => {
                             let ctx = ContextBuilder::new(key.table)
                                 .data(*int)
                                 .shards(schema.shards)
@@ -97,6 +103,10 @@ impl<'a> Insert<'a> {
                             return Ok(ctx.apply()?);
                         }
 
+                        Value::String(str) if json_column => {
+                            return Ok(json::shard(key.table, str.as_bytes(), schema.shards)?);
+                        }
+
                         Value::String(str) => {
                             let ctx = ContextBuilder::new(key.table)
                                 .data(*str)
@@ -296,4 +306,53 @@ mod test {
             _ => panic!("not a select"),
         }
     }
+
+    #[test]
+    fn test_shard_insert_json() {
+        let schema = ShardingSchema {
+            shards: 3,
+            tables: ShardedTables::new(
+                vec![ShardedTable {
+                    name: Some("events".into()),
+                    column: "data".into(),
+                    json_path: Some("tenant_id".into()),
+                    ..Default::default()
+                }],
+                vec![],
+                false,
+            ),
+        };
+
+        let query =
+            parse(r#"INSERT INTO events (id, data) VALUES (5, '{"tenant_id": 1}')"#).unwrap();
+        let select = query.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+
+        match &select.node {
+            Some(NodeEnum::InsertStmt(stmt)) => {
+                let insert = Insert::new(stmt);
+                // Same shard as the BIGINT 1.
+                let shard = insert.shard(&schema, None).unwrap();
+                assert!(matches!(shard, Shard::Direct(2)));
+
+                let json = r#"{"tenant_id": "3", "kind": "click"}"#;
+                let bind = Bind::test_params(
+                    "",
+                    &[
+                        Parameter {
+                            len: 1,
+                            data: "5".as_bytes().to_vec(),
+                        },
+                        Parameter {
+                            len: json.len() as i32,
+                            data: json.as_bytes().to_vec(),
+                        },
+                    ],
+                );
+                let shard = insert.shard(&schema, Some(&bind)).unwrap();
+                assert!(matches!(shard, Shard::Direct(1)));
+            }
+
+            _ => panic!("not an insert"),
+        }
+    }
 }
@@ -0,0 +1,266 @@
+//! Handle `INSERT ... SELECT` statements targeting sharded tables.
+//!
+//! The `SELECT` part is executed on its source shard(s) first.
+//! Returned rows are then re-sharded using the target table's sharding key
+//! and inserted into the right shards.
+use pg_query::{
+    parse,
+    protobuf::{a_const::Val, *},
+    NodeEnum,
+};
+
+use crate::{
+    backend::ShardingSchema,
+    config::ShardedTable,
+    frontend::router::sharding::{ContextBuilder, Tables},
+    net::messages::DataRow,
+};
+
+use super::{Error, Insert, Shard};
+
+/// Maximum number of rows sent in one `INSERT` statement.
+pub const INSERT_SELECT_BATCH: usize = 1000;
+
+/// `INSERT ... SELECT` execution plan.
+#[derive(Debug, Clone)]
+pub struct InsertSelect {
+    /// Sharded table rows are inserted into.
+    table: ShardedTable,
+    /// Position of the sharding key in the `SELECT` output.
+    key: usize,
+    /// Number of columns in the target column list.
+    columns: usize,
+    /// Number of shards in the cluster.
+    shards: usize,
+    /// Shard(s) the `SELECT` is executed on.
+    source: Shard,
+    /// The `SELECT` statement.
+    select: std::string::String,
+    /// `INSERT` statement used as a template for re-sharded rows.
+    insert: InsertStmt,
+}
+
+impl InsertSelect {
+    /// Plan an `INSERT ... SELECT` statement.
+    ///
+    /// Returns `None` if the statement is a regular `INSERT ... VALUES` or
+    /// the target table isn't sharded.
+    pub fn new(stmt: &InsertStmt, schema: &ShardingSchema) -> Result<Option<Self>, Error> {
+        let Some(NodeEnum::SelectStmt(select)) = stmt
+            .select_stmt
+            .as_ref()
+            .and_then(|node| node.node.as_ref())
+        else {
+            return Ok(None);
+        };
+
+        // Regular INSERT ... VALUES.
+        if !select.values_lists.is_empty() {
+            return Ok(None);
+        }
+
+        let insert = Insert::new(stmt);
+        let Some(table) = insert.table() else {
+            return Ok(None);
+        };
+        let tables = Tables::new(schema);
+        let columns = insert.columns();
+
+        let key = match tables.key(table, &columns) {
+            Some(key) => key,
+            None => {
+                if tables.sharded(table).is_some() && columns.is_empty() {
+                    return Err(Error::InsertSelectColumns(table.name.to_owned()));
+                }
+                return Ok(None);
+            }
+        };
+
+        if !stmt.returning_list.is_empty() {
+            return Err(Error::InsertSelectReturning);
+        }
+
+        // CTEs attached to the INSERT are needed by the SELECT only.
+        let mut select = select.clone();
+        if select.with_clause.is_none() {
+            select.with_clause = stmt.with_clause.clone();
+        }
+        let select = NodeEnum::SelectStmt(select)
+            .deparse()
+            .map_err(Error::PgQuery)?;
+
+        let mut template = stmt.clone();
+        template.select_stmt = None;
+        template.with_clause = None;
+
+        Ok(Some(Self {
+            table: key.table.clone(),
+            key: key.position,
+            columns: columns.len(),
+            shards: schema.shards,
+            source: Shard::All,
+            select,
+            insert: template,
+        }))
+    }
+
+    /// The `SELECT` statement to execute on the source shard(s).
+    pub fn select(&self) -> &str {
+        &self.select
+    }
+
+    /// Tables referenced by the `SELECT` statement.
+    pub fn tables(&self) -> Result<Vec<std::string::String>, Error> {
+        Ok(parse(&self.select).map_err(Error::PgQuery)?.tables())
+    }
+
+    /// Shard(s) the `SELECT` statement is executed on.
+    pub fn source(&self) -> &Shard {
+        &self.source
+    }
+
+    /// Set shard(s) the `SELECT` statement is executed on.
+    pub fn set_source(&mut self, source: Shard) {
+        self.source = source;
+    }
+
+    /// Number of columns each row must have.
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Get the shard number for a row returned by the `SELECT` statement.
+    pub fn shard(&self, row: &DataRow) -> Result<usize, Error> {
+        if row.is_null(self.key) {
+            return Err(Error::InsertSelectNullKey(self.table.column.clone()));
+        }
+        let value = row.get_text(self.key).unwrap_or_default();
+        let ctx = ContextBuilder::new(&self.table)
+            .data(value.as_str())
+            .shards(self.shards)
+            .build()?;
+
+        match ctx.apply()? {
+            Shard::Direct(shard) => Ok(shard),
+            _ => Err(Error::InsertSelectShard(self.table.column.clone())),
+        }
+    }
+
+    /// Build an `INSERT` statement for a batch of rows.
+    ///
+    /// Values are sent as text literals and cast by Postgres
+    /// to the target column types.
+    pub fn insert(&self, rows: &[DataRow]) -> Result<std::string::String, Error> {
+        let values_lists = rows
+            .iter()
+            .map(|row| {
+                let items = (0..self.columns)
+                    .map(|column| Node {
+                        node: Some(NodeEnum::AConst(if row.is_null(column) {
+                            AConst {
+                                isnull: true,
+                                location: -1,
+                                val: None,
+                            }
+                        } else {
+                            AConst {
+                                isnull: false,
+                                location: -1,
+                                val: Some(Val::Sval(String {
+                                    sval: row.get_text(column).unwrap_or_default(),
+                                })),
+                            }
+                        })),
+                    })
+                    .collect();
+                Node {
+                    node: Some(NodeEnum::List(List { items })),
+                }
+            })
+            .collect();
+
+        let mut insert = self.insert.clone();
+        insert.select_stmt = Some(Box::new(Node {
+            node: Some(NodeEnum::SelectStmt(Box::new(SelectStmt {
+                values_lists,
+                op: SetOperation::SetopNone.into(),
+                limit_option: LimitOption::Default.into(),
+                ..Default::default()
+            }))),
+        }));
+
+        NodeEnum::InsertStmt(Box::new(insert))
+            .deparse()
+            .map_err(Error::PgQuery)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{backend::Cluster, net::messages::data_row::Data};
+
+    use super::*;
+
+    fn plan(query: &str) -> Result<Option<InsertSelect>, Error> {
+        let cluster = Cluster::new_test();
+        let ast = parse(query).unwrap();
+        let stmt = ast.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+        match stmt.node {
+            Some(NodeEnum::InsertStmt(ref stmt)) => {
+                InsertSelect::new(stmt, &cluster.sharding_schema())
+            }
+            _ => panic!("not an insert"),
+        }
+    }
+
+    #[test]
+    fn test_insert_select() {
+        let plan = plan(
+            "WITH s AS (SELECT id, email FROM users) INSERT INTO sharded (id, email) SELECT id, email FROM s",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            plan.select(),
+            "WITH s AS (SELECT id, email FROM users) SELECT id, email FROM s"
+        );
+        assert_eq!(plan.columns(), 2);
+        assert_eq!(plan.tables().unwrap(), vec!["users".to_string()]);
+
+        let mut row = DataRow::new();
+        row.add(1_i64).add("test@test.com");
+        let mut null = DataRow::new();
+        null.add(2_i64).add(Data::null());
+
+        let shard = plan.shard(&row).unwrap();
+        assert!(shard < 2);
+
+        let insert = plan.insert(&[row, null]).unwrap();
+        assert_eq!(
+            insert,
+            "INSERT INTO sharded (id, email) VALUES ('1', 'test@test.com'), ('2', NULL)"
+        );
+    }
+
+    #[test]
+    fn test_insert_values_not_planned() {
+        assert!(plan("INSERT INTO sharded (id) VALUES (1)")
+            .unwrap()
+            .is_none());
+        assert!(plan("INSERT INTO not_sharded (id) SELECT id FROM sharded")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_insert_select_errors() {
+        assert!(matches!(
+            plan("INSERT INTO sharded SELECT * FROM users"),
+            Err(Error::InsertSelectColumns(_))
+        ));
+        assert!(matches!(
+            plan("INSERT INTO sharded (id) SELECT id FROM users RETURNING *"),
+            Err(Error::InsertSelectReturning)
+        ));
+    }
+}
@@ -0,0 +1,588 @@
+//! Equi-joins between two tables that don't live on the same shards.
+//!
+//! Rows are fetched from one side of the join first. Their join keys are
+//! then pushed to the other side's shards as `= ANY($1)` and matching rows
+//! are joined in pgdog.
+use pg_query::{
+    protobuf::{
+        AExpr, AExprKind, AStar, BoolExpr, BoolExprType, ColumnRef, JoinType, LimitOption, Node,
+        ParamRef, RangeVar, ResTarget, SelectStmt, SetOperation, String as PgString,
+    },
+    NodeEnum, NodeRef,
+};
+
+use crate::{backend::ShardingSchema, config::ShardedTable};
+
+use super::{
+    from_clause::{equality, split},
+    Error, Route, Shard,
+};
+
+/// Column in the join's target list.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JoinTarget {
+    /// `SELECT *`
+    Star,
+    /// `SELECT t.*`
+    Table(usize),
+    /// `SELECT t.column [AS name]`
+    Column {
+        side: usize,
+        column: String,
+        name: String,
+    },
+}
+
+/// One of the two tables in a join.
+#[derive(Debug, Clone)]
+pub struct JoinSide {
+    /// Table name.
+    pub table: String,
+    /// Name used to reference the table in the query.
+    pub alias: String,
+    /// Join key column.
+    pub column: String,
+    /// Shard(s) containing this side's rows.
+    pub route: Route,
+    /// Fetch all rows from this side matching the filters.
+    pub query: String,
+    /// Fetch rows matching the filters and the join keys passed in as `$1`.
+    pub lookup: String,
+    /// Sharding config, if the join key is this table's sharding key.
+    pub sharded: Option<ShardedTable>,
+    /// Table isn't sharded, all shards have the same rows.
+    pub unsharded: bool,
+}
+
+/// Two-table equi-join executed by pgdog.
+#[derive(Debug, Clone)]
+pub struct Join {
+    /// Tables, in the order they appear in the `FROM` clause.
+    sides: [JoinSide; 2],
+    /// Side fetched first.
+    outer: usize,
+    /// Target list.
+    targets: Vec<JoinTarget>,
+}
+
+impl Join {
+    /// Plan a join.
+    ///
+    /// Returns `None` if the statement isn't a two-table inner equi-join pgdog can execute,
+    /// or if the tables are sharded on the join key and the join can run on the shards.
+    pub fn new(
+        stmt: &SelectStmt,
+        schema: &ShardingSchema,
+        route: impl Fn(&SelectStmt) -> Result<Route, Error>,
+    ) -> Result<Option<Self>, Error> {
+        if stmt.from_clause.len() != 1
+            || !stmt.group_clause.is_empty()
+            || stmt.having_clause.is_some()
+            || !stmt.sort_clause.is_empty()
+            || stmt.limit_count.is_some()
+            || stmt.limit_offset.is_some()
+            || !stmt.distinct_clause.is_empty()
+            || !stmt.window_clause.is_empty()
+            || !stmt.locking_clause.is_empty()
+            || !stmt.values_lists.is_empty()
+            || stmt.with_clause.is_some()
+            || stmt.into_clause.is_some()
+            || stmt.op() != SetOperation::SetopNone
+        {
+            return Ok(None);
+        }
+
+        let Some(NodeEnum::JoinExpr(ref join)) = stmt.from_clause[0].node else {
+            return Ok(None);
+        };
+
+        if join.jointype() != JoinType::JoinInner
+            || join.is_natural
+            || !join.using_clause.is_empty()
+            || join.alias.is_some()
+        {
+            return Ok(None);
+        }
+
+        let (Some(left), Some(right)) = (range_var(&join.larg), range_var(&join.rarg)) else {
+            return Ok(None);
+        };
+        let tables = [left, right];
+        let aliases = [alias(left), alias(right)];
+
+        if aliases[0] == aliases[1] {
+            return Ok(None);
+        }
+
+        // Split ON and WHERE into conjuncts, find the join key
+        // and assign the rest to either side.
+        let mut conjuncts = vec![];
+        if let Some(ref quals) = join.quals {
+            split(quals, &mut conjuncts);
+        }
+        if let Some(ref where_clause) = stmt.where_clause {
+            split(where_clause, &mut conjuncts);
+        }
+
+        let mut keys: Option<[String; 2]> = None;
+        let mut filters: [Vec<Node>; 2] = [vec![], vec![]];
+
+        for conjunct in conjuncts {
+            if keys.is_none() {
+                if let Some(found) = join_key(conjunct, &aliases) {
+                    keys = Some(found);
+                    continue;
+                }
+            }
+
+            match side(conjunct, &aliases) {
+                Some(side) => filters[side].push(conjunct.clone()),
+                None => return Ok(None),
+            }
+        }
+
+        let Some(keys) = keys else {
+            return Ok(None);
+        };
+
+        let mut targets = vec![];
+        for target in &stmt.target_list {
+            match join_target(target, &aliases) {
+                Some(target) => targets.push(target),
+                None => return Ok(None),
+            }
+        }
+
+        // Tables with the same data on all shards can be joined on the shards.
+        if tables
+            .iter()
+            .any(|t| schema.tables.omnishards().contains(&t.relname))
+        {
+            return Ok(None);
+        }
+
+        let sharded = [
+            schema.tables.table(&left.relname),
+            schema.tables.table(&right.relname),
+        ];
+
+        // Nothing is sharded, the join is correct on any shard.
+        if sharded.iter().all(|t| t.is_none()) {
+            return Ok(None);
+        }
+
+        let on_key = [0, 1].map(|side| {
+            sharded[side]
+                .filter(|table| table.column == keys[side])
+                .cloned()
+        });
+
+        // Both tables are co-located and sharded on the join key, rows are on the same shard.
+        if on_key.iter().all(|t| t.is_some())
+            && schema.tables.co_located(&left.relname, &right.relname)
+        {
+            return Ok(None);
+        }
+
+        let mut sides = vec![];
+        for (side, table) in tables.iter().enumerate() {
+            let fetch = side_stmt(table, &filters[side], None);
+            let lookup = side_stmt(
+                table,
+                &filters[side],
+                Some(any(&aliases[side], &keys[side])),
+            );
+
+            sides.push(JoinSide {
+                table: table.relname.clone(),
+                alias: aliases[side].clone(),
+                column: keys[side].clone(),
+                route: route(&fetch)?,
+                query: deparse(fetch)?,
+                lookup: deparse(lookup)?,
+                sharded: on_key[side].clone(),
+                unsharded: sharded[side].is_none(),
+            });
+        }
+
+        let sides: [JoinSide; 2] = sides.try_into().map_err(|_| Error::EmptyQuery)?;
+
+        // Fetch the side with a direct route first, since it's cheap.
+        // Otherwise, fetch the side that isn't sharded on the join key, so lookups
+        // can be sent directly to the shards that have the matching rows.
+        let outer = if matches!(sides[1].route.shard(), Shard::Direct(_))
+            && !matches!(sides[0].route.shard(), Shard::Direct(_))
+        {
+            1
+        } else if matches!(sides[0].route.shard(), Shard::Direct(_)) {
+            0
+        } else if sides[0].sharded.is_some() {
+            1
+        } else {
+            0
+        };
+
+        Ok(Some(Self {
+            sides,
+            outer,
+            targets,
+        }))
+    }
+
+    /// Side of the join fetched first.
+    pub fn outer(&self) -> &JoinSide {
+        &self.sides[self.outer]
+    }
+
+    /// Side of the join fetched using join keys from the outer side.
+    pub fn inner(&self) -> &JoinSide {
+        &self.sides[1 - self.outer]
+    }
+
+    /// Position of the outer side in the `FROM` clause.
+    pub fn outer_position(&self) -> usize {
+        self.outer
+    }
+
+    /// Tables, in the order they appear in the `FROM` clause.
+    pub fn sides(&self) -> &[JoinSide; 2] {
+        &self.sides
+    }
+
+    /// Target list.
+    pub fn targets(&self) -> &[JoinTarget] {
+        &self.targets
+    }
+}
+
+fn range_var(node: &Option<Box<Node>>) -> Option<&RangeVar> {
+    match node.as_ref().and_then(|node| node.node.as_ref()) {
+        Some(NodeEnum::RangeVar(range_var)) => Some(range_var),
+        _ => None,
+    }
+}
+
+fn alias(range_var: &RangeVar) -> String {
+    range_var
+        .alias
+        .as_ref()
+        .map(|alias| alias.aliasname.clone())
+        .unwrap_or(range_var.relname.clone())
+}
+
+/// Find the `a.x = b.y` join predicate.
+fn join_key(node: &Node, aliases: &[String; 2]) -> Option<[String; 2]> {
+    let (lexpr, rexpr) = equality(node)?;
+
+    if lexpr.0 == aliases[0] && rexpr.0 == aliases[1] {
+        Some([lexpr.1.to_owned(), rexpr.1.to_owned()])
+    } else if lexpr.0 == aliases[1] && rexpr.0 == aliases[0] {
+        Some([rexpr.1.to_owned(), lexpr.1.to_owned()])
+    } else {
+        None
+    }
+}
+
+/// Figure out which side of the join the filter belongs to.
+fn side(node: &Node, aliases: &[String; 2]) -> Option<usize> {
+    let nodes = node.node.as_ref()?.nodes();
+    let mut side = None;
+
+    for (node, _, _, _) in nodes {
+        match node {
+            // Subqueries can reference either table.
+            NodeRef::SubLink(_) => return None,
+            NodeRef::ColumnRef(column_ref) => {
+                let table = match column_ref.fields.as_slice() {
+                    [table, _] => match table.node {
+                        Some(NodeEnum::String(ref table)) => table.sval.as_str(),
+                        _ => return None,
+                    },
+                    // Unqualified columns are ambiguous without the schema.
+                    _ => return None,
+                };
+                let found = aliases.iter().position(|alias| alias == table)?;
+                if side.is_some_and(|side| side != found) {
+                    return None;
+                }
+                side = Some(found);
+            }
+            _ => (),
+        }
+    }
+
+    side
+}
+
+fn join_target(node: &Node, aliases: &[String; 2]) -> Option<JoinTarget> {
+    let Some(NodeEnum::ResTarget(ref target)) = node.node else {
+        return None;
+    };
+    let Some(NodeEnum::ColumnRef(ref column_ref)) = target.val.as_ref()?.node else {
+        return None;
+    };
+
+    match column_ref.fields.as_slice() {
+        [star] if matches!(star.node, Some(NodeEnum::AStar(_))) => Some(JoinTarget::Star),
+        [table, field] => {
+            let Some(NodeEnum::String(ref table)) = table.node else {
+                return None;
+            };
+            let side = aliases.iter().position(|alias| alias == &table.sval)?;
+            match field.node {
+                Some(NodeEnum::AStar(_)) => Some(JoinTarget::Table(side)),
+                Some(NodeEnum::String(ref column)) => Some(JoinTarget::Column {
+                    side,
+                    column: column.sval.clone(),
+                    name: if target.name.is_empty() {
+                        column.sval.clone()
+                    } else {
+                        target.name.clone()
+                    },
+                }),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// `alias.column = ANY($1)`
+fn any(alias: &str, column: &str) -> Node {
+    let string = |sval: &str| Node {
+        node: Some(NodeEnum::String(PgString {
+            sval: sval.to_owned(),
+        })),
+    };
+
+    Node {
+        node: Some(NodeEnum::AExpr(Box::new(AExpr {
+            kind: AExprKind::AexprOpAny.into(),
+            name: vec![string("=")],
+            lexpr: Some(Box::new(Node {
+                node: Some(NodeEnum::ColumnRef(ColumnRef {
+                    fields: vec![string(alias), string(column)],
+                    location: -1,
+                })),
+            })),
+            rexpr: Some(Box::new(Node {
+                node: Some(NodeEnum::ParamRef(ParamRef {
+                    number: 1,
+                    location: -1,
+                })),
+            })),
+            location: -1,
+        }))),
+    }
+}
+
+/// `SELECT * FROM table WHERE filters`
+fn side_stmt(table: &RangeVar, filters: &[Node], key: Option<Node>) -> SelectStmt {
+    let mut args = filters.to_vec();
+    args.extend(key);
+
+    let where_clause = match args.len() {
+        0 => None,
+        1 => args.pop().map(Box::new),
+        _ => Some(Box::new(Node {
+            node: Some(NodeEnum::BoolExpr(Box::new(BoolExpr {
+                xpr: None,
+                boolop: BoolExprType::AndExpr.into(),
+                args,
+                location: -1,
+            }))),
+        })),
+    };
+
+    SelectStmt {
+        target_list: vec![Node {
+            node: Some(NodeEnum::ResTarget(Box::new(ResTarget {
+                name: String::new(),
+                indirection: vec![],
+                val: Some(Box::new(Node {
+                    node: Some(NodeEnum::ColumnRef(ColumnRef {
+                        fields: vec![Node {
+                            node: Some(NodeEnum::AStar(AStar {})),
+                        }],
+                        location: -1,
+                    })),
+                })),
+                location: -1,
+            }))),
+        }],
+        from_clause: vec![Node {
+            node: Some(NodeEnum::RangeVar(table.clone())),
+        }],
+        where_clause,
+        op: SetOperation::SetopNone.into(),
+        limit_option: LimitOption::Default.into(),
+        ..Default::default()
+    }
+}
+
+fn deparse(stmt: SelectStmt) -> Result<String, Error> {
+    NodeEnum::SelectStmt(Box::new(stmt))
+        .deparse()
+        .map_err(Error::PgQuery)
+}
+
+#[cfg(test)]
+mod test {
+    use pg_query::parse;
+
+    use crate::{
+        backend::{replication::ShardedTables, Cluster},
+        config::DataType,
+    };
+
+    use super::*;
+
+    fn plan(query: &str, schema: &ShardingSchema) -> Option<Join> {
+        let ast = parse(query).unwrap();
+        let stmt = ast.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+        match stmt.node {
+            Some(NodeEnum::SelectStmt(ref stmt)) => {
+                Join::new(stmt, schema, |_| Ok(Route::read(Shard::All))).unwrap()
+            }
+            _ => panic!("not a select"),
+        }
+    }
+
+    fn schema() -> ShardingSchema {
+        ShardingSchema {
+            shards: 2,
+            tables: ShardedTables::new(
+                vec![
+                    ShardedTable {
+                        database: "pgdog".into(),
+                        name: Some("orders".into()),
+                        column: "customer_id".into(),
+                        ..Default::default()
+                    },
+                    ShardedTable {
+                        database: "pgdog".into(),
+                        name: Some("customers".into()),
+                        column: "id".into(),
+                        ..Default::default()
+                    },
+                ],
+                vec!["countries".into()],
+                false,
+            ),
+        }
+    }
+
+    #[test]
+    fn test_join() {
+        let join = plan(
+            "SELECT o.id, p.name AS product FROM orders o JOIN products p ON o.product_id = p.id WHERE o.customer_id = 1 AND p.active",
+            &schema(),
+        )
+        .unwrap();
+
+        assert_eq!(join.sides()[0].table, "orders");
+        assert_eq!(join.sides()[0].column, "product_id");
+        assert_eq!(
+            join.sides()[0].query,
+            "SELECT * FROM orders o WHERE o.customer_id = 1"
+        );
+        assert_eq!(
+            join.sides()[1].lookup,
+            "SELECT * FROM products p WHERE p.active AND p.id = ANY($1)"
+        );
+        assert!(join.sides()[0].sharded.is_none());
+        assert!(!join.sides()[0].unsharded);
+        assert!(join.sides()[1].unsharded);
+        assert_eq!(join.outer().table, "orders");
+        assert_eq!(
+            join.targets(),
+            &[
+                JoinTarget::Column {
+                    side: 0,
+                    column: "id".into(),
+                    name: "id".into()
+                },
+                JoinTarget::Column {
+                    side: 1,
+                    column: "name".into(),
+                    name: "product".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_join_lookup_on_sharding_key() {
+        let join = plan(
+            "SELECT * FROM customers c JOIN orders o ON c.email = o.email",
+            &schema(),
+        )
+        .unwrap();
+        assert_eq!(join.targets(), &[JoinTarget::Star]);
+        assert_eq!(join.outer().table, "customers");
+
+        let join = plan(
+            "SELECT * FROM orders o JOIN customers c ON o.id = c.id",
+            &schema(),
+        )
+        .unwrap();
+        assert_eq!(join.outer().table, "orders");
+        assert!(join.inner().sharded.is_some());
+    }
+
+    #[test]
+    fn test_join_not_planned() {
+        let schema = schema();
+
+        // Co-located.
+        assert!(plan(
+            "SELECT * FROM orders o JOIN customers c ON o.customer_id = c.id",
+            &schema
+        )
+        .is_none());
+        // Omnisharded.
+        assert!(plan(
+            "SELECT * FROM orders o JOIN countries c ON o.country_id = c.id",
+            &schema
+        )
+        .is_none());
+        // Not sharded.
+        assert!(plan("SELECT * FROM a JOIN b ON a.id = b.id", &schema).is_none());
+        // Unqualified column.
+        assert!(plan(
+            "SELECT email FROM orders o JOIN products p ON o.product_id = p.id",
+            &schema
+        )
+        .is_none());
+        // Filter references both tables.
+        assert!(plan(
+            "SELECT * FROM orders o JOIN products p ON o.product_id = p.id WHERE o.price > p.price",
+            &schema
+        )
+        .is_none());
+        // Outer join.
+        assert!(plan(
+            "SELECT * FROM orders o LEFT JOIN products p ON o.product_id = p.id",
+            &schema
+        )
+        .is_none());
+        // Single table.
+        let cluster = Cluster::new_test();
+        assert!(plan("SELECT * FROM sharded", &cluster.sharding_schema()).is_none());
+    }
+
+    #[test]
+    fn test_join_not_co_located() {
+        let mut schema = schema();
+        let mut tables = schema.tables.tables().to_vec();
+        tables[1].data_type = DataType::Uuid;
+        schema.tables = ShardedTables::new(tables, vec![], false);
+
+        let join = plan(
+            "SELECT * FROM orders o JOIN customers c ON o.customer_id = c.id",
+            &schema,
+        )
+        .unwrap();
+        assert!(join.sides().iter().all(|side| side.sharded.is_some()));
+    }
+}
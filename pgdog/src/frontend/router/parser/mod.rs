@@ -8,9 +8,14 @@ pub mod command;
 pub mod comment;
 pub mod copy;
 pub mod csv;
+pub mod cursor;
 pub mod error;
+pub mod explain;
+pub mod from_clause;
 pub mod function;
 pub mod insert;
+pub mod insert_select;
+pub mod join;
 pub mod key;
 pub mod limit;
 pub mod multi_tenant;
@@ -18,7 +23,9 @@ pub mod order_by;
 pub mod prepare;
 pub mod query;
 pub mod rewrite;
+pub mod role;
 pub mod route;
+pub mod strict;
 pub mod table;
 pub mod tuple;
 pub mod value;
@@ -31,16 +38,23 @@ pub use column::Column;
 pub use command::Command;
 pub use copy::{CopyFormat, CopyParser};
 pub use csv::{CsvStream, Record};
+pub use cursor::{Cursors, Fetch};
 pub use error::Error;
+pub use explain::Explain;
+pub use from_clause::{FromClause, Relation};
 pub use function::Function;
-pub use function::{FunctionBehavior, LockingBehavior};
+pub use function::{FunctionBehavior, FunctionLists, LockingBehavior};
 pub use insert::Insert;
+pub use insert_select::InsertSelect;
+pub use join::{Join, JoinSide, JoinTarget};
 pub use key::Key;
 pub use limit::{Limit, LimitClause};
 pub use order_by::OrderBy;
 pub use prepare::Prepare;
 pub use query::QueryParser;
-pub use route::{Route, Shard};
+pub use role::TargetRole;
+pub use route::{KeySource, Route, Shard};
+pub use strict::Strict;
 pub use table::Table;
 pub use tuple::Tuple;
 pub use value::Value;
@@ -1,6 +1,6 @@
 //! Sorting columns extracted from the query.
 
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 
 use crate::net::messages::Vector;
 
@@ -54,3 +54,17 @@ impl OrderBy {
         }
     }
 }
+
+impl Display for OrderBy {
+    /// Sort key, referencing columns by name or by position in the target list.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderBy::Asc(column) => write!(f, "${}", column),
+            OrderBy::Desc(column) => write!(f, "${} DESC", column),
+            OrderBy::AscColumn(name) => write!(f, "{}", name),
+            OrderBy::DescColumn(name) => write!(f, "{} DESC", name),
+            OrderBy::AscVectorL2(column, _) => write!(f, "${} <-> vector", column),
+            OrderBy::AscVectorL2Column(name, _) => write!(f, "{} <-> vector", name),
+        }
+    }
+}
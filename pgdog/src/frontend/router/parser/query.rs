@@ -2,8 +2,8 @@
 use std::{collections::HashSet, sync::Arc};
 
 use crate::{
-    backend::{databases::databases, Cluster, ShardingSchema},
-    config::{config, ReadWriteStrategy},
+    backend::{databases::databases, Cluster, Schema, ShardingSchema},
+    config::{config, AllShardsWrites, ReadWriteStrategy, ShardedTable},
     frontend::{
         buffer::BufferedQuery,
         router::{
@@ -29,9 +29,10 @@ use once_cell::sync::Lazy;
 use pg_query::{
     fingerprint, parse,
     protobuf::{a_const::Val, *},
-    NodeEnum,
+    NodeEnum, NodeRef,
 };
 use regex::Regex;
+use role::ROLE_PARAMETER;
 use tracing::{debug, trace};
 
 static REPLICATION_REGEX: Lazy<Regex> = Lazy::new(|| {
@@ -48,6 +49,11 @@ pub struct QueryParser {
     routed: bool,
     in_transaction: bool,
     write_override: Option<bool>,
+    read_only_transaction: bool,
+    role: Option<TargetRole>,
+    cursors: Cursors,
+    cursor_command: Option<Command>,
+    cache: bool,
 }
 
 impl Default for QueryParser {
@@ -58,6 +64,11 @@ impl Default for QueryParser {
             routed: false,
             in_transaction: false,
             write_override: None,
+            read_only_transaction: false,
+            role: None,
+            cursors: Cursors::default(),
+            cursor_command: None,
+            cache: true,
         }
     }
 }
@@ -68,6 +79,11 @@ impl QueryParser {
         self.replication_mode = true;
     }
 
+    /// Don't use the AST cache, e.g. to route a query without affecting clients.
+    pub fn uncached(&mut self) {
+        self.cache = false;
+    }
+
     /// In transaction.
     pub fn in_transaction(&self) -> bool {
         self.in_transaction
@@ -75,7 +91,7 @@ impl QueryParser {
 
     pub fn parse(&mut self, context: RouterContext) -> Result<&Command, Error> {
         if let Some(ref query) = context.query {
-            self.command = self.query(
+            let command = self.query(
                 query,
                 context.cluster,
                 context.bind,
@@ -84,6 +100,14 @@ impl QueryParser {
                 context.in_transaction,
             )?;
 
+            // Cursor commands don't change the routing decision
+            // for the rest of the transaction.
+            if matches!(command, Command::Fetch(_) | Command::CloseCursor(_)) {
+                return Ok(self.cursor_command.insert(command));
+            }
+
+            self.command = command;
+
             // If the cluster only has one shard, use direct-to-shard queries.
             if let Command::Query(ref mut query) = self.command {
                 if !matches!(query.shard(), Shard::Direct(_)) && context.cluster.shards().len() == 1
@@ -91,6 +115,33 @@ impl QueryParser {
                     query.set_shard_mut(0);
                 }
             }
+
+            // Read-only transactions go to replicas, whatever the statements are.
+            if self.read_only_transaction {
+                if let Command::Query(ref mut query) = self.command {
+                    query.set_read_mut(true);
+                }
+            }
+
+            // Role requested by the client for the transaction or the session.
+            let role = self.role.unwrap_or_else(|| {
+                context
+                    .params
+                    .get(ROLE_PARAMETER)
+                    .and_then(|role| role.as_str())
+                    .and_then(|role| role.parse().ok())
+                    .unwrap_or_default()
+            });
+            if let (Command::Query(ref mut query), Some(read)) = (&mut self.command, role.read()) {
+                query.set_read_mut(read);
+            }
+
+            // Reads marked critical can use the primary if replicas are down.
+            if let Command::Query(ref mut route) = self.command {
+                if route.is_read() && context.cluster.critical_reads() {
+                    route.set_critical_mut(super::comment::critical(query.query())?);
+                }
+            }
         }
 
         Ok(&self.command)
@@ -122,6 +173,10 @@ impl QueryParser {
         self.in_transaction = false;
         self.command = Command::Query(Route::default());
         self.write_override = None;
+        self.read_only_transaction = false;
+        self.role = None;
+        self.cursors.clear();
+        self.cursor_command = None;
     }
 
     fn query(
@@ -151,6 +206,7 @@ impl QueryParser {
         let write_only = cluster.write_only();
         let full_prepared_statements = config().config.general.prepared_statements.full();
         let sharding_schema = cluster.sharding_schema();
+        let schema = cluster.schema();
         let dry_run = sharding_schema.tables.dry_run();
         let multi_tenant = cluster.multi_tenant();
         let router_needed = cluster.router_needed();
@@ -159,8 +215,11 @@ impl QueryParser {
         let rw_strategy = cluster.read_write_strategy();
         self.in_transaction = in_transaction;
 
-        // Route transaction to primary.
-        if in_transaction && rw_strategy == &ReadWriteStrategy::Conservative {
+        // Route transaction to primary, unless it's read-only.
+        if in_transaction
+            && rw_strategy == &ReadWriteStrategy::Conservative
+            && !self.read_only_transaction
+        {
             self.write_override = Some(true);
         }
 
@@ -188,6 +247,13 @@ impl QueryParser {
             }
         }
 
+        // Cursors open on multiple shards are fetched and closed by pgdog.
+        if shards > 1 {
+            if let Some(command) = self.cursor(query, &sharding_schema, bind)? {
+                return Ok(command);
+            }
+        }
+
         // We already decided where all queries for this
         // transaction are going to go.
         if self.routed && multi_tenant.is_none() {
@@ -211,9 +277,14 @@ impl QueryParser {
 
         // Parse hardcoded shard from a query comment.
         // Skipped if cluster isn't sharded.
+        let mut comment_key = None;
         if router_needed && !self.routed && shards > 1 {
             if let BufferedQuery::Query(query) = query {
                 shard = super::comment::shard(query.query(), &sharding_schema)?;
+                if let Shard::Direct(_) = shard {
+                    comment_key =
+                        super::comment::hint(query.query())?.map(|hint| (hint, KeySource::Comment));
+                }
             }
         }
 
@@ -223,11 +294,11 @@ impl QueryParser {
         if !full_prepared_statements && multi_tenant.is_none() {
             if let Shard::Direct(_) = shard {
                 if read_only {
-                    return Ok(Command::Query(Route::read(shard)));
+                    return Ok(Command::Query(Route::read(shard).set_key(comment_key)));
                 }
 
                 if write_only {
-                    return Ok(Command::Query(Route::write(shard)));
+                    return Ok(Command::Query(Route::write(shard).set_key(comment_key)));
                 }
             }
         }
@@ -237,7 +308,9 @@ impl QueryParser {
         // Get the AST from cache or parse the statement live.
         let ast = match query {
             // Only prepared statements (or just extended) are cached.
-            BufferedQuery::Prepared(query) => cache.parse(query.query()).map_err(Error::PgQuery)?,
+            BufferedQuery::Prepared(query) if self.cache => {
+                cache.parse(query.query()).map_err(Error::PgQuery)?
+            }
             // Don't cache simple queries.
             //
             // They contain parameter values, which makes the cache
@@ -246,7 +319,7 @@ impl QueryParser {
             // Make your clients use prepared statements
             // or at least send statements with placeholders using the
             // extended protocol.
-            BufferedQuery::Query(query) => Arc::new(parse(query.query()).map_err(Error::PgQuery)?),
+            query => Arc::new(parse(query.query()).map_err(Error::PgQuery)?),
         };
 
         debug!("{}", query.query());
@@ -273,9 +346,6 @@ impl QueryParser {
         //
         // Get the root AST node.
         //
-        // We don't expect clients to send multiple queries. If they do
-        // only the first one is used for routing.
-        //
         let root = ast
             .protobuf
             .stmts
@@ -285,11 +355,37 @@ impl QueryParser {
             .as_ref()
             .ok_or(Error::EmptyQuery)?;
 
+        // Simple queries can contain multiple statements.
+        // They are sent together, so they must all go to the same shard.
+        let statements =
+            if shards > 1 && !matches!(shard, Shard::Direct(_)) && ast.protobuf.stmts.len() > 1 {
+                self.statements(
+                    &ast.protobuf.stmts,
+                    query,
+                    &sharding_schema,
+                    &schema,
+                    cluster.strict(),
+                    bind,
+                )?
+            } else {
+                None
+            };
+
+        if let Some(route) = statements {
+            self.routed = true;
+            debug!("query router decision: {:#?}", route);
+            if dry_run {
+                cache.record_command(query, &route)?;
+                return Ok(Command::Query(route).dry_run());
+            }
+            return Ok(Command::Query(route));
+        }
+
         let mut command = match root.node {
             // SELECT statements.
             Some(NodeEnum::SelectStmt(ref stmt)) => {
                 let cte_writes = Self::cte_writes(stmt);
-                let mut writes = Self::functions(stmt)?;
+                let mut writes = Self::functions(stmt, &schema)?;
 
                 // Write overwrite because of conservative read/write split.
                 if let Some(true) = self.write_override {
@@ -302,7 +398,9 @@ impl QueryParser {
 
                 if matches!(shard, Shard::Direct(_)) {
                     self.routed = true;
-                    return Ok(Command::Query(Route::read(shard).set_write(writes)));
+                    return Ok(Command::Query(
+                        Route::read(shard).set_write(writes).set_key(comment_key),
+                    ));
                 }
                 // `SELECT NOW()`, `SELECT 1`, etc.
                 else if ast.tables().is_empty() {
@@ -311,8 +409,17 @@ impl QueryParser {
                         Route::read(Some(round_robin::next() % cluster.shards().len()))
                             .set_write(writes),
                     ));
+                } else if let Some(join) =
+                    Self::join(stmt, &sharding_schema, query, in_transaction, &writes)?
+                {
+                    Ok(Command::Join(Box::new(join)))
                 } else {
                     let command = Self::select(stmt, &sharding_schema, bind)?;
+                    let cte_shard = if cte_writes {
+                        Self::cte_writes_shard(stmt, &sharding_schema, query, bind)?
+                    } else {
+                        None
+                    };
                     let mut omni = false;
                     if let Command::Query(mut query) = command {
                         // Try to route an all-shard query to one
@@ -325,8 +432,24 @@ impl QueryParser {
                                 .all(|t| sharding_schema.tables.omnishards().contains(t));
                         }
 
-                        if omni {
+                        if let Some(cte_shard) = cte_shard {
+                            // Rows written by the CTEs are on their shards,
+                            // and so is anything else the statement reads.
+                            if omni || Self::reads_ctes_only(stmt) {
+                                query.set_shards_mut(cte_shard);
+                            } else {
+                                query.set_shards_mut(Self::converge(HashSet::from([
+                                    query.shard().clone(),
+                                    cte_shard,
+                                ])));
+                            }
+                        } else if omni {
                             query.set_shard_mut(round_robin::next() % cluster.shards().len());
+                        } else if cluster.strict()
+                            && cluster.shards().len() > 1
+                            && !matches!(query.shard(), Shard::Direct(_))
+                        {
+                            Strict::new(stmt, &sharding_schema).check()?;
                         }
 
                         Ok(Command::Query(query.set_write(writes)))
@@ -345,7 +468,80 @@ impl QueryParser {
             // COPY statements.
             Some(NodeEnum::CopyStmt(ref stmt)) => Self::copy(stmt, cluster),
             // INSERT statements.
-            Some(NodeEnum::InsertStmt(ref stmt)) => Self::insert(stmt, &sharding_schema, bind),
+            Some(NodeEnum::InsertStmt(ref stmt)) => {
+                // INSERT ... SELECT into a sharded table needs
+                // to re-shard rows returned by the SELECT.
+                let plan = if shards > 1 {
+                    InsertSelect::new(stmt, &sharding_schema)?
+                } else {
+                    None
+                };
+
+                match plan {
+                    Some(mut plan) => {
+                        if !query.simple() || in_transaction {
+                            return Err(Error::InsertSelectUnsupported);
+                        }
+                        plan.set_source(Self::insert_select_source(stmt, &plan, &sharding_schema)?);
+                        Ok(Command::InsertSelect(Box::new(plan)))
+                    }
+                    None => Self::insert(stmt, &sharding_schema, bind),
+                }
+            }
+            // DECLARE ... CURSOR FOR SELECT ...
+            Some(NodeEnum::DeclareCursorStmt(ref stmt)) => {
+                match stmt.query.as_ref().and_then(|query| query.node.as_ref()) {
+                    Some(NodeEnum::SelectStmt(ref select)) => {
+                        let mut writes = Self::functions(select, &schema)?;
+                        if let Some(true) = self.write_override {
+                            writes.writes = true;
+                        }
+                        match Self::select(select, &sharding_schema, bind)? {
+                            Command::Query(route) => {
+                                if shards > 1 && !matches!(shard, Shard::Direct(_)) {
+                                    self.declare_cursor(stmt, route.shard(), &route)?;
+                                }
+                                Ok(Command::Query(route.set_write(writes)))
+                            }
+                            command => Ok(command),
+                        }
+                    }
+                    _ => Ok(Command::Query(Route::write(None))),
+                }
+            }
+            // EXPLAIN of a query sent to multiple shards is
+            // combined by pgdog.
+            Some(NodeEnum::ExplainStmt(ref stmt)) => {
+                match stmt.query.as_ref().and_then(|query| query.node.as_ref()) {
+                    Some(NodeEnum::SelectStmt(ref select)) => {
+                        let mut writes = Self::functions(select, &schema)?;
+                        if let Some(true) = self.write_override {
+                            writes.writes = true;
+                        }
+                        let route = match Self::select(select, &sharding_schema, bind)? {
+                            Command::Query(route) => route.set_write(writes),
+                            _ => Route::write(None),
+                        };
+
+                        let explain = if shards > 1
+                            && !matches!(shard, Shard::Direct(_))
+                            && !matches!(route.shard(), Shard::Direct(_))
+                            && query.simple()
+                            && !in_transaction
+                        {
+                            Explain::new(stmt, query.query(), route.clone())
+                        } else {
+                            None
+                        };
+
+                        match explain {
+                            Some(explain) => Ok(Command::Explain(Box::new(explain))),
+                            None => Ok(Command::Query(route)),
+                        }
+                    }
+                    _ => Ok(Command::Query(Route::write(None))),
+                }
+            }
             // UPDATE statements.
             Some(NodeEnum::UpdateStmt(ref stmt)) => Self::update(stmt, &sharding_schema, bind),
             // DELETE statements.
@@ -356,7 +552,17 @@ impl QueryParser {
                 // Only allow to intercept transaction statements
                 // if they are using the simple protocol.
                 if query.simple() {
-                    if rw_strategy == &ReadWriteStrategy::Conservative && !read_only {
+                    if matches!(
+                        stmt.kind(),
+                        TransactionStmtKind::TransStmtBegin | TransactionStmtKind::TransStmtStart
+                    ) {
+                        self.read_only_transaction = Self::transaction_read_only(&stmt.options);
+                    }
+
+                    if rw_strategy == &ReadWriteStrategy::Conservative
+                        && !read_only
+                        && !self.read_only_transaction
+                    {
                         self.write_override = Some(true);
                     }
 
@@ -383,12 +589,27 @@ impl QueryParser {
             _ => Ok(Command::Query(Route::write(None))),
         }?;
 
+        // Don't send UPDATE and DELETE to all shards by accident.
+        if shards > 1 && !matches!(shard, Shard::Direct(_)) {
+            if let Command::Query(ref route) = command {
+                if route.shard().all() {
+                    Self::all_shards_write(
+                        root,
+                        query,
+                        &sharding_schema,
+                        config().config.general.all_shards_writes,
+                    )?;
+                }
+            }
+        }
+
         self.routed = true;
 
         // Overwrite shard using shard we got from a comment, if any.
         if let Shard::Direct(shard) = shard {
             if let Command::Query(ref mut route) = command {
                 route.set_shard_mut(shard);
+                route.set_key_mut(comment_key);
             }
         }
 
@@ -444,6 +665,246 @@ impl QueryParser {
         }
     }
 
+    /// Route a query containing multiple statements.
+    ///
+    /// The route is taken from the first statement that needs a particular shard.
+    /// Statements that pgdog would have to execute or merge itself
+    /// can't be sent together with others.
+    ///
+    /// Returns `None` if none of the statements need a particular shard
+    /// and the query can be routed using the first statement.
+    fn statements(
+        &self,
+        stmts: &[RawStmt],
+        query: &BufferedQuery,
+        sharding_schema: &ShardingSchema,
+        schema: &Schema,
+        strict: bool,
+        bind: Option<&Bind>,
+    ) -> Result<Option<Route>, Error> {
+        let mut target: Option<Route> = None;
+        let mut writes = FunctionBehavior::default();
+
+        if let Some(true) = self.write_override {
+            writes.writes = true;
+        }
+
+        for stmt in stmts {
+            let Some(node) = stmt.stmt.as_ref() else {
+                continue;
+            };
+
+            let command = match node.node {
+                Some(NodeEnum::SelectStmt(ref stmt)) => {
+                    let functions = Self::functions(stmt, schema)?;
+                    writes.merge(functions);
+                    writes.writes |= Self::cte_writes(stmt);
+                    if stmt.from_clause.is_empty() {
+                        continue;
+                    }
+                    if Self::join(
+                        stmt,
+                        sharding_schema,
+                        query,
+                        self.in_transaction,
+                        &functions,
+                    )?
+                    .is_some()
+                    {
+                        return Err(Error::MultiStatementUnsupported(
+                            "join between tables on different shards".into(),
+                        ));
+                    }
+                    // Tables that aren't sharded can be read anywhere.
+                    if !functions.writes && Self::unsharded(node, sharding_schema) {
+                        continue;
+                    }
+                    let command = Self::select(stmt, sharding_schema, bind)?;
+                    if let Command::Query(ref route) = command {
+                        if strict && !matches!(route.shard(), Shard::Direct(_)) {
+                            Strict::new(stmt, sharding_schema).check()?;
+                        }
+                    }
+                    command
+                }
+                Some(NodeEnum::InsertStmt(ref stmt)) => {
+                    writes.writes = true;
+                    if InsertSelect::new(stmt, sharding_schema)?.is_some() {
+                        return Err(Error::MultiStatementUnsupported(
+                            "INSERT ... SELECT into a sharded table".into(),
+                        ));
+                    }
+                    Self::insert(stmt, sharding_schema, bind)?
+                }
+                Some(NodeEnum::UpdateStmt(ref stmt)) => {
+                    writes.writes = true;
+                    Self::update(stmt, sharding_schema, bind)?
+                }
+                Some(NodeEnum::DeleteStmt(ref stmt)) => {
+                    writes.writes = true;
+                    Self::delete(stmt, sharding_schema, bind)?
+                }
+                // Session and transaction control statements
+                // can go anywhere.
+                Some(NodeEnum::VariableSetStmt(_))
+                | Some(NodeEnum::VariableShowStmt(_))
+                | Some(NodeEnum::TransactionStmt(_)) => continue,
+                // DDL, etc., is sent to all shards.
+                _ => {
+                    writes.writes = true;
+                    Command::Query(Route::write(None))
+                }
+            };
+
+            let Command::Query(route) = command else {
+                continue;
+            };
+
+            if route.shard().all() {
+                Self::all_shards_write(
+                    node,
+                    query,
+                    sharding_schema,
+                    config().config.general.all_shards_writes,
+                )?;
+            }
+
+            // Results from multiple shards are merged one statement at a time.
+            let limit = route.limit();
+            if !matches!(route.shard(), Shard::Direct(_))
+                && (route.should_buffer() || limit.limit.is_some() || limit.offset.is_some())
+            {
+                return Err(Error::MultiStatementUnsupported(
+                    "ORDER BY, LIMIT or aggregate on multiple shards".into(),
+                ));
+            }
+
+            match target {
+                Some(ref target) if target.shard() != route.shard() => {
+                    return Err(Error::MultiStatementShards(
+                        target.shard().clone(),
+                        route.shard().clone(),
+                    ))
+                }
+                Some(_) => (),
+                None => target = Some(route),
+            }
+        }
+
+        Ok(match target {
+            Some(route) => Some(route.set_write(writes)),
+            None if writes.writes => Some(Route::write(None).set_write(writes)),
+            None => None,
+        })
+    }
+
+    /// The statement only reads tables that aren't sharded.
+    fn unsharded(node: &Node, sharding_schema: &ShardingSchema) -> bool {
+        let Some(ref node) = node.node else {
+            return true;
+        };
+
+        // Tables without names are sharded by column.
+        if sharding_schema
+            .tables
+            .tables()
+            .iter()
+            .any(|table| table.name.is_none())
+        {
+            return false;
+        }
+
+        node.nodes().into_iter().all(|(node, _, _, _)| match node {
+            NodeRef::RangeVar(table) => sharding_schema.tables.table(&table.relname).is_none(),
+            _ => true,
+        })
+    }
+
+    /// Handle statements using cursors open on multiple shards.
+    ///
+    /// Returns `None` if the statement should be routed like any other.
+    fn cursor(
+        &mut self,
+        query: &BufferedQuery,
+        sharding_schema: &ShardingSchema,
+        bind: Option<&Bind>,
+    ) -> Result<Option<Command>, Error> {
+        // Cursors declared before the transaction is routed
+        // are handled by the parser below. Other statements
+        // don't need to be parsed again.
+        if (!self.routed && self.cursors.is_empty()) || !cursor_statement(query.query()) {
+            return Ok(None);
+        }
+
+        let ast = match query {
+            BufferedQuery::Prepared(query) if self.cache => {
+                Cache::get().parse(query.query()).map_err(Error::PgQuery)?
+            }
+            query => Arc::new(parse(query.query()).map_err(Error::PgQuery)?),
+        };
+        let Some(node) = ast
+            .protobuf
+            .stmts
+            .first()
+            .and_then(|stmt| stmt.stmt.as_ref())
+            .and_then(|stmt| stmt.node.as_ref())
+        else {
+            return Ok(None);
+        };
+
+        match node {
+            NodeEnum::FetchStmt(ref stmt) => Ok(self
+                .cursors
+                .fetch(stmt)?
+                .map(|fetch| Command::Fetch(Box::new(fetch)))),
+
+            NodeEnum::ClosePortalStmt(ref stmt) => {
+                if self.cursors.close(&stmt.portalname) {
+                    Ok(Some(Command::CloseCursor(stmt.portalname.clone())))
+                } else {
+                    Ok(None)
+                }
+            }
+
+            NodeEnum::DeclareCursorStmt(ref stmt) if self.routed => {
+                if let Some(NodeEnum::SelectStmt(ref select)) =
+                    stmt.query.as_ref().and_then(|query| query.node.as_ref())
+                {
+                    if let Command::Query(route) = Self::select(select, sharding_schema, bind)? {
+                        let shard = self.route().shard().clone();
+                        self.declare_cursor(stmt, &shard, &route)?;
+                    }
+                }
+                Ok(None)
+            }
+
+            _ => Ok(None),
+        }
+    }
+
+    /// Track a cursor if it's open on multiple shards.
+    fn declare_cursor(
+        &mut self,
+        stmt: &DeclareCursorStmt,
+        shard: &Shard,
+        route: &Route,
+    ) -> Result<(), Error> {
+        if let Shard::Direct(_) = shard {
+            return Ok(());
+        }
+
+        Cursors::check(stmt.options)?;
+
+        if !route.aggregate().is_empty() {
+            return Err(Error::CursorUnsupported("aggregates".into()));
+        }
+
+        self.cursors
+            .declare(&stmt.portalname, route.order_by().to_vec());
+
+        Ok(())
+    }
+
     fn show(
         &mut self,
         stmt: &VariableShowStmt,
@@ -485,7 +946,9 @@ impl QueryParser {
                 {
                     self.routed = true;
                     return Ok(Command::Query(
-                        Route::write(Some(*ival as usize)).set_read(read_only),
+                        Route::write(Some(*ival as usize))
+                            .set_read(read_only)
+                            .set_key(Some((format!("{} = {}", stmt.name, ival), KeySource::Set))),
                     ));
                 }
             }
@@ -509,10 +972,43 @@ impl QueryParser {
                         .build()?;
                     let shard = ctx.apply()?;
                     self.routed = true;
-                    return Ok(Command::Query(Route::write(shard).set_read(read_only)));
+                    return Ok(Command::Query(
+                        Route::write(shard)
+                            .set_read(read_only)
+                            .set_key(Some((format!("{} = {}", stmt.name, sval), KeySource::Set))),
+                    ));
                 }
             }
 
+            // Role for the rest of the transaction or session.
+            ROLE_PARAMETER => {
+                let role = match stmt.args.first().and_then(|arg| arg.node.as_ref()) {
+                    Some(NodeEnum::AConst(AConst {
+                        val: Some(Val::Sval(String { sval })),
+                        ..
+                    })) => sval.parse::<TargetRole>()?,
+                    _ => return Err(Error::SetRole(std::string::String::new())),
+                };
+
+                if !self.in_transaction {
+                    return Ok(Command::Set {
+                        name: ROLE_PARAMETER.into(),
+                        value: ParameterValue::String(role.to_string()),
+                    });
+                }
+
+                self.role = Some(role);
+                if let Some(read) = role.read() {
+                    return Ok(Command::Query(Route::write(Shard::All).set_read(read)));
+                }
+            }
+
+            // SET TRANSACTION READ ONLY
+            "TRANSACTION" if Self::transaction_read_only(&stmt.args) => {
+                self.read_only_transaction = true;
+                self.write_override = None;
+            }
+
             // TODO: Handle SET commands for updating client
             // params without touching the server.
             name => {
@@ -565,41 +1061,179 @@ impl QueryParser {
         Ok(Command::Query(Route::write(Shard::All).set_read(read_only)))
     }
 
+    /// Transaction options include `READ ONLY`.
+    fn transaction_read_only(options: &[Node]) -> bool {
+        options.iter().any(|option| match option.node {
+            Some(NodeEnum::DefElem(ref elem)) if elem.defname == "transaction_read_only" => {
+                matches!(
+                    elem.arg.as_ref().and_then(|arg| arg.node.as_ref()),
+                    Some(NodeEnum::AConst(AConst {
+                        val: Some(Val::Ival(Integer { ival: 1 })),
+                        ..
+                    }))
+                )
+            }
+            _ => false,
+        })
+    }
+
     fn where_clause(
         sharding_schema: &ShardingSchema,
         where_clause: &WhereClause,
         params: Option<&Bind>,
-    ) -> Result<HashSet<Shard>, Error> {
-        let mut shards = HashSet::new();
+    ) -> Result<Shards, Error> {
+        let mut shards = Shards::default();
         // Complexity: O(number of sharded tables * number of columns in the query)
         for table in sharding_schema.tables().tables() {
             let table_name = table.name.as_deref();
-            let keys = where_clause.keys(table_name, &table.column);
+            let path = table.json_path();
+            let keys = where_clause.keys_path(table_name, &table.column, &path);
             for key in keys {
-                match key {
-                    Key::Constant(value) => {
-                        let ctx = ContextBuilder::new(table)
-                            .data(value.as_str())
-                            .shards(sharding_schema.shards)
-                            .build()?;
-                        shards.insert(ctx.apply()?);
+                let text = key_text(&table.column, &path, &key);
+                if let Some(shard) = Self::key(sharding_schema, table, key, params)? {
+                    shards.insert(shard, text);
+                }
+            }
+        }
+
+        Ok(shards)
+    }
+
+    /// Find sharding keys in a query joining multiple tables.
+    ///
+    /// Keys are propagated through column equalities, so a join between
+    /// co-located tables, e.g. `orders o JOIN customers c ON o.customer_id = c.id`,
+    /// filtered on one table's key is routed to the same shard as the other table.
+    fn join_where_clause(
+        sharding_schema: &ShardingSchema,
+        from_clause: &FromClause,
+        where_clause: &WhereClause,
+        params: Option<&Bind>,
+    ) -> Result<Shards, Error> {
+        let mut shards = Shards::default();
+
+        for relation in from_clause.relations() {
+            let Some(table) = sharding_schema.tables.table(relation.name) else {
+                continue;
+            };
+
+            // Columns are compared as a whole,
+            // so keys in JSON columns can't be propagated.
+            let path = table.json_path();
+            let columns = if path.is_empty() {
+                from_clause
+                    .equivalent(relation.alias, &table.column)
+                    .into_iter()
+                    .filter(|(alias, column)| {
+                        Self::same_shard(sharding_schema, from_clause, relation, alias, column)
+                    })
+                    .collect()
+            } else {
+                vec![(relation.alias, table.column.as_str())]
+            };
+
+            for (alias, column) in columns {
+                for key in where_clause.keys_path(Some(alias), column, &path) {
+                    let text = key_text(&format!("{}.{}", alias, column), &path, &key);
+                    if let Some(shard) = Self::key(sharding_schema, table, key, params)? {
+                        shards.insert(shard, text);
                     }
+                }
+            }
+        }
 
-                    Key::Parameter(param) => {
-                        if let Some(params) = params {
-                            if let Some(param) = params.parameter(param)? {
-                                let value = ShardingValue::from_param(&param, table.data_type)?;
-                                let ctx = ContextBuilder::new(table)
-                                    .value(value)
-                                    .shards(sharding_schema.shards)
-                                    .build()?;
-                                shards.insert(ctx.apply()?);
-                            }
-                        }
+        Ok(shards)
+    }
+
+    /// Rows matching a key on the column are on the same shard as the relation's rows
+    /// with that key, so the key can be propagated to it.
+    ///
+    /// That's the case for tables that aren't sharded and for tables co-located with the relation
+    /// and sharded on that column.
+    fn same_shard(
+        sharding_schema: &ShardingSchema,
+        from_clause: &FromClause,
+        relation: &Relation,
+        alias: &str,
+        column: &str,
+    ) -> bool {
+        if alias == relation.alias {
+            return true;
+        }
+
+        let Some(other) = from_clause.relations().iter().find(|r| r.alias == alias) else {
+            return false;
+        };
+
+        match sharding_schema.tables.table(other.name) {
+            Some(table) => {
+                table.column == column
+                    && sharding_schema.tables.co_located(relation.name, other.name)
+            }
+            None => true,
+        }
+    }
+
+    /// Find sharding keys in CTEs, subqueries in `FROM` and subqueries in `WHERE`,
+    /// e.g. `WHERE id IN (SELECT id FROM orders WHERE tenant_id = 5)`.
+    ///
+    /// Used when the statement itself doesn't filter on a sharding key. A key found in a subquery
+    /// constrains the statement only if every sharded table it reads is joined to the subquery
+    /// on the sharding key, e.g. `orders.id = c.id`, or `orders.id IN (SELECT id ...)`.
+    fn nested(
+        relation: Option<&RangeVar>,
+        with_clause: Option<&WithClause>,
+        from_clause: &[Node],
+        where_clause: &Option<Box<Node>>,
+        sharding_schema: &ShardingSchema,
+        params: Option<&Bind>,
+    ) -> Result<Shards, Error> {
+        let mut subqueries = vec![];
+
+        if let Some(with_clause) = with_clause {
+            for cte in &with_clause.ctes {
+                if let Some(NodeEnum::CommonTableExpr(ref expr)) = cte.node {
+                    if let Some(NodeEnum::SelectStmt(ref stmt)) =
+                        expr.ctequery.as_ref().and_then(|query| query.node.as_ref())
+                    {
+                        subqueries.push(Subquery {
+                            stmt,
+                            alias: Some(&expr.ctename),
+                            columns: names(&expr.aliascolnames),
+                            column: None,
+                        });
                     }
+                }
+            }
+        }
+
+        for node in from_clause {
+            Self::subqueries(node, &mut subqueries);
+        }
+
+        // Only subqueries that must match, i.e. not under OR or NOT.
+        if let Some(ref where_clause) = where_clause {
+            let mut conjuncts = vec![];
+            super::from_clause::split(where_clause, &mut conjuncts);
+            for conjunct in conjuncts {
+                Self::subqueries(conjunct, &mut subqueries);
+            }
+        }
 
-                    // Null doesn't help.
-                    Key::Null => (),
+        let mut outer = FromClause::new(from_clause, where_clause);
+        if let Some(relation) = relation {
+            outer = outer.target(relation);
+        }
+
+        let mut shards = Shards::default();
+        for subquery in subqueries {
+            if let Command::Query(route) = Self::select(subquery.stmt, sharding_schema, params)? {
+                if route.shard().all() || !Self::tied(sharding_schema, &outer, &subquery) {
+                    continue;
+                }
+                shards.shards.insert(route.shard().clone());
+                if let Some(key) = route.key() {
+                    shards.key.get_or_insert(key.clone());
                 }
             }
         }
@@ -607,6 +1241,233 @@ impl QueryParser {
         Ok(shards)
     }
 
+    /// Every sharded table in the outer statement is joined to the subquery on the sharding key.
+    fn tied(sharding_schema: &ShardingSchema, outer: &FromClause, subquery: &Subquery) -> bool {
+        outer.relations().iter().all(|relation| {
+            let Some(table) = sharding_schema.tables.table(relation.name) else {
+                return true;
+            };
+            let keys = Self::key_columns(subquery, sharding_schema, relation.name);
+            let equivalent = outer.equivalent(relation.alias, &table.column);
+
+            // `id IN (SELECT id ...)`
+            let compared = subquery.column.is_some_and(|(alias, column)| {
+                let alias = match alias {
+                    Some(alias) => alias,
+                    None if outer.relations().len() == 1 => relation.alias,
+                    None => return false,
+                };
+                equivalent.contains(&(alias, column))
+                    && keys.iter().any(|(position, _)| *position == Some(0))
+            });
+
+            // `JOIN c ON orders.id = c.id`
+            let joined = subquery.alias.is_some_and(|name| {
+                equivalent.iter().any(|(alias, column)| {
+                    *alias == name && keys.iter().any(|(_, key)| key == column)
+                })
+            });
+
+            compared || joined
+        })
+    }
+
+    /// Columns returned by the subquery that hold the sharding key
+    /// of a table co-located with `outer`, with their position in the target list.
+    fn key_columns<'a>(
+        subquery: &Subquery<'a>,
+        sharding_schema: &'a ShardingSchema,
+        outer: &str,
+    ) -> Vec<(Option<usize>, &'a str)> {
+        let inner = FromClause::new(&subquery.stmt.from_clause, &subquery.stmt.where_clause);
+        let keys = inner
+            .relations()
+            .iter()
+            .filter(|relation| sharding_schema.tables.co_located(outer, relation.name))
+            .filter_map(|relation| {
+                sharding_schema
+                    .tables
+                    .table(relation.name)
+                    .map(|table| (relation.alias, table.column.as_str()))
+            })
+            .collect::<Vec<_>>();
+
+        let mut columns = vec![];
+        for (position, target) in subquery.stmt.target_list.iter().enumerate() {
+            let Some(NodeEnum::ResTarget(ref target)) = target.node else {
+                continue;
+            };
+            let Some(NodeEnum::ColumnRef(ref column)) =
+                target.val.as_ref().and_then(|val| val.node.as_ref())
+            else {
+                continue;
+            };
+            let fields = column
+                .fields
+                .iter()
+                .map(|field| field.node.as_ref())
+                .collect::<Vec<_>>();
+
+            match fields.as_slice() {
+                // `SELECT *` keeps column names, unless they're renamed.
+                [.., Some(NodeEnum::AStar(_))] if subquery.columns.is_empty() => {
+                    columns.extend(keys.iter().map(|(_, column)| (None, *column)));
+                }
+                [Some(NodeEnum::String(column))] | [_, Some(NodeEnum::String(column))] => {
+                    let alias = match fields.as_slice() {
+                        [Some(NodeEnum::String(alias)), _] => Some(alias.sval.as_str()),
+                        _ => None,
+                    };
+                    let key = keys.iter().any(|(relation, key)| {
+                        *key == column.sval && alias.is_none_or(|alias| alias == *relation)
+                    });
+                    if key {
+                        let name = subquery
+                            .columns
+                            .get(position)
+                            .copied()
+                            .or((!target.name.is_empty()).then_some(target.name.as_str()))
+                            .unwrap_or(column.sval.as_str());
+                        columns.push((Some(position), name));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        columns
+    }
+
+    /// Collect `SELECT` statements in a `FROM` item or a `WHERE` condition.
+    fn subqueries<'a>(node: &'a Node, subqueries: &mut Vec<Subquery<'a>>) {
+        match node.node {
+            Some(NodeEnum::SelectStmt(ref stmt)) => subqueries.push(Subquery {
+                stmt,
+                alias: None,
+                columns: vec![],
+                column: None,
+            }),
+            Some(NodeEnum::RangeSubselect(ref subselect)) => {
+                if let Some(NodeEnum::SelectStmt(ref stmt)) = subselect
+                    .subquery
+                    .as_ref()
+                    .and_then(|subquery| subquery.node.as_ref())
+                {
+                    subqueries.push(Subquery {
+                        stmt,
+                        alias: subselect
+                            .alias
+                            .as_ref()
+                            .map(|alias| alias.aliasname.as_str()),
+                        columns: subselect
+                            .alias
+                            .as_ref()
+                            .map(|alias| names(&alias.colnames))
+                            .unwrap_or_default(),
+                        column: None,
+                    });
+                }
+            }
+            Some(NodeEnum::JoinExpr(ref join)) => {
+                for arg in [&join.larg, &join.rarg].into_iter().flatten() {
+                    Self::subqueries(arg, subqueries);
+                }
+            }
+            // `id IN (SELECT ...)`, `EXISTS (SELECT ...)`
+            Some(NodeEnum::SubLink(ref sublink)) => {
+                if let Some(NodeEnum::SelectStmt(ref stmt)) = sublink
+                    .subselect
+                    .as_ref()
+                    .and_then(|subselect| subselect.node.as_ref())
+                {
+                    let column = match sublink.sub_link_type() {
+                        // `IN` or `= ANY`
+                        SubLinkType::AnySublink
+                            if sublink.oper_name.iter().all(|name| {
+                                matches!(name.node, Some(NodeEnum::String(ref op)) if op.sval == "=")
+                            }) =>
+                        {
+                            sublink.testexpr.as_deref().and_then(column_ref)
+                        }
+                        _ => None,
+                    };
+                    subqueries.push(Subquery {
+                        stmt,
+                        alias: None,
+                        columns: vec![],
+                        column,
+                    });
+                }
+            }
+            // `id = (SELECT ...)`
+            Some(NodeEnum::AExpr(ref expr)) if expr.kind() == AExprKind::AexprOp => {
+                let equal = matches!(
+                    expr.name.first().and_then(|name| name.node.as_ref()),
+                    Some(NodeEnum::String(op)) if op.sval == "="
+                );
+                for (arg, other) in [(&expr.lexpr, &expr.rexpr), (&expr.rexpr, &expr.lexpr)] {
+                    let Some(NodeEnum::SubLink(ref sublink)) =
+                        arg.as_ref().and_then(|arg| arg.node.as_ref())
+                    else {
+                        continue;
+                    };
+                    if let Some(NodeEnum::SelectStmt(ref stmt)) = sublink
+                        .subselect
+                        .as_ref()
+                        .and_then(|subselect| subselect.node.as_ref())
+                    {
+                        subqueries.push(Subquery {
+                            stmt,
+                            alias: None,
+                            columns: vec![],
+                            column: other.as_deref().filter(|_| equal).and_then(column_ref),
+                        });
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Get the shard for a sharding key.
+    fn key(
+        sharding_schema: &ShardingSchema,
+        table: &ShardedTable,
+        key: Key,
+        params: Option<&Bind>,
+    ) -> Result<Option<Shard>, Error> {
+        match key {
+            Key::Constant(value) => {
+                let ctx = ContextBuilder::new(table)
+                    .data(value.as_str())
+                    .shards(sharding_schema.shards)
+                    .build()?;
+                Ok(Some(ctx.apply()?))
+            }
+
+            Key::Parameter(param) => {
+                if let Some(params) = params {
+                    if let Some(param) = params.parameter(param)? {
+                        // Null doesn't help.
+                        if param.is_null() {
+                            return Ok(None);
+                        }
+                        let value = ShardingValue::from_param(&param, table.data_type)?;
+                        let ctx = ContextBuilder::new(table)
+                            .value(value)
+                            .shards(sharding_schema.shards)
+                            .build()?;
+                        return Ok(Some(ctx.apply()?));
+                    }
+                }
+                Ok(None)
+            }
+
+            // Null doesn't help.
+            Key::Null => Ok(None),
+        }
+    }
+
     fn converge(shards: HashSet<Shard>) -> Shard {
         let shard = if shards.len() == 1 {
             shards.iter().next().cloned().unwrap()
@@ -661,18 +1522,125 @@ impl QueryParser {
         false
     }
 
-    fn functions(stmt: &SelectStmt) -> Result<FunctionBehavior, Error> {
-        for target in &stmt.target_list {
-            if let Ok(func) = Function::try_from(target) {
-                return Ok(func.behavior());
+    /// Route data-modifying CTEs, e.g. `WITH moved AS (DELETE FROM ... RETURNING *) SELECT ...`,
+    /// using their own sharding keys.
+    ///
+    /// Returns `None` if none of the CTEs write.
+    fn cte_writes_shard(
+        stmt: &SelectStmt,
+        sharding_schema: &ShardingSchema,
+        query: &BufferedQuery,
+        params: Option<&Bind>,
+    ) -> Result<Option<Shard>, Error> {
+        let Some(ref with_clause) = stmt.with_clause else {
+            return Ok(None);
+        };
+
+        let mut shards = HashSet::new();
+
+        for cte in &with_clause.ctes {
+            let Some(NodeEnum::CommonTableExpr(ref expr)) = cte.node else {
+                continue;
+            };
+            let Some(ref node) = expr.ctequery else {
+                continue;
+            };
+
+            let command = match node.node {
+                Some(NodeEnum::InsertStmt(ref stmt)) => {
+                    Self::insert(stmt, sharding_schema, params)?
+                }
+                Some(NodeEnum::UpdateStmt(ref stmt)) => {
+                    Self::update(stmt, sharding_schema, params)?
+                }
+                Some(NodeEnum::DeleteStmt(ref stmt)) => {
+                    Self::delete(stmt, sharding_schema, params)?
+                }
+                _ => continue,
+            };
+
+            if let Command::Query(route) = command {
+                if route.shard().all() {
+                    Self::all_shards_write(
+                        node,
+                        query,
+                        sharding_schema,
+                        config().config.general.all_shards_writes,
+                    )?;
+                }
+                shards.insert(route.shard().clone());
             }
         }
 
-        Ok(if stmt.locking_clause.is_empty() {
+        Ok(if shards.is_empty() {
+            None
+        } else {
+            Some(Self::converge(shards))
+        })
+    }
+
+    /// The statement only reads rows returned by its CTEs.
+    fn reads_ctes_only(stmt: &SelectStmt) -> bool {
+        let Some(ref with_clause) = stmt.with_clause else {
+            return false;
+        };
+
+        let ctes = with_clause
+            .ctes
+            .iter()
+            .filter_map(|cte| match cte.node {
+                Some(NodeEnum::CommonTableExpr(ref expr)) => Some(expr.ctename.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        FromClause::new(&stmt.from_clause, &None)
+            .relations()
+            .iter()
+            .all(|relation| ctes.contains(&relation.name))
+    }
+
+    /// Behavior of all functions called by the statement, including
+    /// the ones in `FROM`, `WHERE` and subqueries, and of `FOR UPDATE`.
+    fn functions(stmt: &SelectStmt, schema: &Schema) -> Result<FunctionBehavior, Error> {
+        let mut behavior = if stmt.locking_clause.is_empty() {
             FunctionBehavior::default()
         } else {
             FunctionBehavior::writes_only()
-        })
+        };
+        let config = config();
+        let lists = FunctionLists::new(&config.config.general);
+
+        let nodes = stmt
+            .target_list
+            .iter()
+            .chain(&stmt.from_clause)
+            .chain(stmt.where_clause.as_deref())
+            .chain(&stmt.group_clause)
+            .chain(stmt.having_clause.as_deref())
+            .chain(&stmt.sort_clause)
+            .chain(stmt.with_clause.iter().flat_map(|with| &with.ctes));
+
+        for node in nodes {
+            let Some(ref node) = node.node else {
+                continue;
+            };
+            for (node, _, _, _) in node.nodes() {
+                match node {
+                    NodeRef::FuncCall(func) => {
+                        if let Ok(func) = Function::try_from(func) {
+                            behavior.merge(func.behavior(schema, &lists));
+                        }
+                    }
+                    NodeRef::SelectStmt(stmt) if !stmt.locking_clause.is_empty() => {
+                        behavior.merge(FunctionBehavior::writes_only());
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        Ok(behavior)
     }
 
     fn select(
@@ -681,12 +1649,33 @@ impl QueryParser {
         params: Option<&Bind>,
     ) -> Result<Command, Error> {
         let order_by = Self::select_sort(&stmt.sort_clause, params);
-        let mut shards = HashSet::new();
+        let mut shards = Shards::default();
         let the_table = Table::try_from(&stmt.from_clause).ok();
         if let Some(where_clause) =
             WhereClause::new(the_table.as_ref().map(|t| t.name), &stmt.where_clause)
         {
             shards = Self::where_clause(sharding_schema, &where_clause, params)?;
+
+            let from_clause = FromClause::new(&stmt.from_clause, &stmt.where_clause);
+            if from_clause.join() {
+                shards.extend(Self::join_where_clause(
+                    sharding_schema,
+                    &from_clause,
+                    &where_clause,
+                    params,
+                )?);
+            }
+        }
+
+        if shards.is_empty() {
+            shards = Self::nested(
+                None,
+                stmt.with_clause.as_ref(),
+                &stmt.from_clause,
+                &stmt.where_clause,
+                sharding_schema,
+                params,
+            )?;
         }
 
         // Shard by vector in ORDER BY clause.
@@ -698,23 +1687,74 @@ impl QueryParser {
                             || table.name.as_deref() == the_table.as_ref().map(|t| t.name))
                     {
                         let centroids = Centroids::from(&table.centroids);
-                        shards.insert(centroids.shard(
-                            vector,
-                            sharding_schema.shards,
-                            table.centroid_probes,
-                        ));
+                        shards.insert(
+                            centroids.shard(vector, sharding_schema.shards, table.centroid_probes),
+                            format!("ORDER BY {}", column_name),
+                        );
                     }
                 }
             }
         }
 
-        let shard = Self::converge(shards);
         let aggregates = Aggregate::parse(stmt)?;
         let limit = LimitClause::new(stmt, params).limit_offset()?;
 
-        Ok(Command::Query(Route::select(
-            shard, order_by, aggregates, limit,
-        )))
+        Ok(Command::Query(
+            Route::select(Self::converge(shards.shards), order_by, aggregates, limit)
+                .set_key(shards.key),
+        ))
+    }
+
+    /// Plan a join between two tables that aren't on the same shards.
+    ///
+    /// Only reads sent outside of a transaction using the simple protocol are planned.
+    fn join(
+        stmt: &SelectStmt,
+        sharding_schema: &ShardingSchema,
+        query: &BufferedQuery,
+        in_transaction: bool,
+        writes: &FunctionBehavior,
+    ) -> Result<Option<Join>, Error> {
+        if sharding_schema.shards < 2 || !query.simple() || in_transaction || writes.writes {
+            return Ok(None);
+        }
+
+        Join::new(stmt, sharding_schema, |stmt| {
+            match Self::select(stmt, sharding_schema, None)? {
+                Command::Query(route) => Ok(route),
+                _ => Ok(Route::read(Shard::All)),
+            }
+        })
+    }
+
+    /// Figure out which shard(s) the SELECT part of an `INSERT ... SELECT` runs on.
+    fn insert_select_source(
+        stmt: &InsertStmt,
+        plan: &InsertSelect,
+        sharding_schema: &ShardingSchema,
+    ) -> Result<Shard, Error> {
+        let tables = plan.tables()?;
+
+        // `INSERT INTO sharded SELECT 1, 2` or tables with the same data
+        // on all shards. Running it on all shards would duplicate the rows.
+        if tables
+            .iter()
+            .all(|t| sharding_schema.tables.omnishards().contains(t))
+        {
+            return Ok(Shard::Direct(round_robin::next() % sharding_schema.shards));
+        }
+
+        if let Some(NodeEnum::SelectStmt(ref select)) = stmt
+            .select_stmt
+            .as_ref()
+            .and_then(|node| node.node.as_ref())
+        {
+            if let Command::Query(route) = Self::select(select, sharding_schema, None)? {
+                return Ok(route.shard().clone());
+            }
+        }
+
+        Ok(Shard::All)
     }
 
     /// Parse the `ORDER BY` clause of a `SELECT` statement.
@@ -836,16 +1876,89 @@ impl QueryParser {
         sharding_schema: &ShardingSchema,
         params: Option<&Bind>,
     ) -> Result<Command, Error> {
-        let table = stmt.relation.as_ref().map(Table::from);
+        Ok(Command::Query(Self::write_shard(
+            stmt.relation.as_ref(),
+            stmt.with_clause.as_ref(),
+            &stmt.from_clause,
+            &stmt.where_clause,
+            sharding_schema,
+            params,
+        )?))
+    }
+
+    /// Route an `UPDATE` or `DELETE` to the shard(s) it should go to.
+    ///
+    /// The `FROM` clause of an `UPDATE` and the `USING` clause of a `DELETE`
+    /// are joined with the target table, so keys are propagated through their column equalities.
+    fn write_shard(
+        relation: Option<&RangeVar>,
+        with_clause: Option<&WithClause>,
+        from_clause: &[Node],
+        where_clause: &Option<Box<Node>>,
+        sharding_schema: &ShardingSchema,
+        params: Option<&Bind>,
+    ) -> Result<Route, Error> {
+        let table = relation.map(Table::from);
+        let mut shards = Shards::default();
+
+        if let Some(filter) = WhereClause::new(table.map(|t| t.name), where_clause) {
+            shards = Self::where_clause(sharding_schema, &filter, params)?;
+
+            if let Some(relation) = relation {
+                if shards.is_empty() && !from_clause.is_empty() {
+                    let from_clause = FromClause::new(from_clause, where_clause).target(relation);
+                    shards =
+                        Self::join_where_clause(sharding_schema, &from_clause, &filter, params)?;
+                }
+            }
+        }
+
+        if shards.is_empty() {
+            shards = Self::nested(
+                relation,
+                with_clause,
+                from_clause,
+                where_clause,
+                sharding_schema,
+                params,
+            )?;
+        }
+
+        Ok(Route::write(Self::converge(shards.shards)).set_key(shards.key))
+    }
+
+    /// Check an UPDATE or DELETE on a sharded table is allowed to go to all shards.
+    fn all_shards_write(
+        root: &Node,
+        query: &BufferedQuery,
+        sharding_schema: &ShardingSchema,
+        all_shards_writes: AllShardsWrites,
+    ) -> Result<(), Error> {
+        let (statement, relation) = match root.node {
+            Some(NodeEnum::UpdateStmt(ref stmt)) => ("UPDATE", stmt.relation.as_ref()),
+            Some(NodeEnum::DeleteStmt(ref stmt)) => ("DELETE", stmt.relation.as_ref()),
+            _ => return Ok(()),
+        };
 
-        let where_clause = WhereClause::new(table.map(|t| t.name), &stmt.where_clause);
+        let Some(table) = relation.map(|relation| relation.relname.as_str()) else {
+            return Ok(());
+        };
 
-        if let Some(where_clause) = where_clause {
-            let shards = Self::where_clause(sharding_schema, &where_clause, params)?;
-            return Ok(Command::Query(Route::write(Self::converge(shards))));
+        if sharding_schema.tables.table(table).is_none() {
+            return Ok(());
         }
 
-        Ok(Command::Query(Route::write(Shard::All)))
+        match all_shards_writes {
+            AllShardsWrites::Allow => Ok(()),
+            AllShardsWrites::Comment => {
+                if super::comment::all_shards(query.query())? {
+                    Ok(())
+                } else {
+                    Err(Error::AllShardsWriteComment(statement.into(), table.into()))
+                }
+            }
+            AllShardsWrites::Reject => Err(Error::AllShardsWrite(statement.into(), table.into())),
+        }
     }
 
     fn delete(
@@ -853,18 +1966,132 @@ impl QueryParser {
         sharding_schema: &ShardingSchema,
         params: Option<&Bind>,
     ) -> Result<Command, Error> {
-        let table = stmt.relation.as_ref().map(Table::from);
-        let where_clause = WhereClause::new(table.map(|t| t.name), &stmt.where_clause);
+        Ok(Command::Query(Self::write_shard(
+            stmt.relation.as_ref(),
+            stmt.with_clause.as_ref(),
+            &stmt.using_clause,
+            &stmt.where_clause,
+            sharding_schema,
+            params,
+        )?))
+    }
+}
+
+/// Shards picked using sharding keys.
+#[derive(Debug, Default)]
+struct Shards {
+    shards: HashSet<Shard>,
+    /// First key that picked a shard, e.g. `id = 1`.
+    key: Option<(String, KeySource)>,
+}
+
+impl Shards {
+    fn insert(&mut self, shard: Shard, key: String) {
+        self.shards.insert(shard);
+        self.key.get_or_insert((key, KeySource::Where));
+    }
+
+    fn extend(&mut self, other: Shards) {
+        self.shards.extend(other.shards);
+        if let Some(key) = other.key {
+            self.key.get_or_insert(key);
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.shards.is_empty()
+    }
+}
+
+/// Sharding key as it appears in the query, e.g. `id = $1` or `data->>'tenant_id' = 5`.
+fn key_text(column: &str, path: &[&str], key: &Key) -> String {
+    let value = match key {
+        Key::Constant(value) => value.clone(),
+        Key::Parameter(param) => format!("${}", param + 1),
+        Key::Null => "NULL".into(),
+    };
+    let column = match path.split_last() {
+        Some((last, keys)) => format!(
+            "{}{}->>'{}'",
+            column,
+            keys.iter()
+                .map(|key| format!("->'{}'", key))
+                .collect::<String>(),
+            last
+        ),
+        None => column.to_owned(),
+    };
+
+    format!("{} = {}", column, value)
+}
 
-        if let Some(where_clause) = where_clause {
-            let shards = Self::where_clause(sharding_schema, &where_clause, params)?;
-            return Ok(Command::Query(Route::write(Self::converge(shards))));
+/// The statement declares or uses a cursor, ignoring leading whitespace and comments.
+fn cursor_statement(query: &str) -> bool {
+    let mut query = query.trim_start();
+    loop {
+        if let Some(rest) = query.strip_prefix("--") {
+            query = rest.split_once('\n').map(|(_, rest)| rest).unwrap_or("");
+        } else if let Some(rest) = query.strip_prefix("/*") {
+            query = rest.split_once("*/").map(|(_, rest)| rest).unwrap_or("");
+        } else {
+            break;
         }
+        query = query.trim_start();
+    }
+
+    let keyword = query
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .next()
+        .unwrap_or("");
+
+    ["declare", "fetch", "move", "close"]
+        .iter()
+        .any(|command| keyword.eq_ignore_ascii_case(command))
+}
+
+/// Subquery of a statement that could contain a sharding key.
+struct Subquery<'a> {
+    stmt: &'a SelectStmt,
+    /// Name of the CTE or subquery in `FROM`.
+    alias: Option<&'a str>,
+    /// Column names given to the CTE or subquery in `FROM`.
+    columns: Vec<&'a str>,
+    /// Column compared to the subquery, e.g. `id IN (SELECT ...)`.
+    column: Option<(Option<&'a str>, &'a str)>,
+}
+
+/// Column referenced in an expression, with its table or alias if qualified.
+fn column_ref(node: &Node) -> Option<(Option<&str>, &str)> {
+    let Some(NodeEnum::ColumnRef(ref column)) = node.node else {
+        return None;
+    };
 
-        Ok(Command::Query(Route::write(None)))
+    match column
+        .fields
+        .iter()
+        .map(|field| field.node.as_ref())
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [Some(NodeEnum::String(column))] => Some((None, column.sval.as_str())),
+        [Some(NodeEnum::String(table)), Some(NodeEnum::String(column))] => {
+            Some((Some(table.sval.as_str()), column.sval.as_str()))
+        }
+        _ => None,
     }
 }
 
+/// Names in a list of `String` nodes, e.g. column aliases.
+fn names(nodes: &[Node]) -> Vec<&str> {
+    nodes
+        .iter()
+        .filter_map(|node| match node.node {
+            Some(NodeEnum::String(ref name)) => Some(name.sval.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
 
@@ -874,6 +2101,9 @@ mod test {
     };
 
     use super::{super::Shard, *};
+    use crate::backend::replication::ShardedTables;
+    use crate::backend::schema::Volatility;
+    use crate::config::DataType;
     use crate::frontend::{Buffer, RouterContext};
     use crate::net::messages::Query;
     use crate::net::Parameters;
@@ -1308,6 +2538,30 @@ mod test {
         assert!(route.is_write());
     }
 
+    #[test]
+    fn test_cte_writes() {
+        let direct = query!("SELECT * FROM sharded WHERE id = 1").shard().clone();
+        assert!(matches!(direct, Shard::Direct(_)));
+
+        // Rows deleted on one shard are returned from that shard.
+        let route = query!(
+            "WITH moved AS (DELETE FROM sharded WHERE id = 1 RETURNING *) SELECT * FROM moved"
+        );
+        assert!(route.is_write());
+        assert_eq!(route.shard(), &direct);
+
+        // Other tables read by the statement are on all shards.
+        let route = query!(
+            "WITH moved AS (DELETE FROM sharded WHERE id = 1 RETURNING *) SELECT * FROM moved JOIN other ON other.id = moved.id"
+        );
+        assert!(route.is_write());
+        assert_eq!(route.shard(), &Shard::All);
+
+        let route = query!("WITH moved AS (DELETE FROM sharded RETURNING *) SELECT * FROM moved");
+        assert!(route.is_write());
+        assert_eq!(route.shard(), &Shard::All);
+    }
+
     #[test]
     fn test_function_begin() {
         let (cmd, mut qp) = command!("BEGIN");
@@ -1393,4 +2647,580 @@ mod test {
         assert_eq!(cmd.limit().limit, Some(1));
         assert_eq!(cmd.limit().offset, Some(25));
     }
+
+    #[test]
+    fn test_insert_select() {
+        let (command, _) = command!("INSERT INTO sharded (id, email) SELECT id, email FROM users");
+        match command {
+            Command::InsertSelect(plan) => {
+                assert_eq!(plan.select(), "SELECT id, email FROM users");
+                assert_eq!(plan.source(), &Shard::All);
+            }
+            _ => panic!("not an insert select"),
+        }
+
+        let (command, _) =
+            command!("INSERT INTO sharded (id, email) SELECT id, email FROM sharded WHERE id = 1");
+        match command {
+            Command::InsertSelect(plan) => assert!(matches!(plan.source(), Shard::Direct(_))),
+            _ => panic!("not an insert select"),
+        }
+
+        let route = query!("INSERT INTO sharded (id, email) VALUES (1, 'test@test.com')");
+        assert!(matches!(route.shard(), Shard::Direct(_)));
+    }
+
+    #[test]
+    fn test_join() {
+        let (command, _) = command!(
+            "SELECT sharded.id, users.email FROM sharded JOIN users ON sharded.user_id = users.id WHERE sharded.id = 1"
+        );
+        match command {
+            Command::Join(join) => {
+                assert_eq!(join.outer().table, "sharded");
+                assert!(matches!(join.outer().route.shard(), Shard::Direct(_)));
+                assert_eq!(join.inner().table, "users");
+                assert!(join.inner().route.shard().all());
+            }
+            _ => panic!("not a join"),
+        }
+    }
+
+    #[test]
+    fn test_co_located_join() {
+        // Key propagated through the join predicate.
+        let route = query!(
+            "SELECT * FROM sharded s JOIN sharded_omni o ON s.id = o.sharded_id WHERE o.sharded_id = 1"
+        );
+        assert!(matches!(route.shard(), Shard::Direct(_)));
+
+        // Key on an aliased table.
+        let route = parse!(
+            "SELECT s.id FROM sharded s JOIN sharded_omni o ON s.value = o.value WHERE s.id = $1",
+            ["1".as_bytes()]
+        );
+        assert!(matches!(route.shard(), Shard::Direct(_)));
+
+        // No key.
+        let route = query!("SELECT * FROM sharded s JOIN sharded_omni o ON s.id = o.sharded_id");
+        assert!(route.shard().all());
+    }
+
+    #[test]
+    fn test_join_key_not_co_located() {
+        let table = |name: &str, column: &str, data_type| ShardedTable {
+            database: "pgdog".into(),
+            name: Some(name.into()),
+            column: column.into(),
+            data_type,
+            ..Default::default()
+        };
+        let schema = ShardingSchema {
+            shards: 2,
+            tables: ShardedTables::new(
+                vec![
+                    table("orders", "customer_id", DataType::Bigint),
+                    table("customers", "id", DataType::Bigint),
+                    table("accounts", "id", DataType::Varchar),
+                ],
+                vec![],
+                false,
+            ),
+        };
+
+        let ast = parse(
+            "SELECT * FROM orders o JOIN customers c ON o.customer_id = c.id JOIN accounts a ON a.id = o.customer_id JOIN products p ON p.id = o.customer_id JOIN customers c2 ON c2.email = o.customer_id",
+        )
+        .unwrap();
+        let Some(NodeEnum::SelectStmt(ref stmt)) =
+            ast.protobuf.stmts[0].stmt.as_ref().unwrap().node
+        else {
+            panic!("not a select");
+        };
+        let from_clause = FromClause::new(&stmt.from_clause, &stmt.where_clause);
+        let orders = &from_clause.relations()[0];
+        let same_shard =
+            |alias, column| QueryParser::same_shard(&schema, &from_clause, orders, alias, column);
+
+        assert!(same_shard("o", "customer_id"));
+        // Co-located.
+        assert!(same_shard("c", "id"));
+        // Different data type.
+        assert!(!same_shard("a", "id"));
+        // Not sharded.
+        assert!(same_shard("p", "id"));
+        // Not the sharding key.
+        assert!(!same_shard("c2", "email"));
+    }
+
+    #[test]
+    fn test_strict() {
+        let mut cluster = Cluster::new_test();
+        cluster.set_strict(true);
+
+        let route = |query: &str| {
+            let buffer = Buffer::from(vec![Query::new(query).into()]);
+            let mut stmt = PreparedStatements::default();
+            let params = Parameters::default();
+            let context = RouterContext::new(&buffer, &cluster, &mut stmt, &params, false).unwrap();
+            QueryParser::default()
+                .parse(context)
+                .map(|command| command.clone())
+        };
+
+        assert!(matches!(
+            route("SELECT avg(value) FROM sharded"),
+            Err(Error::Strict(_))
+        ));
+        // Single shard queries are always correct.
+        assert!(route("SELECT avg(value) FROM sharded WHERE id = 1").is_ok());
+        // Omnisharded tables are read from one shard.
+        assert!(route("SELECT DISTINCT id FROM sharded_omni").is_ok());
+        // Checked in queries with multiple statements too.
+        assert!(matches!(
+            route("SET statement_timeout TO 1000; SELECT avg(value) FROM sharded"),
+            Err(Error::Strict(_))
+        ));
+    }
+
+    #[test]
+    fn test_all_shards_write() {
+        let schema = Cluster::new_test().sharding_schema();
+        let check = |sql: &str, all_shards_writes: AllShardsWrites| {
+            let query = BufferedQuery::Query(Query::new(sql));
+            let ast = parse(sql).unwrap();
+            let root = ast.protobuf.stmts[0]
+                .stmt
+                .as_ref()
+                .unwrap()
+                .as_ref()
+                .clone();
+            QueryParser::all_shards_write(&root, &query, &schema, all_shards_writes)
+        };
+
+        assert!(check("DELETE FROM sharded", AllShardsWrites::Allow).is_ok());
+        assert!(matches!(
+            check("DELETE FROM sharded", AllShardsWrites::Comment),
+            Err(Error::AllShardsWriteComment(_, _))
+        ));
+        assert!(check(
+            "/* pgdog_all_shards */ UPDATE sharded SET value = 1",
+            AllShardsWrites::Comment
+        )
+        .is_ok());
+        assert!(matches!(
+            check(
+                "/* pgdog_all_shards */ UPDATE sharded SET value = 1",
+                AllShardsWrites::Reject
+            ),
+            Err(Error::AllShardsWrite(_, _))
+        ));
+        // Not a sharded table.
+        assert!(check("DELETE FROM users", AllShardsWrites::Reject).is_ok());
+    }
+
+    #[test]
+    fn test_cursor() {
+        let cluster = Cluster::new_test();
+        let mut parser = QueryParser::default();
+        let mut route = |query: &str| {
+            let buffer = Buffer::from(vec![Query::new(query).into()]);
+            let mut stmt = PreparedStatements::default();
+            let params = Parameters::default();
+            let context = RouterContext::new(&buffer, &cluster, &mut stmt, &params, true).unwrap();
+            parser.parse(context).map(|command| command.clone())
+        };
+
+        match route("DECLARE c CURSOR FOR SELECT * FROM sharded ORDER BY id").unwrap() {
+            Command::Query(route) => assert!(route.shard().all()),
+            command => panic!("expected query, got {:?}", command),
+        }
+
+        match route("FETCH 100 FROM c").unwrap() {
+            Command::Fetch(fetch) => {
+                assert_eq!(fetch.cursor, "c");
+                assert_eq!(fetch.count, Some(100));
+                assert_eq!(fetch.order_by.len(), 1);
+            }
+            command => panic!("expected fetch, got {:?}", command),
+        }
+
+        // Leading comments don't hide the statement.
+        assert!(matches!(
+            route("/* app */ FETCH 10 FROM c").unwrap(),
+            Command::Fetch(fetch) if fetch.count == Some(10)
+        ));
+
+        assert!(matches!(
+            route("CLOSE c").unwrap(),
+            Command::CloseCursor(name) if name == "c"
+        ));
+
+        // Routing decision for the transaction is unchanged.
+        assert!(matches!(route("SELECT 1").unwrap(), Command::Query(route) if route.shard().all()));
+
+        assert!(matches!(
+            route("DECLARE d CURSOR WITH HOLD FOR SELECT * FROM sharded"),
+            Err(Error::CursorUnsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_cursor_statement() {
+        assert!(cursor_statement("FETCH 10 FROM c"));
+        assert!(cursor_statement("  declare c CURSOR FOR SELECT 1"));
+        assert!(cursor_statement("/* app */ MOVE NEXT IN c"));
+        assert!(cursor_statement("-- app\nCLOSE c"));
+        assert!(!cursor_statement("SELECT * FROM sharded"));
+        assert!(!cursor_statement("/* FETCH */ SELECT 1"));
+        assert!(!cursor_statement("closed"));
+    }
+
+    #[test]
+    fn test_explain() {
+        let (command, _) = command!("EXPLAIN ANALYZE SELECT * FROM sharded ORDER BY id");
+        match command {
+            Command::Explain(explain) => {
+                assert!(explain.analyze());
+                assert!(explain.route().shard().all());
+                assert_eq!(explain.route().order_by().len(), 1);
+            }
+            command => panic!("expected explain, got {:?}", command),
+        }
+
+        // Query goes to one shard.
+        let route = query!("EXPLAIN SELECT * FROM sharded WHERE id = 1");
+        assert!(matches!(route.shard(), Shard::Direct(_)));
+    }
+
+    #[test]
+    fn test_multiple_statements() {
+        let shard = |id: i64| {
+            query!(format!("SELECT * FROM sharded WHERE id = {}", id))
+                .shard()
+                .clone()
+        };
+
+        let route = query!(
+            "UPDATE sharded SET value = 1 WHERE id = 1; UPDATE sharded SET value = 2 WHERE id = 1"
+        );
+        assert!(matches!(route.shard(), Shard::Direct(_)));
+        assert!(route.is_write());
+
+        // Statements that don't touch sharded tables can go anywhere.
+        let route = query!("SELECT 1; DELETE FROM sharded WHERE id = 1");
+        assert!(matches!(route.shard(), Shard::Direct(_)));
+        assert!(route.is_write());
+
+        let route = query!("SET statement_timeout TO 1000; SELECT * FROM sharded WHERE id = 1");
+        assert!(matches!(route.shard(), Shard::Direct(_)));
+        assert!(route.is_read());
+
+        // Statements going to different shards are rejected.
+        let other = (2..100).find(|id| shard(*id) != shard(1)).unwrap();
+        let sql = format!(
+            "UPDATE sharded SET value = 1 WHERE id = 1; UPDATE sharded SET value = 1 WHERE id = {}",
+            other
+        );
+        let buffer = Buffer::from(vec![Query::new(&sql).into()]);
+        let cluster = Cluster::new_test();
+        let mut stmt = PreparedStatements::default();
+        let params = Parameters::default();
+        let context = RouterContext::new(&buffer, &cluster, &mut stmt, &params, false).unwrap();
+        assert!(matches!(
+            QueryParser::default().parse(context),
+            Err(Error::MultiStatementShards(_, _))
+        ));
+
+        let sql = "SELECT * FROM sharded WHERE id = 1; SELECT * FROM sharded";
+        let buffer = Buffer::from(vec![Query::new(sql).into()]);
+        let mut stmt = PreparedStatements::default();
+        let context = RouterContext::new(&buffer, &cluster, &mut stmt, &params, false).unwrap();
+        assert!(matches!(
+            QueryParser::default().parse(context),
+            Err(Error::MultiStatementShards(_, _))
+        ));
+
+        // Tables that aren't sharded are read from the same shard.
+        let route = query!("SELECT * FROM users; SELECT * FROM sharded WHERE id = 1");
+        assert!(matches!(route.shard(), Shard::Direct(_)));
+
+        // The route is the statement's route.
+        let route = query!(
+            "SET statement_timeout TO 1000; SELECT * FROM sharded WHERE id = 1 ORDER BY id LIMIT 5"
+        );
+        assert_eq!(route.order_by().len(), 1);
+        assert_eq!(route.limit().limit, Some(5));
+
+        // Results that need merging are rejected.
+        for sql in [
+            "SET statement_timeout TO 1000; SELECT * FROM sharded ORDER BY id",
+            "SET statement_timeout TO 1000; SELECT count(*) FROM sharded",
+            "SET statement_timeout TO 1000; SELECT * FROM sharded LIMIT 5",
+            "SET statement_timeout TO 1000; INSERT INTO sharded (id, email) SELECT id, email FROM users",
+        ] {
+            let buffer = Buffer::from(vec![Query::new(sql).into()]);
+            let mut stmt = PreparedStatements::default();
+            let context = RouterContext::new(&buffer, &cluster, &mut stmt, &params, false).unwrap();
+            assert!(
+                matches!(
+                    QueryParser::default().parse(context),
+                    Err(Error::MultiStatementUnsupported(_))
+                ),
+                "{}",
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn test_nested_sharding_key() {
+        let shard = query!("SELECT * FROM sharded WHERE id = 1").shard().clone();
+        assert!(matches!(shard, Shard::Direct(_)));
+
+        let route = query!("WITH x AS (SELECT * FROM sharded WHERE id = 1) SELECT * FROM x");
+        assert_eq!(route.shard(), &shard);
+
+        let route =
+            query!("SELECT * FROM sharded_omni WHERE id IN (SELECT id FROM sharded WHERE id = 1)");
+        assert_eq!(route.shard(), &shard);
+
+        let route = query!("SELECT * FROM (SELECT * FROM sharded WHERE id = 1) s");
+        assert_eq!(route.shard(), &shard);
+
+        // Subqueries under OR don't constrain the statement.
+        let route = query!(
+            "SELECT * FROM sharded_omni WHERE value = 2 OR id IN (SELECT id FROM sharded WHERE id = 1)"
+        );
+        assert!(route.shard().all());
+
+        let route = query!(
+            "UPDATE sharded s SET value = 1 FROM sharded_omni o WHERE s.id = o.id AND o.id = 1"
+        );
+        assert_eq!(route.shard(), &shard);
+        assert!(route.is_write());
+
+        let route = query!(
+            "DELETE FROM sharded WHERE id IN (WITH x AS (SELECT 1) SELECT id FROM sharded WHERE id = 1)"
+        );
+        assert_eq!(route.shard(), &shard);
+        let route = query!(
+            "WITH c AS (SELECT * FROM sharded WHERE id = 1) SELECT * FROM sharded JOIN c ON sharded.id = c.id"
+        );
+        assert_eq!(route.shard(), &shard);
+
+        // Keys in subqueries not joined on the sharding key don't constrain the statement.
+        let route =
+            query!("DELETE FROM sharded WHERE value IN (SELECT value FROM sharded WHERE id = 1)");
+        assert!(route.shard().all());
+
+        let route = query!(
+            "WITH c AS (SELECT * FROM sharded WHERE id = 1) SELECT * FROM sharded JOIN c ON sharded.value = c.value"
+        );
+        assert!(route.shard().all());
+
+        let route =
+            query!("SELECT * FROM sharded WHERE id = (SELECT value FROM sharded WHERE id = 1)");
+        assert!(route.shard().all());
+    }
+
+    #[test]
+    fn test_volatile_functions() {
+        let cluster = Cluster::new_test();
+        cluster.set_schema(Schema::new_test(&[
+            ("create_order", Volatility::Volatile),
+            ("get_order", Volatility::Stable),
+        ]));
+        let route = |query: &str| {
+            let buffer = Buffer::from(vec![Query::new(query).into()]);
+            let mut stmt = PreparedStatements::default();
+            let params = Parameters::default();
+            let context = RouterContext::new(&buffer, &cluster, &mut stmt, &params, false).unwrap();
+            match QueryParser::default().parse(context).unwrap().clone() {
+                Command::Query(route) => route,
+                command => panic!("expected query, got {:?}", command),
+            }
+        };
+
+        assert!(route("SELECT create_order(1)").is_write());
+        assert!(route("SELECT get_order(1)").is_read());
+        assert!(route("SELECT random()").is_read());
+        // Functions are found anywhere in the statement.
+        assert!(route("SELECT get_order(1), create_order(2)").is_write());
+        assert!(route("SELECT * FROM create_order(1)").is_write());
+        assert!(route("SELECT * FROM sharded WHERE id = abs(create_order(1))").is_write());
+        assert!(route("SELECT * FROM sharded WHERE id IN (SELECT create_order(1))").is_write());
+        assert!(route("SELECT get_order(1) FROM sharded FOR UPDATE").is_write());
+        assert!(route("SELECT * FROM sharded WHERE id = get_order(1)").is_read());
+    }
+
+    #[test]
+    fn test_read_only_transaction() {
+        let cluster = Cluster::new_test();
+        let route = |qp: &mut QueryParser, query: &str, in_transaction: bool| {
+            let buffer = Buffer::from(vec![Query::new(query).into()]);
+            let mut stmt = PreparedStatements::default();
+            let params = Parameters::default();
+            let context =
+                RouterContext::new(&buffer, &cluster, &mut stmt, &params, in_transaction).unwrap();
+            qp.parse(context).unwrap().clone()
+        };
+
+        for begin in [
+            "BEGIN READ ONLY",
+            "START TRANSACTION ISOLATION LEVEL SERIALIZABLE, READ ONLY",
+        ] {
+            let mut qp = QueryParser::default();
+            assert!(matches!(
+                route(&mut qp, begin, false),
+                Command::StartTransaction(_)
+            ));
+            assert!(qp.read_only_transaction);
+            assert_eq!(qp.write_override, None);
+
+            match route(&mut qp, "SELECT * FROM sharded WHERE id = 1", true) {
+                Command::Query(route) => assert!(route.is_read()),
+                command => panic!("expected query, got {:?}", command),
+            }
+
+            // Locked in for the rest of the transaction.
+            match route(
+                &mut qp,
+                "SELECT * FROM sharded WHERE id = 1 FOR UPDATE",
+                true,
+            ) {
+                Command::Query(route) => assert!(route.is_read()),
+                command => panic!("expected query, got {:?}", command),
+            }
+
+            qp.reset();
+            assert!(!qp.read_only_transaction);
+        }
+
+        let mut qp = QueryParser::default();
+        route(&mut qp, "BEGIN", false);
+        assert_eq!(qp.write_override, Some(true));
+        route(&mut qp, "SET TRANSACTION READ ONLY", true);
+        assert!(qp.read_only_transaction);
+        match route(&mut qp, "SELECT * FROM sharded WHERE id = 1", true) {
+            Command::Query(route) => assert!(route.is_read()),
+            command => panic!("expected query, got {:?}", command),
+        }
+
+        let mut qp = QueryParser::default();
+        route(&mut qp, "BEGIN READ WRITE", false);
+        assert!(!qp.read_only_transaction);
+        match route(&mut qp, "SELECT * FROM sharded WHERE id = 1", true) {
+            Command::Query(route) => assert!(route.is_write()),
+            command => panic!("expected query, got {:?}", command),
+        }
+    }
+
+    #[test]
+    fn test_role() {
+        let cluster = Cluster::new_test();
+        let route =
+            |qp: &mut QueryParser, query: &str, params: &Parameters, in_transaction: bool| {
+                let buffer = Buffer::from(vec![Query::new(query).into()]);
+                let mut stmt = PreparedStatements::default();
+                let context =
+                    RouterContext::new(&buffer, &cluster, &mut stmt, params, in_transaction)
+                        .unwrap();
+                qp.parse(context).map(|command| command.clone())
+            };
+
+        // Outside of a transaction, the role is saved in the session.
+        let mut qp = QueryParser::default();
+        match route(
+            &mut qp,
+            "SET pgdog.role TO 'replica'",
+            &Parameters::default(),
+            false,
+        )
+        .unwrap()
+        {
+            Command::Set { name, value } => {
+                assert_eq!(name, "pgdog.role");
+                assert_eq!(value, ParameterValue::from("replica"));
+            }
+            command => panic!("expected set, got {:?}", command),
+        }
+
+        let mut params = Parameters::default();
+        params.insert("pgdog.role", "primary");
+        let mut qp = QueryParser::default();
+        match route(
+            &mut qp,
+            "SELECT * FROM sharded WHERE id = 1",
+            &params,
+            false,
+        )
+        .unwrap()
+        {
+            Command::Query(route) => assert!(route.is_write()),
+            command => panic!("expected query, got {:?}", command),
+        }
+
+        // Inside a transaction, the role overrides the session.
+        let mut qp = QueryParser::default();
+        route(&mut qp, "BEGIN", &params, false).unwrap();
+        route(&mut qp, "SET pgdog.role TO replica", &params, true).unwrap();
+        match route(
+            &mut qp,
+            "UPDATE sharded SET value = 1 WHERE id = 1",
+            &params,
+            true,
+        )
+        .unwrap()
+        {
+            Command::Query(route) => {
+                assert!(route.is_read());
+                // Sent to a replica by the client, not because it's a read.
+                assert!(!route.is_retryable());
+            }
+            command => panic!("expected query, got {:?}", command),
+        }
+
+        qp.reset();
+        let mut replica = Parameters::default();
+        replica.insert("pgdog.role", "replica");
+        match route(
+            &mut qp,
+            "SELECT * FROM sharded WHERE id = 1",
+            &replica,
+            false,
+        )
+        .unwrap()
+        {
+            Command::Query(route) => assert!(route.is_retryable()),
+            command => panic!("expected query, got {:?}", command),
+        }
+
+        qp.reset();
+        match route(&mut qp, "DELETE FROM sharded WHERE id = 1", &replica, false).unwrap() {
+            Command::Query(route) => {
+                assert!(route.is_read());
+                assert!(!route.is_retryable());
+            }
+            command => panic!("expected query, got {:?}", command),
+        }
+
+        qp.reset();
+        match route(
+            &mut qp,
+            "SELECT * FROM sharded WHERE id = 1",
+            &params,
+            false,
+        )
+        .unwrap()
+        {
+            Command::Query(route) => assert!(route.is_write()),
+            command => panic!("expected query, got {:?}", command),
+        }
+
+        assert!(matches!(
+            route(&mut qp, "SET pgdog.role TO 'standby'", &params, false),
+            Err(Error::SetRole(_))
+        ));
+    }
 }
@@ -0,0 +1,77 @@
+//! Database role requested by the client with `pgdog.role`.
+
+use std::{fmt::Display, str::FromStr};
+
+use super::Error;
+
+/// Name of the parameter.
+pub const ROLE_PARAMETER: &str = "pgdog.role";
+
+/// Role the client wants its queries sent to,
+/// overriding read/write splitting.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TargetRole {
+    /// All queries go to the primary.
+    Primary,
+    /// All queries go to replicas.
+    Replica,
+    /// Read/write splitting decides.
+    #[default]
+    Auto,
+}
+
+impl FromStr for TargetRole {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "primary" => Ok(Self::Primary),
+            "replica" => Ok(Self::Replica),
+            "auto" => Ok(Self::Auto),
+            _ => Err(Error::SetRole(s.to_owned())),
+        }
+    }
+}
+
+impl Display for TargetRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Primary => write!(f, "primary"),
+            Self::Replica => write!(f, "replica"),
+            Self::Auto => write!(f, "auto"),
+        }
+    }
+}
+
+impl TargetRole {
+    /// Queries should be sent to replicas. `None` means read/write splitting decides.
+    pub fn read(&self) -> Option<bool> {
+        match self {
+            Self::Primary => Some(false),
+            Self::Replica => Some(true),
+            Self::Auto => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_target_role() {
+        assert_eq!(
+            "Primary".parse::<TargetRole>().unwrap(),
+            TargetRole::Primary
+        );
+        assert_eq!(
+            "replica".parse::<TargetRole>().unwrap(),
+            TargetRole::Replica
+        );
+        assert_eq!(TargetRole::Auto.to_string(), "auto");
+        assert!(matches!(
+            "standby".parse::<TargetRole>(),
+            Err(Error::SetRole(_))
+        ));
+    }
+}
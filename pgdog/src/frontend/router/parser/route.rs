@@ -32,6 +32,15 @@ impl Shard {
     pub fn direct(shard: usize) -> Self {
         Self::Direct(shard)
     }
+
+    /// This shard selection includes the given shard number.
+    pub fn includes(&self, shard: usize) -> bool {
+        match self {
+            Shard::All => true,
+            Shard::Direct(direct) => *direct == shard,
+            Shard::Multi(multi) => multi.contains(&shard),
+        }
+    }
 }
 
 impl From<Option<usize>> for Shard {
@@ -44,6 +53,27 @@ impl From<Option<usize>> for Shard {
     }
 }
 
+/// Where the sharding key was found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeySource {
+    /// `WHERE` clause.
+    Where,
+    /// Query comment, e.g. `/* pgdog_shard: 1 */`.
+    Comment,
+    /// `SET pgdog.shard` or `SET pgdog.sharding_key`.
+    Set,
+}
+
+impl Display for KeySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeySource::Where => write!(f, "WHERE"),
+            KeySource::Comment => write!(f, "comment"),
+            KeySource::Set => write!(f, "SET"),
+        }
+    }
+}
+
 /// Path a query should take and any transformations
 /// that should be applied along the way.
 #[derive(Debug, Clone, Default)]
@@ -54,6 +84,9 @@ pub struct Route {
     aggregate: Aggregate,
     limit: Limit,
     lock_session: bool,
+    critical: bool,
+    retryable: bool,
+    key: Option<(String, KeySource)>,
 }
 
 impl Display for Route {
@@ -136,6 +169,10 @@ impl Route {
         self.shard = Shard::Direct(shard);
     }
 
+    pub fn set_shards_mut(&mut self, shard: Shard) {
+        self.shard = shard;
+    }
+
     pub fn set_shard(mut self, shard: usize) -> Self {
         self.set_shard_mut(shard);
         self
@@ -167,11 +204,20 @@ impl Route {
         let FunctionBehavior {
             writes,
             locking_behavior,
+            volatile,
         } = write;
         self.read = !writes;
+        self.retryable = !writes && !volatile;
         self.lock_session = matches!(locking_behavior, LockingBehavior::Lock);
     }
 
+    /// The parser found the statement to be a read without volatile functions,
+    /// so it can be sent to another server if this one fails. Roles requested
+    /// by the client don't change that.
+    pub fn is_retryable(&self) -> bool {
+        self.read && self.retryable
+    }
+
     pub fn set_lock_session(mut self) -> Self {
         self.lock_session = true;
         self
@@ -180,4 +226,27 @@ impl Route {
     pub fn lock_session(&self) -> bool {
         self.lock_session
     }
+
+    /// Sharding key used to pick the shard, e.g. `id = 1`, and where it was found.
+    pub fn key(&self) -> Option<&(String, KeySource)> {
+        self.key.as_ref()
+    }
+
+    pub fn set_key(mut self, key: Option<(String, KeySource)>) -> Self {
+        self.set_key_mut(key);
+        self
+    }
+
+    pub fn set_key_mut(&mut self, key: Option<(String, KeySource)>) {
+        self.key = key;
+    }
+
+    /// Read can be sent to the primary if replicas are down.
+    pub fn is_critical(&self) -> bool {
+        self.critical
+    }
+
+    pub fn set_critical_mut(&mut self, critical: bool) {
+        self.critical = critical;
+    }
 }
@@ -0,0 +1,300 @@
+//! Strict sharding mode.
+//!
+//! Multi-shard queries are checked against what pgdog can merge
+//! and rejected if the results returned to the client would be wrong.
+use pg_query::{
+    protobuf::{FuncCall, SelectStmt},
+    NodeEnum, NodeRef,
+};
+
+use crate::backend::ShardingSchema;
+
+use super::{Aggregate, Error, FromClause};
+
+/// Aggregates merged by pgdog.
+static MERGED: &[&str] = &["count", "min", "max", "sum"];
+
+/// Built-in aggregates pgdog can't merge.
+static AGGREGATES: &[&str] = &[
+    "avg",
+    "array_agg",
+    "bit_and",
+    "bit_or",
+    "bit_xor",
+    "bool_and",
+    "bool_or",
+    "corr",
+    "covar_pop",
+    "covar_samp",
+    "every",
+    "json_agg",
+    "json_object_agg",
+    "jsonb_agg",
+    "jsonb_object_agg",
+    "mode",
+    "percentile_cont",
+    "percentile_disc",
+    "range_agg",
+    "range_intersect_agg",
+    "regr_avgx",
+    "regr_avgy",
+    "regr_count",
+    "regr_intercept",
+    "regr_r2",
+    "regr_slope",
+    "regr_sxx",
+    "regr_sxy",
+    "regr_syy",
+    "stddev",
+    "stddev_pop",
+    "stddev_samp",
+    "string_agg",
+    "var_pop",
+    "var_samp",
+    "variance",
+    "xmlagg",
+];
+
+/// Check a multi-shard `SELECT` can be answered correctly.
+pub struct Strict<'a> {
+    stmt: &'a SelectStmt,
+    schema: &'a ShardingSchema,
+}
+
+impl<'a> Strict<'a> {
+    /// Create new strict mode check.
+    pub fn new(stmt: &'a SelectStmt, schema: &'a ShardingSchema) -> Self {
+        Self { stmt, schema }
+    }
+
+    /// Check the statement, returning an error naming
+    /// the first construct pgdog can't merge across shards.
+    pub fn check(&self) -> Result<(), Error> {
+        if !self.stmt.distinct_clause.is_empty() {
+            return Err(Error::Strict("DISTINCT".into()));
+        }
+
+        if self.stmt.limit_offset.is_some() {
+            return Err(Error::Strict("OFFSET".into()));
+        }
+
+        if self.stmt.having_clause.is_some() {
+            return Err(Error::Strict("HAVING".into()));
+        }
+
+        if !self.stmt.window_clause.is_empty() {
+            return Err(Error::Strict("WINDOW".into()));
+        }
+
+        self.targets()?;
+        self.group_by()?;
+        self.join()
+    }
+
+    /// Window functions and aggregates in the target list.
+    fn targets(&self) -> Result<(), Error> {
+        for target in &self.stmt.target_list {
+            let Some(NodeEnum::ResTarget(ref target)) = target.node else {
+                continue;
+            };
+            let Some(val) = target.val.as_ref().and_then(|val| val.node.as_ref()) else {
+                continue;
+            };
+
+            // count(*), min(x), etc. are merged as long as they
+            // are not part of an expression.
+            if let NodeEnum::FuncCall(ref func) = val {
+                if func.over.is_none()
+                    && !func.agg_distinct
+                    && !func.agg_within_group
+                    && MERGED.contains(&name(func).as_str())
+                {
+                    continue;
+                }
+            }
+
+            for (node, _, _, _) in val.nodes() {
+                let NodeRef::FuncCall(func) = node else {
+                    continue;
+                };
+                let name = name(func);
+
+                if func.over.is_some() {
+                    return Err(Error::Strict(format!("window function \"{}\"", name)));
+                }
+
+                if func.agg_distinct {
+                    return Err(Error::Strict(format!("{}(DISTINCT ...)", name)));
+                }
+
+                if name == "avg" {
+                    return Err(Error::Strict("AVG".into()));
+                }
+
+                if MERGED.contains(&name.as_str()) {
+                    return Err(Error::Strict(format!(
+                        "aggregate \"{}\" inside an expression",
+                        name
+                    )));
+                }
+
+                if func.agg_within_group || AGGREGATES.contains(&name.as_str()) {
+                    return Err(Error::Strict(format!("aggregate \"{}\"", name)));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Groups are merged only if they reference the target list by position.
+    fn group_by(&self) -> Result<(), Error> {
+        if self.stmt.group_clause.is_empty() {
+            return Ok(());
+        }
+
+        let aggregate = Aggregate::parse(self.stmt)?;
+
+        if aggregate.is_empty() {
+            return Err(Error::Strict("GROUP BY without aggregates".into()));
+        }
+
+        if aggregate.group_by().len() != self.stmt.group_clause.len() {
+            return Err(Error::Strict(
+                "GROUP BY not referencing columns by position".into(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Joins are correct on each shard only if all sharded tables are co-located
+    /// and joined on their sharding keys.
+    fn join(&self) -> Result<(), Error> {
+        let from_clause = FromClause::new(&self.stmt.from_clause, &self.stmt.where_clause);
+        if !from_clause.join() {
+            return Ok(());
+        }
+
+        let tables = self.schema.tables();
+        let sharded = from_clause
+            .relations()
+            .iter()
+            .filter_map(|relation| tables.table(relation.name).map(|table| (relation, table)))
+            .collect::<Vec<_>>();
+
+        let Some((first, first_table)) = sharded.first() else {
+            return Ok(());
+        };
+
+        for relation in from_clause.relations() {
+            if tables.table(relation.name).is_none() && !tables.omnishards().contains(relation.name)
+            {
+                return Err(Error::Strict(format!(
+                    "join between \"{}\" and \"{}\"",
+                    first.name, relation.name
+                )));
+            }
+        }
+
+        let keys = from_clause.equivalent(first.alias, &first_table.column);
+        for (relation, table) in sharded.iter().skip(1) {
+            if !tables.co_located(first.name, relation.name)
+                || !keys.contains(&(relation.alias, table.column.as_str()))
+            {
+                return Err(Error::Strict(format!(
+                    "join between \"{}\" and \"{}\"",
+                    first.name, relation.name
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Function name, without the schema.
+fn name(func: &FuncCall) -> String {
+    func.funcname
+        .last()
+        .and_then(|name| match name.node {
+            Some(NodeEnum::String(ref name)) => Some(name.sval.to_lowercase()),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use pg_query::parse;
+
+    use crate::backend::Cluster;
+
+    use super::*;
+
+    fn check(query: &str) -> Result<(), Error> {
+        let cluster = Cluster::new_test();
+        let schema = cluster.sharding_schema();
+        let ast = parse(query).unwrap();
+        let stmt = ast.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+        match stmt.node {
+            Some(NodeEnum::SelectStmt(ref stmt)) => Strict::new(stmt, &schema).check(),
+            _ => panic!("not a select"),
+        }
+    }
+
+    fn construct(query: &str) -> String {
+        match check(query) {
+            Err(Error::Strict(construct)) => construct,
+            result => panic!("expected strict mode error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_strict_allowed() {
+        for query in [
+            "SELECT * FROM sharded",
+            "SELECT id, email FROM sharded WHERE email = 'test' ORDER BY id LIMIT 5",
+            "SELECT count(*), max(id), min(id), sum(value), email FROM sharded GROUP BY 5",
+            "SELECT * FROM sharded JOIN sharded_omni ON sharded.id = sharded_omni.id",
+            "SELECT lower(email) FROM sharded",
+        ] {
+            assert!(check(query).is_ok(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_strict_rejected() {
+        assert_eq!(construct("SELECT DISTINCT email FROM sharded"), "DISTINCT");
+        assert_eq!(construct("SELECT * FROM sharded OFFSET 10"), "OFFSET");
+        assert_eq!(construct("SELECT avg(value) FROM sharded"), "AVG");
+        assert_eq!(
+            construct("SELECT id, row_number() OVER (ORDER BY id) FROM sharded"),
+            "window function \"row_number\""
+        );
+        assert_eq!(
+            construct("SELECT string_agg(email, ',') FROM sharded"),
+            "aggregate \"string_agg\""
+        );
+        assert_eq!(
+            construct("SELECT percentile_cont(0.5) WITHIN GROUP (ORDER BY value) FROM sharded"),
+            "aggregate \"percentile_cont\""
+        );
+        assert_eq!(
+            construct("SELECT count(DISTINCT email) FROM sharded"),
+            "count(DISTINCT ...)"
+        );
+        assert_eq!(
+            construct("SELECT count(*) + 1 FROM sharded"),
+            "aggregate \"count\" inside an expression"
+        );
+        assert_eq!(
+            construct("SELECT email FROM sharded GROUP BY email"),
+            "GROUP BY without aggregates"
+        );
+        assert_eq!(
+            construct("SELECT * FROM sharded JOIN users ON sharded.user_id = users.id"),
+            "join between \"sharded\" and \"users\""
+        );
+    }
+}
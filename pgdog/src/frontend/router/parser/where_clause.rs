@@ -15,6 +15,8 @@ pub struct Column<'a> {
     pub table: Option<&'a str>,
     /// Column name.
     pub name: &'a str,
+    /// Keys inside a JSON column, e.g. `data->'org'->>'id'`.
+    pub path: Vec<&'a str>,
 }
 
 #[derive(Debug)]
@@ -50,21 +52,32 @@ impl<'a> WhereClause<'a> {
     }
 
     pub fn keys(&self, table_name: Option<&str>, column_name: &str) -> Vec<Key> {
+        self.keys_path(table_name, column_name, &[])
+    }
+
+    /// Get sharding keys stored in a JSON column at the given path,
+    /// e.g. `data->>'tenant_id' = $1`.
+    pub fn keys_path(
+        &self,
+        table_name: Option<&str>,
+        column_name: &str,
+        path: &[&str],
+    ) -> Vec<Key> {
         let mut keys = vec![];
         for output in &self.output {
-            keys.extend(Self::search_for_keys(output, table_name, column_name));
+            keys.extend(Self::search_for_keys(output, table_name, column_name, path));
         }
         keys
     }
 
-    fn column_match(column: &Column, table: Option<&str>, name: &str) -> bool {
+    fn column_match(column: &Column, table: Option<&str>, name: &str, path: &[&str]) -> bool {
         if let (Some(table), Some(other_table)) = (table, &column.table) {
             if &table != other_table {
                 return false;
             }
         };
 
-        column.name == name
+        column.name == name && column.path == path
     }
 
     fn get_key(output: &Output) -> Option<Key> {
@@ -76,7 +89,12 @@ impl<'a> WhereClause<'a> {
         }
     }
 
-    fn search_for_keys(output: &Output, table_name: Option<&str>, column_name: &str) -> Vec<Key> {
+    fn search_for_keys(
+        output: &Output,
+        table_name: Option<&str>,
+        column_name: &str,
+        path: &[&str],
+    ) -> Vec<Key> {
         let mut keys = vec![];
 
         if let Output::Filter(ref left, ref right) = output {
@@ -87,7 +105,7 @@ impl<'a> WhereClause<'a> {
                 // TODO: Handle something like
                 // id = (SELECT 5) which is stupid but legal SQL.
                 (&[Output::Column(ref column)], output) => {
-                    if Self::column_match(column, table_name, column_name) {
+                    if Self::column_match(column, table_name, column_name, path) {
                         for output in output.iter() {
                             if let Some(key) = Self::get_key(output) {
                                 keys.push(key);
@@ -96,7 +114,7 @@ impl<'a> WhereClause<'a> {
                     }
                 }
                 (output, &[Output::Column(ref column)]) => {
-                    if Self::column_match(column, table_name, column_name) {
+                    if Self::column_match(column, table_name, column_name, path) {
                         for output in output.iter() {
                             if let Some(key) = Self::get_key(output) {
                                 keys.push(key);
@@ -107,18 +125,18 @@ impl<'a> WhereClause<'a> {
 
                 _ => {
                     for output in left {
-                        keys.extend(Self::search_for_keys(output, table_name, column_name));
+                        keys.extend(Self::search_for_keys(output, table_name, column_name, path));
                     }
 
                     for output in right {
-                        keys.extend(Self::search_for_keys(output, table_name, column_name));
+                        keys.extend(Self::search_for_keys(output, table_name, column_name, path));
                     }
                 }
             }
         }
 
         if let Output::NullCheck(c) = output {
-            if c.name == column_name && c.table == table_name {
+            if c.name == column_name && c.table == table_name && c.path == path {
                 keys.push(Key::Null);
             }
         }
@@ -136,6 +154,42 @@ impl<'a> WhereClause<'a> {
         None
     }
 
+    /// Parse a JSON field reference, e.g. `data->>'tenant_id'`.
+    ///
+    /// Only text values, i.e. `->>` as the last operator, are compared to sharding keys.
+    fn json_column(table_name: Option<&'a str>, expr: &'a AExpr) -> Option<Column<'a>> {
+        Self::json_path(table_name, expr, "->>")
+    }
+
+    /// Parse a JSON field reference ending with the given operator.
+    fn json_path(table_name: Option<&'a str>, expr: &'a AExpr, op: &str) -> Option<Column<'a>> {
+        if expr.kind() != AExprKind::AexprOp || Self::string(expr.name.first()) != Some(op) {
+            return None;
+        }
+
+        let key = match expr.rexpr.as_ref().and_then(|node| node.node.as_ref()) {
+            Some(NodeEnum::AConst(AConst {
+                val: Some(Val::Sval(ref key)),
+                ..
+            })) => key.sval.as_str(),
+            _ => return None,
+        };
+
+        let mut column = match expr.lexpr.as_ref().and_then(|node| node.node.as_ref()) {
+            Some(NodeEnum::AExpr(ref expr)) => Self::json_path(table_name, expr, "->")?,
+            Some(NodeEnum::ColumnRef(_)) => {
+                match Self::parse(table_name, expr.lexpr.as_ref()?).pop() {
+                    Some(Output::Column(column)) => column,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+
+        column.path.push(key);
+        Some(column)
+    }
+
     fn parse(table_name: Option<&'a str>, node: &'a Node) -> Vec<Output<'a>> {
         let mut keys = vec![];
 
@@ -168,6 +222,10 @@ impl<'a> WhereClause<'a> {
             }
 
             Some(NodeEnum::AExpr(ref expr)) => {
+                if let Some(column) = Self::json_column(table_name, expr) {
+                    return vec![Output::Column(column)];
+                }
+
                 if matches!(expr.kind(), AExprKind::AexprOp | AExprKind::AexprIn) {
                     let op = Self::string(expr.name.first());
                     if let Some(op) = op {
@@ -207,7 +265,11 @@ impl<'a> WhereClause<'a> {
                 };
 
                 if let Some(name) = name {
-                    return vec![Output::Column(Column { name, table })];
+                    return vec![Output::Column(Column {
+                        name,
+                        table,
+                        path: vec![],
+                    })];
                 }
             }
 
@@ -295,4 +357,44 @@ mod test {
             panic!("not a select");
         }
     }
+
+    #[test]
+    fn test_json_path() {
+        let query = "SELECT * FROM users WHERE data->'org'->>'id' = 'acme' AND users.data->>'tenant_id' = $1";
+        let ast = parse(query).unwrap();
+        let stmt = ast.protobuf.stmts.first().cloned().unwrap().stmt.unwrap();
+
+        if let Some(NodeEnum::SelectStmt(stmt)) = stmt.node {
+            let where_ = WhereClause::new(Some("users"), &stmt.where_clause).unwrap();
+            assert_eq!(
+                where_.keys_path(Some("users"), "data", &["tenant_id"]),
+                vec![Key::Parameter(0)]
+            );
+            assert_eq!(
+                where_.keys_path(Some("users"), "data", &["org", "id"]),
+                vec![Key::Constant("acme".into())]
+            );
+            assert!(where_.keys(Some("users"), "data").is_empty());
+        } else {
+            panic!("not a select");
+        }
+
+        // JSON values aren't text and intermediate keys must be objects.
+        let query =
+            "SELECT * FROM users WHERE data->'tenant_id' = '5' AND data->>'org'->>'id' = 'acme'";
+        let ast = parse(query).unwrap();
+        let stmt = ast.protobuf.stmts.first().cloned().unwrap().stmt.unwrap();
+
+        if let Some(NodeEnum::SelectStmt(stmt)) = stmt.node {
+            let where_ = WhereClause::new(Some("users"), &stmt.where_clause).unwrap();
+            assert!(where_
+                .keys_path(Some("users"), "data", &["tenant_id"])
+                .is_empty());
+            assert!(where_
+                .keys_path(Some("users"), "data", &["org", "id"])
+                .is_empty());
+        } else {
+            panic!("not a select");
+        }
+    }
 }
@@ -0,0 +1,140 @@
+//! Explain the routing decision for a query.
+//!
+//! The query parser is executed offline, without connecting to any servers,
+//! using the cluster's configuration and sharding schema.
+
+use pg_query::normalize;
+
+use crate::{
+    backend::Cluster,
+    frontend::{Buffer, PreparedStatements},
+    net::{
+        messages::{Bind, Parameter, Parse, Query},
+        Parameters,
+    },
+};
+
+use super::{parser::Cache, Command, Error, KeySource, QueryParser, Route, RouterContext};
+
+/// Routing decision for a query.
+#[derive(Debug)]
+pub struct RouteInfo {
+    /// Command returned by the parser.
+    pub command: Command,
+    /// Route the query would take.
+    pub route: Route,
+    /// Sharding key, if any, and where it was found.
+    pub key: Option<(String, KeySource)>,
+    /// Query is in the AST cache.
+    pub cached: bool,
+}
+
+impl RouteInfo {
+    /// Route the query. If parameters are given, the query is sent
+    /// using the extended protocol, with parameters in text format.
+    /// `None` is a `NULL` parameter.
+    pub fn new(cluster: &Cluster, query: &str, params: &[Option<String>]) -> Result<Self, Error> {
+        let cached = Self::cached(query);
+
+        let buffer = if params.is_empty() {
+            Buffer::from(vec![Query::new(query).into()])
+        } else {
+            let params = params
+                .iter()
+                .map(|param| match param {
+                    Some(param) => Parameter {
+                        len: param.len() as i32,
+                        data: param.as_bytes().to_vec(),
+                    },
+                    None => Parameter {
+                        len: -1,
+                        data: vec![],
+                    },
+                })
+                .collect::<Vec<_>>();
+            Buffer::from(vec![
+                Parse::new_anonymous(query).into(),
+                Bind::new_params("", &params).into(),
+            ])
+        };
+
+        let mut prepared_statements = PreparedStatements::default();
+        let client_params = Parameters::default();
+        let context = RouterContext::new(
+            &buffer,
+            cluster,
+            &mut prepared_statements,
+            &client_params,
+            false,
+        )?;
+
+        let mut parser = QueryParser::default();
+        parser.uncached();
+        let command = parser.parse(context)?.clone();
+        let route = match command {
+            Command::Query(ref route) => route.clone(),
+            _ => parser.route(),
+        };
+
+        Ok(Self {
+            key: route.key().cloned(),
+            command,
+            route,
+            cached,
+        })
+    }
+
+    /// Check if the query, or its normalized version, is in the AST cache.
+    fn cached(query: &str) -> bool {
+        let queries = Cache::queries();
+        queries.contains_key(query)
+            || normalize(query)
+                .map(|query| queries.contains_key(&query))
+                .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::frontend::router::parser::Shard;
+
+    use super::*;
+
+    #[test]
+    fn test_route_info() {
+        let cluster = Cluster::new_test();
+
+        let info = RouteInfo::new(&cluster, "SELECT * FROM sharded WHERE id = 1", &[]).unwrap();
+        assert!(matches!(info.command, Command::Query(_)));
+        assert!(matches!(info.route.shard(), Shard::Direct(_)));
+        assert!(info.route.is_read());
+        assert_eq!(info.key, Some(("id = 1".into(), KeySource::Where)));
+
+        let info = RouteInfo::new(
+            &cluster,
+            "UPDATE sharded SET value = 1 WHERE id = $1",
+            &[Some("5".into())],
+        )
+        .unwrap();
+        assert!(matches!(info.route.shard(), Shard::Direct(_)));
+        assert!(info.route.is_write());
+        assert_eq!(info.key, Some(("id = $1".into(), KeySource::Where)));
+        // Routing a query doesn't add it to the cache.
+        assert!(!Cache::queries().contains_key("UPDATE sharded SET value = 1 WHERE id = $1"));
+
+        let info = RouteInfo::new(&cluster, "/* pgdog_shard: 1 */ SELECT 1", &[]).unwrap();
+        assert_eq!(info.route.shard(), &Shard::Direct(1));
+        assert_eq!(
+            info.key,
+            Some(("pgdog_shard: 1".into(), KeySource::Comment))
+        );
+
+        let info = RouteInfo::new(&cluster, "SET pgdog.shard TO 1", &[]).unwrap();
+        assert_eq!(info.key, Some(("pgdog.shard = 1".into(), KeySource::Set)));
+
+        let info = RouteInfo::new(&cluster, "SELECT * FROM sharded ORDER BY id", &[]).unwrap();
+        assert!(info.route.shard().all());
+        assert_eq!(info.route.order_by().len(), 1);
+        assert!(info.key.is_none());
+    }
+}
@@ -0,0 +1,71 @@
+//! Sharding keys stored inside JSON columns.
+
+use serde_json::Value;
+
+use crate::{config::ShardedTable, frontend::router::parser::Shard};
+
+use super::{ContextBuilder, Error};
+
+/// `jsonb` binary format version.
+const JSONB_VERSION: u8 = 1;
+
+/// Extract the sharding key at `path` from a JSON or `jsonb` value.
+///
+/// Returns `None` if the value isn't valid JSON or the key isn't
+/// a string, a number or a boolean.
+pub fn extract(data: &[u8], path: &[&str]) -> Option<String> {
+    // jsonb in binary format is the text representation prefixed with its version.
+    let data = match data.split_first() {
+        Some((&JSONB_VERSION, rest)) => rest,
+        _ => data,
+    };
+
+    let mut value = serde_json::from_slice::<Value>(data).ok()?;
+    for key in path {
+        value = match value {
+            Value::Object(mut object) => object.remove(*key)?,
+            _ => return None,
+        };
+    }
+
+    match value {
+        Value::String(string) => Some(string),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(boolean) => Some(boolean.to_string()),
+        _ => None,
+    }
+}
+
+/// Shard a JSON value using the key at the table's JSON path.
+///
+/// Rows without the key go to all shards.
+pub fn shard(table: &ShardedTable, data: &[u8], shards: usize) -> Result<Shard, Error> {
+    let Some(key) = extract(data, &table.json_path()) else {
+        return Ok(Shard::All);
+    };
+
+    ContextBuilder::new(table)
+        .data(key.as_str())
+        .shards(shards)
+        .build()?
+        .apply()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_extract() {
+        let json = br#"{"tenant_id": 5, "org": {"id": "acme"}, "tags": [1]}"#;
+        assert_eq!(extract(json, &["tenant_id"]), Some("5".into()));
+        assert_eq!(extract(json, &["org", "id"]), Some("acme".into()));
+        assert_eq!(extract(json, &["tags"]), None);
+        assert_eq!(extract(json, &["missing"]), None);
+        assert_eq!(extract(b"not json", &["tenant_id"]), None);
+
+        let mut jsonb = vec![JSONB_VERSION];
+        jsonb.extend_from_slice(json);
+        assert_eq!(extract(&jsonb, &["tenant_id"]), Some("5".into()));
+    }
+}
@@ -11,6 +11,7 @@ pub mod context;
 pub mod context_builder;
 pub mod error;
 pub mod ffi;
+pub mod json;
 pub mod operator;
 pub mod tables;
 #[cfg(test)]
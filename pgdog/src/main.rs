@@ -2,6 +2,7 @@
 
 use clap::Parser;
 use pgdog::backend::databases;
+use pgdog::backend::pool::failover::Failover;
 use pgdog::cli::{self, Commands};
 use pgdog::config;
 use pgdog::frontend::listener::Listener;
@@ -12,6 +13,7 @@ use tokio::runtime::Builder;
 use tracing::info;
 
 use std::process::exit;
+use std::time::Duration;
 
 #[cfg(not(target_env = "msvc"))]
 use tikv_jemallocator::Jemalloc;
@@ -35,6 +37,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         Some(Commands::Schema) => (),
 
+        Some(Commands::Route {
+            database,
+            user,
+            path,
+        }) => {
+            let config = config::load(&args.config, &args.users)?;
+            let ok = pgdog::cli::route(&config, &database, user.as_deref(), path)?;
+            exit(if ok { 0 } else { 1 });
+        }
+
         Some(Commands::Run {
             pool_size,
             min_pool_size,
@@ -98,6 +110,13 @@ async fn pgdog() -> Result<(), Box<dyn std::error::Error>> {
         net::discovery::Listener::get().run(broadcast_addr, general.broadcast_port);
     }
 
+    if general.failover {
+        Failover::launch(
+            Duration::from_millis(general.failover_threshold),
+            general.failover_nodes,
+        );
+    }
+
     if let Some(openmetrics_port) = general.openmetrics_port {
         tokio::spawn(async move { stats::http_server::server(openmetrics_port).await });
     }
@@ -5,7 +5,7 @@ use parking_lot::Mutex;
 use rand::Rng;
 use tracing::{debug, error, info};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::net::{Ipv4Addr, SocketAddr};
 use std::sync::Arc;
 use std::time::SystemTime;
@@ -23,8 +23,13 @@ pub struct Listener {
     inner: Arc<Mutex<Inner>>,
 }
 
+/// Peers and votes older than this are ignored.
+static EXPIRY: Duration = Duration::from_secs(10);
+
 #[derive(Debug, Clone)]
 pub struct State {
+    /// Peer node identifier.
+    pub node_id: u64,
     /// Number of connected clients.
     pub clients: u64,
     /// When we received the last state update.
@@ -34,6 +39,10 @@ pub struct State {
 #[derive(Debug)]
 struct Inner {
     peers: HashMap<SocketAddr, State>,
+    /// Primaries this node thinks are down.
+    down: HashSet<String>,
+    /// Primaries other nodes think are down, and when they told us.
+    votes: HashMap<String, HashMap<u64, SystemTime>>,
 }
 
 static LISTENER: Lazy<Listener> = Lazy::new(Listener::new);
@@ -45,6 +54,8 @@ impl Listener {
             id: rand::thread_rng().gen(),
             inner: Arc::new(Mutex::new(Inner {
                 peers: HashMap::new(),
+                down: HashSet::new(),
+                votes: HashMap::new(),
             })),
         }
     }
@@ -59,6 +70,59 @@ impl Listener {
         self.inner.lock().peers.clone()
     }
 
+    /// Tell other nodes if we think the primary is down.
+    pub fn primary_down(&self, addr: &str, down: bool) {
+        let mut guard = self.inner.lock();
+        if down {
+            guard.down.insert(addr.to_owned());
+        } else {
+            guard.down.remove(addr);
+            guard.votes.remove(addr);
+        }
+    }
+
+    /// A majority of the `nodes` pgdog nodes, including this one, think the primary is down.
+    ///
+    /// The number of nodes is configured, so nodes cut off from the others
+    /// can't form a majority of their own.
+    pub fn quorum(&self, addr: &str, nodes: usize) -> bool {
+        majority(self.voters(addr).len(), nodes)
+    }
+
+    /// This node has the lowest ID of the nodes that think the primary is down,
+    /// so it's the one promoting a replica.
+    pub fn leader(&self, addr: &str) -> bool {
+        self.voters(addr).into_iter().min() == Some(self.id)
+    }
+
+    /// Nodes that recently voted the primary is down, including this one.
+    fn voters(&self, addr: &str) -> HashSet<u64> {
+        let now = SystemTime::now();
+        let guard = self.inner.lock();
+
+        let mut voters = guard
+            .votes
+            .get(addr)
+            .map(|votes| {
+                votes
+                    .iter()
+                    .filter(|(_, time)| {
+                        now.duration_since(**time)
+                            .map(|age| age < EXPIRY)
+                            .unwrap_or(true)
+                    })
+                    .map(|(node_id, _)| *node_id)
+                    .collect::<HashSet<_>>()
+            })
+            .unwrap_or_default();
+
+        if guard.down.contains(addr) {
+            voters.insert(self.id);
+        }
+
+        voters
+    }
+
     /// Run the listener.
     pub fn run(&self, address: Ipv4Addr, port: u16) {
         let listener = self.clone();
@@ -89,13 +153,25 @@ impl Listener {
                     if let Some(message) = message {
                         debug!("{}: {:#?}", addr, message);
 
-                        if let Payload::Stats {
-                                clients
-                            } = message.payload {
-                            self.inner.lock().peers.insert(addr, State {
-                                clients,
-                                last_message: now,
-                            });
+                        match message.payload {
+                            Payload::Stats { clients } => {
+                                self.inner.lock().peers.insert(addr, State {
+                                    node_id: message.node_id,
+                                    clients,
+                                    last_message: now,
+                                });
+                            }
+
+                            Payload::PrimaryDown { addr } if message.node_id != self.id => {
+                                self.inner
+                                    .lock()
+                                    .votes
+                                    .entry(addr)
+                                    .or_default()
+                                    .insert(message.node_id, now);
+                            }
+
+                            _ => (),
                         }
 
                     }
@@ -105,8 +181,59 @@ impl Listener {
                     let healthcheck = Message::stats(self.id).to_bytes()?;
                     socket.send_to(&healthcheck, format!("{}:{}", address, port)).await?;
                     debug!("healtcheck");
+
+                    let down = self.inner.lock().down.iter().cloned().collect::<Vec<_>>();
+                    for addr in down {
+                        let vote = Message::primary_down(self.id, &addr).to_bytes()?;
+                        socket.send_to(&vote, format!("{}:{}", address, port)).await?;
+                    }
                 }
             }
         }
     }
 }
+
+/// More than half of the nodes voted.
+fn majority(votes: usize, nodes: usize) -> bool {
+    votes * 2 > nodes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quorum() {
+        assert!(majority(1, 1));
+        assert!(!majority(1, 2));
+        assert!(majority(2, 3));
+        assert!(!majority(2, 4));
+
+        // This node doesn't think the primary is down.
+        let listener = Listener::new();
+        assert!(!listener.quorum("127.0.0.1:5432", 1));
+
+        // Single node decides alone.
+        listener.primary_down("127.0.0.1:5432", true);
+        assert!(listener.quorum("127.0.0.1:5432", 1));
+        assert!(listener.leader("127.0.0.1:5432"));
+
+        // Another node didn't vote.
+        assert!(!listener.quorum("127.0.0.1:5432", 2));
+
+        // Votes from a node with a lower ID make it the leader.
+        listener
+            .inner
+            .lock()
+            .votes
+            .entry("127.0.0.1:5432".into())
+            .or_default()
+            .insert(listener.id.wrapping_sub(1), SystemTime::now());
+        assert!(listener.quorum("127.0.0.1:5432", 2));
+        assert!(!listener.quorum("127.0.0.1:5432", 4));
+        assert_eq!(listener.leader("127.0.0.1:5432"), listener.id == 0);
+
+        listener.primary_down("127.0.0.1:5432", false);
+        assert!(!listener.quorum("127.0.0.1:5432", 1));
+    }
+}
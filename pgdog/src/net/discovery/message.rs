@@ -7,7 +7,13 @@ use crate::frontend::comms::comms;
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Payload {
     Healthcheck,
-    Stats { clients: u64 },
+    Stats {
+        clients: u64,
+    },
+    /// This node thinks the primary is down.
+    PrimaryDown {
+        addr: String,
+    },
 }
 
 /// Message sent via UDP.
@@ -38,6 +44,16 @@ impl Message {
         }
     }
 
+    /// Vote for failing over the primary.
+    pub fn primary_down(node_id: u64, addr: &str) -> Self {
+        Self {
+            node_id,
+            payload: Payload::PrimaryDown {
+                addr: addr.to_owned(),
+            },
+        }
+    }
+
     /// Collect statistics.
     pub fn stats(node_id: u64) -> Self {
         let clients = comms().len() as u64;
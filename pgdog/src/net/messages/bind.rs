@@ -92,6 +92,11 @@ impl<'a> ParameterWithFormat<'a> {
         self.format
     }
 
+    /// Parameter is `NULL`.
+    pub fn is_null(&self) -> bool {
+        self.parameter.len < 0
+    }
+
     pub fn data(&'a self) -> &'a [u8] {
         &self.parameter.data
     }
@@ -178,10 +183,26 @@ impl Bind {
         unsafe { from_utf8_unchecked(&self.statement[0..self.statement.len() - 1]) }
     }
 
+    /// Name of the portal created by this Bind message.
+    #[inline]
+    pub(crate) fn portal(&self) -> &str {
+        // SAFETY: We check that this is valid UTF-8 in FromBytes::from_bytes below.
+        unsafe { from_utf8_unchecked(&self.portal[0..self.portal.len() - 1]) }
+    }
+
     /// Format codes, if any.
     pub fn codes(&self) -> &[Format] {
         &self.codes
     }
+
+    /// Bind text parameters to a prepared statement.
+    pub fn new_params(name: &str, params: &[Parameter]) -> Self {
+        Self {
+            statement: Bytes::from(name.to_string() + "\0"),
+            params: params.to_vec(),
+            ..Default::default()
+        }
+    }
 }
 
 #[cfg(test)]
@@ -65,6 +65,12 @@ pub trait ToDataRowColumn {
     fn to_data_row_column(&self) -> Data;
 }
 
+impl ToDataRowColumn for Data {
+    fn to_data_row_column(&self) -> Data {
+        self.clone()
+    }
+}
+
 impl ToDataRowColumn for Bytes {
     fn to_data_row_column(&self) -> Data {
         self.clone().into()
@@ -89,6 +95,15 @@ impl ToDataRowColumn for &str {
     }
 }
 
+impl<T: ToDataRowColumn> ToDataRowColumn for Option<T> {
+    fn to_data_row_column(&self) -> Data {
+        match self {
+            Some(value) => value.to_data_row_column(),
+            None => Data::null(),
+        }
+    }
+}
+
 impl ToDataRowColumn for i64 {
     fn to_data_row_column(&self) -> Data {
         Bytes::copy_from_slice(self.to_string().as_bytes()).into()
@@ -168,6 +183,18 @@ impl DataRow {
         self.columns.get(index).cloned().map(|d| d.data)
     }
 
+    /// Get column data at index, preserving NULLs.
+    #[inline]
+    pub fn data(&self, index: usize) -> Option<&Data> {
+        self.columns.get(index)
+    }
+
+    /// Column at index is NULL or missing.
+    #[inline]
+    pub fn is_null(&self, index: usize) -> bool {
+        self.columns.get(index).map(|d| d.is_null).unwrap_or(true)
+    }
+
     /// Get integer at index with text/binary encoding.
     pub fn get_int(&self, index: usize, text: bool) -> Option<i64> {
         self.get::<i64>(index, if text { Format::Text } else { Format::Binary })
@@ -99,6 +99,17 @@ impl ErrorResponse {
         }
     }
 
+    pub fn in_failed_transaction() -> Self {
+        Self {
+            severity: "ERROR".into(),
+            code: "25P02".into(),
+            message:
+                "current transaction is aborted, commands ignored until end of transaction block"
+                    .into(),
+            ..Default::default()
+        }
+    }
+
     pub fn no_transaction() -> Self {
         Self {
             severity: "WARNING".into(),
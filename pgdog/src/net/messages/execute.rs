@@ -38,9 +38,14 @@ impl Execute {
     }
 
     pub fn new_portal(name: &str) -> Self {
+        Self::new_portal_max_rows(name, 0)
+    }
+
+    /// Execute portal, returning at most `max_rows` rows.
+    pub fn new_portal_max_rows(name: &str, max_rows: i32) -> Self {
         let mut payload = Payload::named('E');
         payload.put_string(name);
-        payload.put_i32(0);
+        payload.put_i32(max_rows);
         Self {
             payload: payload.freeze(),
             portal_len: name.len() + 1,
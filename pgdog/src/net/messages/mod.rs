@@ -22,6 +22,7 @@ pub mod parameter_status;
 pub mod parse;
 pub mod parse_complete;
 pub mod payload;
+pub mod portal_suspended;
 pub mod prelude;
 pub mod query;
 pub mod replication;
@@ -53,6 +54,7 @@ pub use parameter_status::ParameterStatus;
 pub use parse::Parse;
 pub use parse_complete::ParseComplete;
 pub use payload::Payload;
+pub use portal_suspended::PortalSuspended;
 pub use query::Query;
 pub use rfq::ReadyForQuery;
 pub use row_description::{Field, RowDescription};
@@ -138,6 +140,7 @@ impl std::fmt::Debug for Message {
             'd' => CopyData::from_bytes(self.payload()).unwrap().fmt(f),
             'W' => f.debug_struct("CopyBothResponse").finish(),
             'I' => f.debug_struct("EmptyQueryResponse").finish(),
+            's' => f.debug_struct("PortalSuspended").finish(),
             't' => ParameterDescription::from_bytes(self.payload())
                 .unwrap()
                 .fmt(f),
@@ -0,0 +1,24 @@
+use super::code;
+use super::prelude::*;
+
+#[derive(Debug, Clone)]
+pub struct PortalSuspended;
+
+impl Protocol for PortalSuspended {
+    fn code(&self) -> char {
+        's'
+    }
+}
+
+impl FromBytes for PortalSuspended {
+    fn from_bytes(mut bytes: Bytes) -> Result<Self, Error> {
+        code!(bytes, 's');
+        Ok(Self)
+    }
+}
+
+impl ToBytes for PortalSuspended {
+    fn to_bytes(&self) -> Result<Bytes, Error> {
+        Ok(Payload::named('s').freeze())
+    }
+}
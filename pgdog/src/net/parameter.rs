@@ -16,6 +16,8 @@ static IMMUTABLE_PARAMS: Lazy<Vec<String>> = Lazy::new(|| {
         String::from("database"),
         String::from("user"),
         String::from("client_encoding"),
+        // Handled by pgdog, not sent to servers.
+        String::from("pgdog.role"),
     ])
 });
 
@@ -49,6 +49,7 @@ impl Pools {
         let mut maxwait = vec![];
         let mut errors = vec![];
         let mut out_of_sync = vec![];
+        let mut replica_lag = vec![];
         let mut total_xact_count = vec![];
         let mut avg_xact_count = vec![];
         let mut total_query_count = vec![];
@@ -104,6 +105,13 @@ impl Pools {
                         measurement: state.out_of_sync.into(),
                     });
 
+                    if let Some(lag) = state.replica_lag {
+                        replica_lag.push(Measurement {
+                            labels: labels.clone(),
+                            measurement: lag.as_millis().into(),
+                        });
+                    }
+
                     let stats = state.stats;
                     let totals = stats.counts;
                     let averages = stats.averages;
@@ -219,6 +227,14 @@ impl Pools {
             metric_type: Some("counter".into()),
         }));
 
+        metrics.push(Metric::new(PoolMetric {
+            name: "replica_lag".into(),
+            measurements: replica_lag,
+            help: "How far behind the primary the replica is.".into(),
+            unit: Some("milliseconds".into()),
+            metric_type: None,
+        }));
+
         metrics.push(Metric::new(PoolMetric {
             name: "total_xact_count".into(),
             measurements: total_xact_count,